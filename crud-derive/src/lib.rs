@@ -66,7 +66,7 @@ pub fn derive_submitted(input: TokenStream) -> TokenStream {
                 } else {
                     parse_quote!(#ident)
                 });
-                let field = Field {
+                Field {
                     attrs,
                     ty: if is_super {
                         parse_quote!(<#ty as Postable>::Post)
@@ -74,8 +74,7 @@ pub fn derive_submitted(input: TokenStream) -> TokenStream {
                         ty
                     },
                     ..f
-                };
-                field
+                }
             });
             quote! {
                 const _: () = {
@@ -1,12 +1,28 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20230601_000001_create_session_table;
+mod m20230602_000001_add_account_verification;
+mod m20230603_000001_add_session_owner;
+mod m20230604_000001_add_account_lock;
+mod m20230605_000001_create_grant_table;
+mod m20230606_000001_create_api_token_table;
+mod m20230607_000001_seed_reviewer_grants;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20230601_000001_create_session_table::Migration),
+            Box::new(m20230602_000001_add_account_verification::Migration),
+            Box::new(m20230603_000001_add_session_owner::Migration),
+            Box::new(m20230604_000001_add_account_lock::Migration),
+            Box::new(m20230605_000001_create_grant_table::Migration),
+            Box::new(m20230606_000001_create_api_token_table::Migration),
+            Box::new(m20230607_000001_seed_reviewer_grants::Migration),
+        ]
     }
 }
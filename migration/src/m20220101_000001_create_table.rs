@@ -13,8 +13,17 @@ impl MigrationTrait for Migration {
                     .if_not_exists()
                     .col(ColumnDef::new(Account::Email).string().primary_key())
                     .col(ColumnDef::new(Account::Salt).uuid().not_null())
-                    .col(ColumnDef::new(Account::PasswordHash).binary().binary_len(24).not_null())
-                    .col(ColumnDef::new(Account::IsAdministrator).boolean().not_null())
+                    .col(
+                        ColumnDef::new(Account::PasswordHash)
+                            .binary()
+                            .binary_len(24)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Account::IsAdministrator)
+                            .boolean()
+                            .not_null(),
+                    )
                     .col(ColumnDef::new(Account::IsEditor).boolean().not_null())
                     .col(
                         ColumnDef::new(Account::CreatedAt)
@@ -0,0 +1,158 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const ADMINISTRATOR: &str = "administrator";
+
+const EDITOR: &str = "editor";
+
+const GLOBAL: &str = "global";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountGrant::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountGrant::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AccountGrant::Email).string().not_null())
+                    .col(ColumnDef::new(AccountGrant::Role).string().not_null())
+                    .col(ColumnDef::new(AccountGrant::Scope).string().not_null())
+                    .col(
+                        ColumnDef::new(AccountGrant::CreatedAt)
+                            .timestamp()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-account_grant-email")
+                            .from(AccountGrant::Table, AccountGrant::Email)
+                            .to(Account::Table, Account::Email)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-account_grant-email-role-scope")
+                    .table(AccountGrant::Table)
+                    .col(AccountGrant::Email)
+                    .col(AccountGrant::Role)
+                    .col(AccountGrant::Scope)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        for (role, column) in [
+            (ADMINISTRATOR, Account::IsAdministrator),
+            (EDITOR, Account::IsEditor),
+        ] {
+            manager
+                .exec_stmt(
+                    Query::insert()
+                        .into_table(AccountGrant::Table)
+                        .columns([AccountGrant::Email, AccountGrant::Role, AccountGrant::Scope])
+                        .select_from(
+                            Query::select()
+                                .column(Account::Email)
+                                .expr(Expr::val(role))
+                                .expr(Expr::val(GLOBAL))
+                                .from(Account::Table)
+                                .and_where(Expr::col(column).eq(true))
+                                .to_owned(),
+                        )
+                        .map_err(|e| DbErr::Migration(e.to_string()))?
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Account::Table)
+                    .drop_column(Account::IsAdministrator)
+                    .drop_column(Account::IsEditor)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Account::Table)
+                    .add_column(
+                        ColumnDef::new(Account::IsAdministrator)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .add_column(
+                        ColumnDef::new(Account::IsEditor)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        for (role, column) in [
+            (ADMINISTRATOR, Account::IsAdministrator),
+            (EDITOR, Account::IsEditor),
+        ] {
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(Account::Table)
+                        .value(column, true)
+                        .and_where(
+                            Expr::col(Account::Email).in_subquery(
+                                Query::select()
+                                    .column(AccountGrant::Email)
+                                    .from(AccountGrant::Table)
+                                    .and_where(Expr::col(AccountGrant::Role).eq(role))
+                                    .and_where(Expr::col(AccountGrant::Scope).eq(GLOBAL))
+                                    .to_owned(),
+                            ),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .drop_table(Table::drop().table(AccountGrant::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Account {
+    Table,
+    Email,
+    IsAdministrator,
+    IsEditor,
+}
+
+#[derive(Iden)]
+enum AccountGrant {
+    Table,
+    Id,
+    Email,
+    Role,
+    Scope,
+    CreatedAt,
+}
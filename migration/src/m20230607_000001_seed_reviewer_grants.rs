@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const REVIEWER: &str = "reviewer";
+
+const GLOBAL: &str = "global";
+
+/// Grants every account there already is a global reviewer role, since anybody could be
+/// assigned to review before there were grants.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(AccountGrant::Table)
+                    .columns([AccountGrant::Email, AccountGrant::Role, AccountGrant::Scope])
+                    .select_from(
+                        Query::select()
+                            .column(Account::Email)
+                            .expr(Expr::val(REVIEWER))
+                            .expr(Expr::val(GLOBAL))
+                            .from(Account::Table)
+                            .and_where(
+                                Expr::col(Account::Email).not_in_subquery(
+                                    Query::select()
+                                        .column(AccountGrant::Email)
+                                        .from(AccountGrant::Table)
+                                        .and_where(Expr::col(AccountGrant::Role).eq(REVIEWER))
+                                        .and_where(Expr::col(AccountGrant::Scope).eq(GLOBAL))
+                                        .to_owned(),
+                                ),
+                            )
+                            .to_owned(),
+                    )
+                    .map_err(|e| DbErr::Migration(e.to_string()))?
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // The seeded grants cannot be told from those given since, so all are kept.
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Account {
+    Table,
+    Email,
+}
+
+#[derive(Iden)]
+enum AccountGrant {
+    Table,
+    Email,
+    Role,
+    Scope,
+}
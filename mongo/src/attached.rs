@@ -1,16 +1,13 @@
 use async_trait::async_trait;
 use crud::Viewable;
 use crud_derive::Viewable;
-use mongodm::{
-    doc, field,
-    mongo::{error, Database},
-    CollectionConfig, Index, Indexes,
-};
+use mongodm::{doc, field, mongo::error, CollectionConfig, Index, Indexes};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use super::{
     entity::{update::Update, BlankData, Data, Entity, EntityView},
     oid::{ObjectId, ObjectIdDef},
+    transaction::Transaction,
 };
 
 #[async_trait]
@@ -20,7 +17,8 @@ pub trait AttachedContent:
     fn collection_name() -> &'static str;
     fn schema_name() -> &'static str;
     fn indexes() -> Indexes;
-    async fn windup(db: Database, entity: &Entity<Attached<Self>>) -> error::Result<()>;
+    /// Cleans up what refers to the entity, which is deleted right after in the same transaction.
+    async fn windup(tx: &mut Transaction, entity: &Entity<Attached<Self>>) -> error::Result<()>;
 }
 
 #[async_trait]
@@ -37,7 +35,8 @@ impl AttachedContent for () {
         unreachable!()
     }
 
-    async fn windup(_: Database, _: &Entity<Attached<Self>>) -> error::Result<()> {
+    #[allow(clippy::diverging_sub_expression)]
+    async fn windup(_: &mut Transaction, _: &Entity<Attached<Self>>) -> error::Result<()> {
         unreachable!()
     }
 }
@@ -64,7 +63,7 @@ impl<C: AttachedContent> CollectionConfig for Attached<C> {
 
     fn indexes() -> Indexes {
         C::indexes().with(
-            Index::new(field!(creator_id in Attached<()>))
+            Index::new(field!((data in Entity<Attached<()>>).(creator_id in Attached<()>)))
                 .with_key(field!(created_at in Entity<BlankData>)),
         )
     }
@@ -81,13 +80,13 @@ impl<C: AttachedContent> Viewable for Entity<Attached<C>> {
 }
 
 impl<C: AttachedContent> Entity<Attached<C>> {
-    pub async fn remove_creator_of_attached(
-        db: Database,
+    pub async fn remove_creator_of_attached_in(
+        tx: &mut Transaction,
         creator_id: ObjectId,
     ) -> error::Result<(u64, u64)> {
         let path = field!((data in Entity<Attached<()>>).(creator_id in Attached<()>));
-        Self::update_many(
-            db,
+        Self::update_many_in(
+            tx,
             doc! {path: Some(creator_id)},
             Update {
                 set: doc! {path: None::<ObjectId>},
@@ -1,11 +1,16 @@
+pub mod page;
+pub mod trash;
 pub mod update;
 
 use crud::View;
-pub use mongodm::{doc, field, CollectionConfig, Index, IndexOption, Indexes};
+pub use mongodm::{
+    doc, field, operator, sync_indexes, CollectionConfig, Index, IndexOption, Indexes,
+};
 use mongodm::{
     mongo::{
         bson::{self, Document},
-        error, Cursor, Database,
+        error::{self, ErrorKind, WriteFailure},
+        Cursor, Database,
     },
     prelude::MongoFindOptions,
     Model, ToRepository,
@@ -30,6 +35,9 @@ pub struct Entity<D: Data> {
     pub data: D,
     pub created_at: bson::DateTime,
     pub updated_at: bson::DateTime,
+    /// When the entity was moved to the trash. Finders skip such entities.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<bson::DateTime>,
 }
 
 impl<D: Data> Model for Entity<D> {
@@ -37,12 +45,13 @@ impl<D: Data> Model for Entity<D> {
 }
 
 impl<D: Data> Entity<D> {
-    fn new(data: D) -> Self {
+    pub(crate) fn new(data: D) -> Self {
         Self {
             _id: ObjectId::new(),
             data,
             created_at: bson::DateTime::now(),
             updated_at: bson::DateTime::now(),
+            deleted_at: None,
         }
     }
 
@@ -52,6 +61,14 @@ impl<D: Data> Entity<D> {
             .await
             .map(|r| r.inserted_id.as_object_id())
     }
+
+    /// Puts back an entity deleted before, keeping its id and times.
+    pub async fn reinsert(db: Database, entity: &Self) -> error::Result<()> {
+        db.repository::<Self>()
+            .insert_one(entity, None)
+            .await
+            .map(|_| ())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -112,13 +129,28 @@ where
     type Object = Entity<DV::Object>;
 }
 
+/// Whether `e` is a violation of a unique index, as when two writers race for the same key.
+pub fn is_duplicate_key(e: &error::Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    match e.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY,
+        ErrorKind::Command(e) => e.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+/// Restricts `filter` to entities not in the trash.
+pub(crate) fn alive(filter: Document) -> Document {
+    doc! {operator::And: [filter, {field!(deleted_at in Entity<BlankData>): null}]}
+}
+
 impl<D: Data> Entity<D> {
     pub async fn try_find_one(db: Database, filter: Document) -> error::Result<Option<Self>> {
-        db.repository::<Self>().find_one(filter, None).await
+        db.repository::<Self>().find_one(alive(filter), None).await
     }
 
     pub async fn find(db: Database, filter: Document) -> error::Result<Cursor<Self>> {
-        db.repository::<Self>().find(filter, None).await
+        db.repository::<Self>().find(alive(filter), None).await
     }
 
     pub async fn find_peak(
@@ -127,7 +159,10 @@ impl<D: Data> Entity<D> {
         sort: Document,
     ) -> error::Result<Cursor<Self>> {
         db.repository::<Self>()
-            .find(filter, Some(MongoFindOptions::builder().sort(sort).build()))
+            .find(
+                alive(filter),
+                Some(MongoFindOptions::builder().sort(sort).build()),
+            )
             .await
     }
 
@@ -135,12 +170,17 @@ impl<D: Data> Entity<D> {
         Self::try_find_one(db, doc! {field!(_id in Entity<BlankData>): id}).await
     }
 
+    /// The distinct values at `path` over all entities, in the trash or not. Arrays are unwound.
+    pub async fn distinct(db: Database, path: &str) -> error::Result<Vec<bson::Bson>> {
+        db.repository::<Self>().distinct(path, None, None).await
+    }
+
     pub async fn include(
         db: Database,
         ids: impl IntoIterator<Item = &ObjectId>,
     ) -> error::Result<bool> {
         for &id in ids {
-            if let None = Self::try_find_one_by_id(db.clone(), id).await? {
+            if Self::try_find_one_by_id(db.clone(), id).await?.is_none() {
                 return Ok(false);
             }
         }
@@ -0,0 +1,105 @@
+use futures_util::TryStreamExt;
+use mongodm::{
+    mongo::{bson::Document, error, options::FindOptions, Database},
+    ToRepository,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{alive, Data, Entity};
+
+const MAX_PER_PAGE: u64 = 100;
+
+fn default_per_page() -> u64 {
+    20
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+#[serde(default)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct Paging {
+    #[schemars(title = "Page", description = "Counting from 0.")]
+    pub page: u64,
+    #[schemars(
+        title = "Items per Page",
+        description = "At most 100.",
+        default = "default_per_page"
+    )]
+    pub per_page: u64,
+}
+
+impl Default for Paging {
+    fn default() -> Self {
+        Self {
+            page: 0,
+            per_page: default_per_page(),
+        }
+    }
+}
+
+impl Paging {
+    pub fn limit(&self) -> u64 {
+        self.per_page.clamp(1, MAX_PER_PAGE)
+    }
+
+    pub fn skip(&self) -> u64 {
+        self.page.saturating_mul(self.limit())
+    }
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub per_page: u64,
+    #[schemars(title = "Total", description = "Count of all matched items.")]
+    pub total: u64,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            page: self.page,
+            per_page: self.per_page,
+            total: self.total,
+        }
+    }
+}
+
+impl<D: Data> Entity<D> {
+    pub async fn find_page(
+        db: Database,
+        filter: Document,
+        sort: Document,
+        paging: Paging,
+    ) -> error::Result<Page<Self>> {
+        let repository = db.repository::<Self>();
+        let filter = alive(filter);
+        let total = repository.count_documents(filter.clone(), None).await?;
+        let items = repository
+            .find(
+                filter,
+                FindOptions::builder()
+                    .sort(sort)
+                    .skip(paging.skip())
+                    .limit(paging.limit() as i64)
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        Ok(Page {
+            items,
+            page: paging.page,
+            per_page: paging.limit(),
+            total,
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use mongodm::{
+    doc, field,
+    mongo::{
+        bson::{self, Bson, Document},
+        error,
+        options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+        Cursor, Database,
+    },
+    operator::*,
+    prelude::ObjectId,
+    ToRepository,
+};
+
+use super::{update::Update, BlankData, Data, Entity};
+
+/// Restricts `filter` to entities in the trash.
+fn trashed(filter: Document) -> Document {
+    doc! {And: [filter, {field!(deleted_at in Entity<BlankData>): {NotEqual: null}}]}
+}
+
+impl<D: Data> Entity<D> {
+    /// Moves the entity to the trash, where finders cannot see it any more.
+    pub async fn trash_by_id(db: Database, id: ObjectId) -> error::Result<Option<Self>> {
+        Self::try_find_one_and_update_by_id(
+            db,
+            id,
+            Update {
+                set: doc! {field!(deleted_at in Entity<BlankData>): bson::DateTime::now()},
+                ..Update::default()
+            },
+        )
+        .await
+    }
+
+    /// Takes the entity out of the trash.
+    pub async fn restore_by_id(db: Database, id: ObjectId) -> error::Result<Option<Self>> {
+        db.repository::<Self>()
+            .find_one_and_update(
+                trashed(doc! {field!(_id in Entity<BlankData>): id}),
+                Update {
+                    set: doc! {field!(deleted_at in Entity<BlankData>): Bson::Null},
+                    ..Update::default()
+                }
+                .into_update_document(),
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+    }
+
+    pub async fn try_find_one_trashed_by_id(
+        db: Database,
+        id: ObjectId,
+    ) -> error::Result<Option<Self>> {
+        db.repository::<Self>()
+            .find_one(trashed(doc! {field!(_id in Entity<BlankData>): id}), None)
+            .await
+    }
+
+    /// Entities in the trash matching `filter`, the latest deleted first.
+    pub async fn find_trashed(db: Database, filter: Document) -> error::Result<Cursor<Self>> {
+        db.repository::<Self>()
+            .find(
+                trashed(filter),
+                FindOptions::builder()
+                    .sort(doc! {field!(deleted_at in Entity<BlankData>): -1})
+                    .build(),
+            )
+            .await
+    }
+
+    /// Entities moved to the trash before `before`.
+    pub async fn find_trashed_before(
+        db: Database,
+        before: bson::DateTime,
+    ) -> error::Result<Cursor<Self>> {
+        db.repository::<Self>()
+            .find(
+                doc! {field!(deleted_at in Entity<BlankData>): {LesserThan: before}},
+                None,
+            )
+            .await
+    }
+}
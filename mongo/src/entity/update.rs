@@ -12,7 +12,7 @@ use mongodm::{
     ToRepository,
 };
 
-use super::{BlankData, Data, Entity};
+use super::{alive, BlankData, Data, Entity};
 
 #[derive(Default)]
 #[derive(Debug)]
@@ -30,15 +30,23 @@ pub struct Update {
 
 impl Update {
     pub fn into_update_document(self) -> Document {
-        doc! {
-            Set: self.set,
-            AddToSet: self.add_to_set,
-            Pull: self.pull,
-            Push: self.push,
-            Pop: self.pop,
-            Inc: self.inc,
-            "$updatedDate": { field!(updated_at in Entity<BlankData>): true }
-        }
+        let mut update: Document = [
+            (String::from(Set), self.set),
+            (String::from(AddToSet), self.add_to_set),
+            (String::from(Pull), self.pull),
+            (String::from(Push), self.push),
+            (String::from(Pop), self.pop),
+            (String::from(Inc), self.inc),
+        ]
+        .into_iter()
+        .filter(|(_, document)| !document.is_empty())
+        .map(|(operator, document)| (operator, Bson::Document(document)))
+        .collect();
+        update.insert(
+            CurrentDate,
+            doc! { field!(updated_at in Entity<BlankData>): true },
+        );
+        update
     }
 }
 
@@ -50,7 +58,7 @@ impl<D: Data> Entity<D> {
     ) -> error::Result<Option<Self>> {
         db.repository::<Self>()
             .find_one_and_update(
-                filter,
+                alive(filter),
                 update.into_update_document(),
                 FindOneAndUpdateOptions::builder()
                     .return_document(ReturnDocument::After)
@@ -1,30 +1,109 @@
-use futures_util::{io::AsyncWriteExt, StreamExt};
+use futures_util::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    Stream, StreamExt, TryStreamExt,
+};
 use mongodm::{
     bson, doc,
     mongo::{
-        error, gridfs::FilesCollectionDocument, options::GridFsUploadOptions, GridFsBucket,
-        GridFsDownloadStream,
+        bson::{Binary, Document},
+        error,
+        options::{FindOptions, GridFsUploadOptions},
+        Cursor, Database, GridFsBucket, GridFsDownloadStream,
     },
     prelude::ObjectId,
 };
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
-pub async fn upload(
-    bucket: &GridFsBucket,
+pub use mongodm::mongo::gridfs::FilesCollectionDocument;
+
+/// Where the default bucket keeps its file documents.
+const FILES_COLLECTION: &str = "fs.files";
+
+/// Where the default bucket keeps the content of its files.
+const CHUNKS_COLLECTION: &str = "fs.chunks";
+
+/// What [`upload`] stored.
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Uploaded {
+    pub id: ObjectId,
+    pub length: u64,
+    /// In lowercase hex, also kept in the metadata of the file as `sha256`.
+    pub sha256: String,
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    Io(std::io::Error),
+    Mongo(error::Error),
+    /// The content is longer than the limit given. Nothing is kept.
+    TooLarge,
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<error::Error> for UploadError {
+    fn from(e: error::Error) -> Self {
+        Self::Mongo(e)
+    }
+}
+
+/// Stores `content` chunk by chunk, hashing it on the way, as long as it is at most `limit`
+/// bytes long.
+///
+/// `metadata` is kept with the file, along with the hash.
+pub async fn upload<B: AsRef<[u8]>>(
+    db: &Database,
     filename: impl AsRef<str>,
-    size: impl Into<Option<u32>>,
-    content_type: impl Into<Option<String>>,
-    content: &[u8],
-) -> std::io::Result<Option<ObjectId>> {
+    metadata: Document,
+    limit: u64,
+    mut content: impl Stream<Item = std::io::Result<B>> + Unpin,
+) -> Result<Uploaded, UploadError> {
+    let bucket = db.gridfs_bucket(None);
     let mut stream = bucket.open_upload_stream(
         filename,
-        GridFsUploadOptions::builder()
-            .chunk_size_bytes(size)
-            .metadata(doc! {"Content-Type": content_type.into()})
-            .build(),
+        GridFsUploadOptions::builder().metadata(metadata).build(),
     );
-    stream.write_all(content).await?;
+    let mut hasher = Sha256::new();
+    let mut length = 0u64;
+    while let Some(chunk) = content.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                stream.abort().await?;
+                return Err(e.into());
+            }
+        };
+        let chunk = chunk.as_ref();
+        length += chunk.len() as u64;
+        if length > limit {
+            stream.abort().await?;
+            return Err(UploadError::TooLarge);
+        }
+        hasher.update(chunk);
+        stream.write_all(chunk).await?;
+    }
     stream.close().await?;
-    Ok(stream.id().as_object_id())
+    let id = stream
+        .id()
+        .as_object_id()
+        .ok_or(UploadError::Io(std::io::Error::other(
+            "the file id is not an object id",
+        )))?;
+    let sha256 = format!("{:x}", hasher.finalize());
+    // The hash is only known after the file document is written with the metadata, so a file
+    // it cannot be added to is not kept. Should deleting fail as well, the file is left to be
+    // swept, as nothing refers to it.
+    if let Err(e) = set_metadata(db, id, "sha256", &sha256).await {
+        bucket.delete(bson!(id)).await.ok();
+        return Err(e.into());
+    }
+    Ok(Uploaded { id, length, sha256 })
 }
 
 pub async fn download(
@@ -41,3 +120,106 @@ pub async fn download(
         bucket.open_download_stream(bson!(id)).await?,
     ))
 }
+
+/// The first `limit` bytes of the file, and whether that is all of it.
+pub async fn read_at_most(
+    bucket: &GridFsBucket,
+    id: ObjectId,
+    limit: usize,
+) -> error::Result<(Vec<u8>, bool)> {
+    let mut content = Vec::new();
+    bucket
+        .open_download_stream(bson!(id))
+        .await?
+        .take(limit as u64 + 1)
+        .read_to_end(&mut content)
+        .await?;
+    let whole = content.len() <= limit;
+    content.truncate(limit);
+    Ok((content, whole))
+}
+
+/// Sets `key` in the metadata of the file.
+pub async fn set_metadata(
+    db: &Database,
+    id: ObjectId,
+    key: &str,
+    value: impl Into<bson::Bson>,
+) -> error::Result<()> {
+    db.collection::<Document>(FILES_COLLECTION)
+        .update_one(
+            doc! {"_id": id},
+            doc! {"$set": {format!("metadata.{}", key): value.into()}},
+            None,
+        )
+        .await
+        .map(|_| ())
+}
+
+/// Deletes the file, if it is still there.
+pub async fn delete(bucket: &GridFsBucket, id: ObjectId) -> error::Result<()> {
+    if bucket
+        .find(doc! {"_id": id}, None)
+        .await?
+        .next()
+        .await
+        .is_none()
+    {
+        return Ok(());
+    }
+    bucket.delete(bson!(id)).await
+}
+
+/// Files uploaded before `before`.
+pub async fn find_uploaded_before(
+    bucket: &GridFsBucket,
+    before: bson::DateTime,
+) -> error::Result<Cursor<FilesCollectionDocument>> {
+    bucket
+        .find(doc! {"uploadDate": {"$lt": before}}, None)
+        .await
+}
+
+pub async fn find_file(
+    bucket: &GridFsBucket,
+    id: ObjectId,
+) -> error::Result<Option<FilesCollectionDocument>> {
+    bucket
+        .find(doc! {"_id": id}, None)
+        .await?
+        .next()
+        .await
+        .transpose()
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    n: i64,
+    data: Binary,
+}
+
+/// Bytes `start..=end` of `file`, read from only the chunks holding them.
+pub async fn download_range(
+    db: &Database,
+    file: &FilesCollectionDocument,
+    start: u64,
+    end: u64,
+) -> error::Result<impl Stream<Item = error::Result<Vec<u8>>>> {
+    let chunk_size = u64::from(file.chunk_size_bytes.max(1));
+    let chunks = db
+        .collection::<Chunk>(CHUNKS_COLLECTION)
+        .find(
+            doc! {
+                "files_id": &file.id,
+                "n": {"$gte": (start / chunk_size) as i64, "$lte": (end / chunk_size) as i64},
+            },
+            FindOptions::builder().sort(doc! {"n": 1}).build(),
+        )
+        .await?;
+    Ok(chunks.map_ok(move |chunk| {
+        let offset = chunk.n as u64 * chunk_size;
+        let from = start.saturating_sub(offset) as usize;
+        let to = (end + 1 - offset).min(chunk.data.bytes.len() as u64) as usize;
+        chunk.data.bytes.get(from..to).unwrap_or_default().to_vec()
+    }))
+}
@@ -3,9 +3,10 @@ pub mod entity;
 pub mod gridfs;
 pub mod oid;
 pub mod owned;
+pub mod transaction;
 
 pub use mongodm::{
     bson,
     mongo::error::{Error as MongoError, Result as MongoResult},
-    prelude::{MongoClient, MongoDatabase},
+    prelude::{MongoClient, MongoCollection, MongoDatabase, MongoReplaceOptions},
 };
@@ -3,14 +3,19 @@ use crud::{BlankPatch, BlankSubmitted, Patch, Post, Viewable};
 use crud_derive::Viewable;
 use mongodm::{
     doc, field,
-    mongo::{error, Database},
-    CollectionConfig, Index, IndexOption, Indexes,
+    mongo::{bson::Document, error, Client, Database},
+    operator::Set,
+    CollectionConfig, Index, IndexOption, Indexes, ToRepository,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use super::{
-    entity::{update::SettableData, BlankData, Data, Entity, EntityView},
+    entity::{
+        update::{SettableData, Update},
+        BlankData, Data, Entity, EntityView,
+    },
     oid::{ObjectId, ObjectIdDef},
+    transaction::Transaction,
 };
 
 #[async_trait]
@@ -23,7 +28,24 @@ pub trait OwnedContent:
     fn schema_name() -> &'static str;
     fn new(submitted: Self::Post) -> Self;
     fn settable_path() -> &'static str;
-    async fn windup(db: Database, entity: &Entity<Owned<Self>>) -> error::Result<()>;
+    fn indexes() -> Indexes {
+        Indexes::new()
+    }
+    /// Fields computed from the settable ones, relative to the content.
+    ///
+    /// They are written again after every patch.
+    fn derived(&self) -> error::Result<Document> {
+        Ok(Document::new())
+    }
+    /// Cleans up what refers to the entity, which is deleted right after in the same transaction.
+    async fn windup(tx: &mut Transaction, entity: &Entity<Owned<Self>>) -> error::Result<()>;
+    /// GridFS files of the entity, deleted along with it when it is purged from the trash.
+    async fn file_ids(
+        _db: Database,
+        _entity: &Entity<Owned<Self>>,
+    ) -> error::Result<Vec<ObjectId>> {
+        Ok(Vec::new())
+    }
 }
 
 #[async_trait]
@@ -48,7 +70,8 @@ impl OwnedContent for () {
         unreachable!()
     }
 
-    async fn windup(_: Database, _: &Entity<Owned<Self>>) -> error::Result<()> {
+    #[allow(clippy::diverging_sub_expression)]
+    async fn windup(_: &mut Transaction, _: &Entity<Owned<Self>>) -> error::Result<()> {
         unreachable!()
     }
 }
@@ -61,7 +84,11 @@ impl OwnedContent for () {
 #[derive(Debug)]
 pub struct Owned<C: OwnedContent> {
     #[viewable(serialize_with = "oid::serialize_object_id_as_hex_string")]
-    #[schemars(title = "Owner ID", with = "ObjectIdDef")]
+    #[schemars(
+        title = "Owner ID",
+        description = "All zeros if kept from the viewer.",
+        with = "ObjectIdDef"
+    )]
     pub owner_id: ObjectId,
     #[viewable]
     pub is_public: bool,
@@ -76,12 +103,14 @@ impl<C: OwnedContent> CollectionConfig for Owned<C> {
     }
 
     fn indexes() -> Indexes {
-        Indexes::new().with(
-            Index::new(field!(owner_id in Owned<()>))
-                .with_key(field!(is_public in Owned<()>))
-                .with_key(field!(created_at in Entity<BlankData>))
-                .with_option(IndexOption::Unique),
-        )
+        C::indexes()
+            .with(
+                Index::new(field!((data in Entity<Owned<()>>).(owner_id in Owned<()>)))
+                    .with_key(field!((data in Entity<Owned<()>>).(is_public in Owned<()>)))
+                    .with_key(field!(created_at in Entity<BlankData>))
+                    .with_option(IndexOption::Unique),
+            )
+            .with(Index::new(field!(deleted_at in Entity<BlankData>)))
     }
 }
 
@@ -91,6 +120,23 @@ impl<C: OwnedContent> Data for Owned<C> {
     }
 }
 
+/// Puts `fields`, relative to the content, under the content of an owned entity.
+fn content_fields(fields: Document) -> Document {
+    fields
+        .into_iter()
+        .map(|(k, v)| {
+            (
+                format!(
+                    "{}.{}",
+                    field!((data in Entity<Owned<()>>).(content in Owned<()>)),
+                    k
+                ),
+                v,
+            )
+        })
+        .collect()
+}
+
 impl<C: OwnedContent> SettableData for Owned<C> {
     fn settable_path() -> &'static str {
         C::settable_path()
@@ -128,7 +174,7 @@ impl<C: OwnedContent> Entity<Owned<C>> {
         Self::try_find_one_and_update_by_id(
             db,
             id,
-            crate::entity::update::Update {
+            Update {
                 set: doc! {field!((data in Entity<Owned<()>>).(is_public in Owned<()>)): is_public},
                 ..Default::default()
             },
@@ -136,18 +182,71 @@ impl<C: OwnedContent> Entity<Owned<C>> {
         .await
     }
 
-    pub async fn delete_owneds(self, db: Database) -> error::Result<u64> {
-        C::windup(db.clone(), &self).await?;
-        Self::delete_by_id(db, self._id).await
+    pub async fn set_owned_by_id(
+        db: Database,
+        id: ObjectId,
+        patch: C::P,
+    ) -> error::Result<Option<Self>> {
+        let entity = match Self::set_by_id(db.clone(), id, patch).await? {
+            Some(entity) => entity,
+            None => return Ok(None),
+        };
+        let derived = entity.data.content.derived()?;
+        if derived.is_empty() {
+            return Ok(Some(entity));
+        }
+        Self::try_find_one_and_update_by_id(
+            db,
+            id,
+            Update {
+                set: content_fields(derived),
+                ..Update::default()
+            },
+        )
+        .await
+    }
+
+    /// Writes again the derived fields of every entity matching `filter`, in the trash or not,
+    /// for fields derived after the entities were stored. `updated_at` is left alone.
+    pub async fn rederive(db: Database, filter: Document) -> error::Result<u64> {
+        let repository = db.repository::<Self>();
+        let mut found = repository.find(filter, None).await?;
+        let mut rederived = 0;
+        while found.advance().await? {
+            let entity = found.deserialize_current()?;
+            let derived = entity.data.content.derived()?;
+            if derived.is_empty() {
+                continue;
+            }
+            repository
+                .update_one(
+                    doc! {field!(_id in Entity<BlankData>): entity._id},
+                    doc! {Set: content_fields(derived)},
+                    None,
+                )
+                .await?;
+            rederived += 1;
+        }
+        Ok(rederived)
+    }
+
+    pub async fn delete_owneds(self, client: &Client, db: Database) -> error::Result<u64> {
+        Transaction::run(client, db, |tx| Box::pin(self.clone().delete_owneds_in(tx))).await
     }
 
-    pub async fn delete_owneds_of_owner(db: Database, owner_id: ObjectId) -> error::Result<u64> {
+    pub async fn delete_owneds_in(self, tx: &mut Transaction) -> error::Result<u64> {
+        C::windup(tx, &self).await?;
+        Self::delete_by_id_in(tx, self._id).await
+    }
+
+    pub async fn delete_owneds_of_owner_in(
+        tx: &mut Transaction,
+        owner_id: ObjectId,
+    ) -> error::Result<u64> {
         let filter = doc! {field!((data in Entity<Owned<()>>).(owner_id in Owned<()>)): owner_id};
-        let mut owneds = Self::find(db.clone(), filter.clone()).await?;
-        while owneds.advance().await? {
-            let owned = owneds.deserialize_current()?;
-            C::windup(db.clone(), &owned).await?;
+        for owned in Self::find_with_trashed_in(tx, filter.clone()).await? {
+            C::windup(tx, &owned).await?;
         }
-        Self::delete(db, filter).await
+        Self::delete_in(tx, filter).await
     }
 }
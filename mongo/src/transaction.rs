@@ -0,0 +1,239 @@
+//! Multi-document transactions.
+//!
+//! Only replica sets and sharded clusters have transactions. On a standalone server a
+//! [`Transaction`] has no session, and the operations run in it are done one by one as usual.
+
+use std::time::Duration;
+
+use futures_util::{future::BoxFuture, TryStreamExt};
+use mongodm::{
+    doc, field,
+    mongo::{
+        bson::Document,
+        error::{self, TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT},
+        options::{FindOneAndUpdateOptions, ReturnDocument},
+        Client, ClientSession, Database,
+    },
+    ToRepository,
+};
+
+use super::{
+    entity::{alive, update::Update, BlankData, Data, Entity},
+    oid::ObjectId,
+};
+
+/// How many times a transaction, or its commit, is tried before its error is given up to.
+const ATTEMPTS: u32 = 5;
+
+/// How long to wait before the attempt after `attempt`, doubling each time.
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(50 << attempt)
+}
+
+pub struct Transaction {
+    db: Database,
+    session: Option<ClientSession>,
+}
+
+impl Transaction {
+    /// Starts a transaction on `db`, or nothing if the server cannot do transactions.
+    ///
+    /// `client` must be the one `db` was got from.
+    pub async fn start(client: &Client, db: Database) -> error::Result<Self> {
+        let hello = db.run_command(doc! {"hello": 1}, None).await?;
+        let session = if hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid") {
+            let mut session = client.start_session(None).await?;
+            session.start_transaction(None).await?;
+            Some(session)
+        } else {
+            None
+        };
+        Ok(Self { db, session })
+    }
+
+    /// Runs `body` in a transaction and commits it, all over again in a new transaction if it
+    /// fails for a transient error, as when another transaction writes the same documents.
+    pub async fn run<T, F>(client: &Client, db: Database, mut body: F) -> error::Result<T>
+    where
+        F: for<'t> FnMut(&'t mut Transaction) -> BoxFuture<'t, error::Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let mut tx = Self::start(client, db.clone()).await?;
+            let result = match body(&mut tx).await {
+                Ok(value) => tx.commit().await.map(|()| value),
+                Err(e) => Err(e),
+            };
+            match result {
+                Err(e)
+                    if e.contains_label(TRANSIENT_TRANSACTION_ERROR) && attempt + 1 < ATTEMPTS =>
+                {
+                    tokio::time::sleep(backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub fn db(&self) -> Database {
+        self.db.clone()
+    }
+
+    /// Makes the writes visible to others, trying again a few times while it is unknown whether
+    /// they are.
+    ///
+    /// A transaction dropped without being committed is aborted.
+    pub async fn commit(self) -> error::Result<()> {
+        let Some(mut session) = self.session else {
+            return Ok(());
+        };
+        let mut attempt = 0;
+        loop {
+            match session.commit_transaction().await {
+                Err(e)
+                    if e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT)
+                        && attempt + 1 < ATTEMPTS =>
+                {
+                    tokio::time::sleep(backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<D: Data> Entity<D> {
+    pub async fn insert_one_in(tx: &mut Transaction, data: D) -> error::Result<Option<ObjectId>> {
+        let repository = tx.db.repository::<Self>();
+        let entity = Self::new(data);
+        match &mut tx.session {
+            Some(session) => {
+                repository
+                    .insert_one_with_session(entity, None, session)
+                    .await
+            }
+            None => repository.insert_one(entity, None).await,
+        }
+        .map(|r| r.inserted_id.as_object_id())
+    }
+
+    pub async fn try_find_one_in(
+        tx: &mut Transaction,
+        filter: Document,
+    ) -> error::Result<Option<Self>> {
+        let repository = tx.db.repository::<Self>();
+        let filter = alive(filter);
+        match &mut tx.session {
+            Some(session) => {
+                repository
+                    .find_one_with_session(filter, None, session)
+                    .await
+            }
+            None => repository.find_one(filter, None).await,
+        }
+    }
+
+    /// Unlike [`Entity::find`], collects everything found, so that the transaction is free
+    /// again for the next operation.
+    pub async fn find_in(tx: &mut Transaction, filter: Document) -> error::Result<Vec<Self>> {
+        Self::find_with_trashed_in(tx, alive(filter)).await
+    }
+
+    /// Like [`Entity::find_in`], but also finds entities in the trash.
+    pub async fn find_with_trashed_in(
+        tx: &mut Transaction,
+        filter: Document,
+    ) -> error::Result<Vec<Self>> {
+        let repository = tx.db.repository::<Self>();
+        match &mut tx.session {
+            Some(session) => {
+                repository
+                    .find_with_session(filter, None, session)
+                    .await?
+                    .stream(session)
+                    .try_collect()
+                    .await
+            }
+            None => repository.find(filter, None).await?.try_collect().await,
+        }
+    }
+
+    pub async fn try_find_one_and_update_in(
+        tx: &mut Transaction,
+        filter: Document,
+        update: Update,
+    ) -> error::Result<Option<Self>> {
+        let repository = tx.db.repository::<Self>();
+        let update = update.into_update_document();
+        let filter = alive(filter);
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        match &mut tx.session {
+            Some(session) => {
+                repository
+                    .find_one_and_update_with_session(filter, update, options, session)
+                    .await
+            }
+            None => {
+                repository
+                    .find_one_and_update(filter, update, options)
+                    .await
+            }
+        }
+    }
+
+    pub async fn update_many_in(
+        tx: &mut Transaction,
+        query: Document,
+        update: Update,
+    ) -> error::Result<(u64, u64)> {
+        let repository = tx.db.repository::<Self>();
+        let update = update.into_update_document();
+        match &mut tx.session {
+            Some(session) => {
+                repository
+                    .update_many_with_session(query, update, None, session)
+                    .await
+            }
+            None => repository.update_many(query, update, None).await,
+        }
+        .map(|r| (r.matched_count, r.modified_count))
+    }
+
+    pub async fn pull_sets_in(
+        tx: &mut Transaction,
+        path: &'static str,
+        value: ObjectId,
+    ) -> error::Result<(u64, u64)> {
+        let query = doc! {path: value};
+        Self::update_many_in(
+            tx,
+            query.clone(),
+            Update {
+                pull: query,
+                ..Update::default()
+            },
+        )
+        .await
+    }
+
+    pub async fn delete_in(tx: &mut Transaction, query: Document) -> error::Result<u64> {
+        let repository = tx.db.repository::<Self>();
+        match &mut tx.session {
+            Some(session) => {
+                repository
+                    .delete_many_with_session(query, None, session)
+                    .await
+            }
+            None => repository.delete_many(query, None).await,
+        }
+        .map(|result| result.deleted_count)
+    }
+
+    pub async fn delete_by_id_in(tx: &mut Transaction, id: ObjectId) -> error::Result<u64> {
+        Self::delete_in(tx, doc! {field!(_id in Entity<BlankData>): id}).await
+    }
+}
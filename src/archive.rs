@@ -0,0 +1,774 @@
+//! Listing what is inside zip and tar archives, and where, so that a single file can be got out
+//! later without unpacking the rest.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::inflate::{self, InflateError};
+
+#[derive(Error)]
+#[derive(Debug)]
+pub(crate) enum ArchiveError {
+    #[error("the archive ends too early")]
+    Truncated,
+    #[error("the archive is corrupt: {0}")]
+    Corrupt(&'static str),
+    #[error("the file is stored in a way not understood here: {0}")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Inflate(#[from] InflateError),
+}
+
+type Result<T> = std::result::Result<T, ArchiveError>;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum ArchiveFormat {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar")]
+    Tar,
+    /// Unpacked from its start to get a single file out.
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+/// How a file is kept in a zip archive.
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct ZipStorage {
+    /// 0 for stored, 8 for deflated.
+    pub(crate) method: u16,
+    pub(crate) compressed_size: u64,
+    pub(crate) encrypted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct Entry {
+    pub(crate) path: String,
+    /// Unpacked.
+    pub(crate) size: u64,
+    /// Of the local header in a zip archive, or of the content in a tar archive, unpacked if
+    /// it is gzipped.
+    pub(crate) offset: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) zip: Option<ZipStorage>,
+}
+
+/// Strips what would make the path climb out or start at the root, or nothing if it names a
+/// directory.
+fn clean_path(path: &str) -> Option<String> {
+    let parts: Vec<_> = path
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+        .collect();
+    (!parts.is_empty() && !path.ends_with(['/', '\\'])).then(|| parts.join("/"))
+}
+
+/// The entry at `path`, written however [`clean_path`] would list it.
+pub(crate) fn find_entry<'a>(entries: &'a [Entry], path: &str) -> Option<&'a Entry> {
+    let path = clean_path(path)?;
+    entries.iter().find(|entry| entry.path == path)
+}
+
+fn le16(data: &[u8], at: usize) -> Result<u16> {
+    data.get(at..at + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(ArchiveError::Truncated)
+}
+
+fn le32(data: &[u8], at: usize) -> Result<u32> {
+    data.get(at..at + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+        .ok_or(ArchiveError::Truncated)
+}
+
+fn le64(data: &[u8], at: usize) -> Result<u64> {
+    data.get(at..at + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+        .ok_or(ArchiveError::Truncated)
+}
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_END: u32 = 0x0605_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+
+/// How many bytes at the end of a zip archive hold the end of its central directory, comment
+/// and zip64 records included.
+pub(crate) const ZIP_TAIL_LEN: u64 = 22 + 0xffff + 20 + 56;
+
+/// Where the central directory of a zip archive is.
+pub(crate) struct ZipDirectory {
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+}
+
+/// Finds the central directory from `tail`, the last bytes of the archive, which start at
+/// `tail_start` in it.
+pub(crate) fn zip_directory(tail: &[u8], tail_start: u64) -> Result<ZipDirectory> {
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| le32(tail, at).ok() == Some(ZIP_END))
+        .ok_or(ArchiveError::Corrupt("no end of central directory"))?;
+    let count = le16(tail, end + 10)?;
+    let size = le32(tail, end + 12)?;
+    let offset = le32(tail, end + 16)?;
+    if count != 0xffff && size != 0xffff_ffff && offset != 0xffff_ffff {
+        return Ok(ZipDirectory {
+            offset: offset.into(),
+            size: size.into(),
+        });
+    }
+    let locator = end
+        .checked_sub(20)
+        .filter(|&at| le32(tail, at).ok() == Some(ZIP64_LOCATOR))
+        .ok_or(ArchiveError::Corrupt(
+            "no zip64 end of central directory locator",
+        ))?;
+    let record = le64(tail, locator + 8)?
+        .checked_sub(tail_start)
+        .and_then(|at| usize::try_from(at).ok())
+        .filter(|&at| le32(tail, at).ok() == Some(ZIP64_END))
+        .ok_or(ArchiveError::Unsupported(
+            "zip64 end of central directory far from the end",
+        ))?;
+    Ok(ZipDirectory {
+        offset: le64(tail, record + 48)?,
+        size: le64(tail, record + 40)?,
+    })
+}
+
+/// The files listed in `directory`, the central directory of a zip archive.
+pub(crate) fn zip_entries(directory: &[u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while at < directory.len() {
+        if le32(directory, at)? != ZIP_CENTRAL_HEADER {
+            return Err(ArchiveError::Corrupt("bad central directory header"));
+        }
+        let flags = le16(directory, at + 8)?;
+        let method = le16(directory, at + 10)?;
+        let mut compressed_size = u64::from(le32(directory, at + 20)?);
+        let mut size = u64::from(le32(directory, at + 24)?);
+        let name_len = usize::from(le16(directory, at + 28)?);
+        let extra_len = usize::from(le16(directory, at + 30)?);
+        let comment_len = usize::from(le16(directory, at + 32)?);
+        let mut offset = u64::from(le32(directory, at + 42)?);
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or(ArchiveError::Truncated)?;
+        let extra = directory
+            .get(at + 46 + name_len..at + 46 + name_len + extra_len)
+            .ok_or(ArchiveError::Truncated)?;
+        // What does not fit in 32 bits is in the zip64 extra field, in this order.
+        let mut field = 0;
+        while field + 4 <= extra.len() {
+            let id = le16(extra, field)?;
+            let len = usize::from(le16(extra, field + 2)?);
+            if id == 1 {
+                let mut value = field + 4;
+                for wide in [&mut size, &mut compressed_size, &mut offset] {
+                    if *wide == 0xffff_ffff {
+                        *wide = le64(extra, value)?;
+                        value += 8;
+                    }
+                }
+            }
+            field += 4 + len;
+        }
+        if let Some(path) = clean_path(&String::from_utf8_lossy(name)) {
+            entries.push(Entry {
+                path,
+                size,
+                offset,
+                zip: Some(ZipStorage {
+                    method,
+                    compressed_size,
+                    encrypted: flags & 1 != 0,
+                }),
+            });
+        }
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// How many bytes of a zip archive to read at the offset of an entry to find its data.
+pub(crate) const ZIP_LOCAL_HEADER_LEN: u64 = 30;
+
+/// Where the data of an entry starts, from the fixed part of its local header.
+pub(crate) fn zip_data_offset(entry: &Entry, local_header: &[u8]) -> Result<u64> {
+    if le32(local_header, 0)? != ZIP_LOCAL_HEADER {
+        return Err(ArchiveError::Corrupt("bad local header"));
+    }
+    let name_len = u64::from(le16(local_header, 26)?);
+    let extra_len = u64::from(le16(local_header, 28)?);
+    Ok(entry.offset + ZIP_LOCAL_HEADER_LEN + name_len + extra_len)
+}
+
+/// Unpacks a zip entry from its `data` as stored.
+pub(crate) fn zip_unpack(entry: &Entry, data: Vec<u8>) -> Result<Vec<u8>> {
+    let storage = entry
+        .zip
+        .as_ref()
+        .ok_or(ArchiveError::Corrupt("no zip storage"))?;
+    if storage.encrypted {
+        return Err(ArchiveError::Unsupported("encrypted"));
+    }
+    let limit = usize::try_from(entry.size).map_err(|_| ArchiveError::Unsupported("too large"))?;
+    match storage.method {
+        0 => Ok(data),
+        8 => Ok(inflate::inflate(&data, limit)?.0),
+        _ => Err(ArchiveError::Unsupported("compression method")),
+    }
+}
+
+const TAR_BLOCK: usize = 512;
+
+/// Reads a number, in octal or, if the high bit is set, in base 256.
+fn tar_number(field: &[u8]) -> Result<u64> {
+    if field.first().is_some_and(|&byte| byte & 0x80 != 0) {
+        return Ok(field[1..]
+            .iter()
+            .fold(0, |value, &byte| value << 8 | u64::from(byte)));
+    }
+    let digits = std::str::from_utf8(field)
+        .map_err(|_| ArchiveError::Corrupt("bad number"))?
+        .trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| ArchiveError::Corrupt("bad number"))
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn tar_checksum_ok(header: &[u8]) -> bool {
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                u64::from(byte)
+            }
+        })
+        .sum();
+    tar_number(&header[148..156]).ok() == Some(sum)
+}
+
+/// Whether `data` starts like a tar archive.
+pub(crate) fn is_tar(data: &[u8]) -> bool {
+    data.len() >= TAR_BLOCK && tar_checksum_ok(&data[..TAR_BLOCK])
+}
+
+/// The value of `key` among pax extended header records, which read `<length> <key>=<value>\n`.
+fn pax_value(records: &[u8], key: &str) -> Option<String> {
+    let mut rest = records;
+    while let Some(space) = rest.iter().position(|&byte| byte == b' ') {
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..len)?;
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record
+            .strip_prefix(key.as_bytes())
+            .and_then(|value| value.strip_prefix(b"="))
+        {
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+        rest = rest.get(len..)?;
+    }
+    None
+}
+
+/// The most a GNU long name or a pax header may take.
+const TAR_META_LIMIT: u64 = 1 << 20;
+
+/// A GNU long name or a pax header being read, for the entry after it.
+struct TarMeta {
+    kind: u8,
+    content: Vec<u8>,
+    left: u64,
+    padding: u64,
+}
+
+/// Lists the regular files of a tar archive fed to it a piece at a time, keeping only the
+/// headers and what names the files, and skipping their content.
+#[derive(Default)]
+struct TarIndexer {
+    entries: Vec<Entry>,
+    /// How much of the archive has been fed.
+    at: u64,
+    header: Vec<u8>,
+    meta: Option<TarMeta>,
+    /// Of the content of the last entry, and then of the padding after it.
+    content_left: u64,
+    padding_left: u64,
+    /// Set by a GNU long name or a pax header for the entry after it.
+    next_path: Option<String>,
+    /// Whether the blocks of zeros which end the archive have been read.
+    ended: bool,
+}
+
+impl TarIndexer {
+    /// Whether what follows is of no interest.
+    fn ended(&self) -> bool {
+        self.ended
+    }
+
+    fn feed(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() && !self.ended {
+            let skip = if self.content_left > 0 {
+                &mut self.content_left
+            } else {
+                &mut self.padding_left
+            };
+            if *skip > 0 {
+                let len = data.len().min(usize::try_from(*skip).unwrap_or(usize::MAX));
+                *skip -= len as u64;
+                self.at += len as u64;
+                data = &data[len..];
+                continue;
+            }
+            if let Some(mut meta) = self.meta.take() {
+                let len = data
+                    .len()
+                    .min(usize::try_from(meta.left).unwrap_or(usize::MAX));
+                meta.content.extend_from_slice(&data[..len]);
+                meta.left -= len as u64;
+                self.at += len as u64;
+                data = &data[len..];
+                if meta.left == 0 {
+                    self.end_meta(meta);
+                } else {
+                    self.meta = Some(meta);
+                }
+                continue;
+            }
+            let len = data.len().min(TAR_BLOCK - self.header.len());
+            self.header.extend_from_slice(&data[..len]);
+            self.at += len as u64;
+            data = &data[len..];
+            if self.header.len() == TAR_BLOCK {
+                let header = std::mem::take(&mut self.header);
+                self.read_header(&header)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn end_meta(&mut self, meta: TarMeta) {
+        self.padding_left = meta.padding;
+        self.next_path = match meta.kind {
+            b'L' => Some(tar_string(&meta.content)),
+            _ => pax_value(&meta.content, "path"),
+        };
+    }
+
+    fn read_header(&mut self, header: &[u8]) -> Result<()> {
+        if header.iter().all(|&byte| byte == 0) {
+            self.ended = true;
+            return Ok(());
+        }
+        if !tar_checksum_ok(header) {
+            return Err(ArchiveError::Corrupt("bad tar header checksum"));
+        }
+        let size = tar_number(&header[124..136])?;
+        let padding = size.next_multiple_of(TAR_BLOCK as u64) - size;
+        match header[156] {
+            kind @ (b'L' | b'x') => {
+                if size > TAR_META_LIMIT {
+                    return Err(ArchiveError::Unsupported("overlong extended header"));
+                }
+                let meta = TarMeta {
+                    kind,
+                    content: Vec::new(),
+                    left: size,
+                    padding,
+                };
+                if size == 0 {
+                    self.end_meta(meta);
+                } else {
+                    self.meta = Some(meta);
+                }
+                return Ok(());
+            }
+            b'g' => {}
+            0 | b'0' | b'7' => {
+                let path = self.next_path.take().unwrap_or_else(|| {
+                    let name = tar_string(&header[..100]);
+                    let prefix = tar_string(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                if let Some(path) = clean_path(&path) {
+                    self.entries.push(Entry {
+                        path,
+                        size,
+                        offset: self.at,
+                        zip: None,
+                    });
+                }
+            }
+            _ => self.next_path = None,
+        }
+        self.content_left = size;
+        self.padding_left = padding;
+        Ok(())
+    }
+
+    /// The entries, unless the archive was cut short in the middle of one.
+    fn finish(self) -> Result<Vec<Entry>> {
+        if self.meta.is_some() || self.content_left > 0 {
+            return Err(ArchiveError::Truncated);
+        }
+        Ok(self.entries)
+    }
+}
+
+/// The regular files in the tar archive read a piece at a time from `pieces`, which are taken
+/// no further than its end.
+pub(crate) fn tar_entries(
+    pieces: impl IntoIterator<Item = impl AsRef<[u8]>>,
+) -> Result<Vec<Entry>> {
+    let mut indexer = TarIndexer::default();
+    for piece in pieces {
+        indexer.feed(piece.as_ref())?;
+        if indexer.ended() {
+            break;
+        }
+    }
+    indexer.finish()
+}
+
+/// The regular files in the gzipped tar archive read a byte at a time from `input`, or `None` if
+/// it is not a tar archive once unpacked. No more than `limit` bytes are unpacked, and only as
+/// far as the end of the archive.
+pub(crate) fn tar_gz_entries(
+    input: impl Iterator<Item = u8>,
+    limit: usize,
+) -> Result<Option<Vec<Entry>>> {
+    let mut head = Vec::new();
+    let mut indexer = TarIndexer::default();
+    let mut failed = None;
+    inflate::gunzip_stream(input, limit, |mut piece| {
+        if head.len() < TAR_BLOCK {
+            let len = piece.len().min(TAR_BLOCK - head.len());
+            head.extend_from_slice(&piece[..len]);
+            piece = &piece[len..];
+            if head.len() < TAR_BLOCK {
+                return true;
+            }
+            if !is_tar(&head) {
+                return false;
+            }
+            if let Err(e) = indexer.feed(&head) {
+                failed = Some(e);
+                return false;
+            }
+        }
+        if let Err(e) = indexer.feed(piece) {
+            failed = Some(e);
+            return false;
+        }
+        !indexer.ended()
+    })?;
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    if !is_tar(&head) {
+        return Ok(None);
+    }
+    indexer.finish().map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A zip archive of `files`, stored, with the central directory at the end.
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, data) in files {
+            let offset = archive.len() as u32;
+            let size = data.len() as u32;
+            archive.extend(ZIP_LOCAL_HEADER.to_le_bytes());
+            archive.extend([20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            archive.extend(size.to_le_bytes());
+            archive.extend(size.to_le_bytes());
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend([0, 0]);
+            archive.extend(name.as_bytes());
+            archive.extend(data);
+            directory.extend(ZIP_CENTRAL_HEADER.to_le_bytes());
+            directory.extend([20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            directory.extend(size.to_le_bytes());
+            directory.extend(size.to_le_bytes());
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = archive.len() as u32;
+        archive.extend(&directory);
+        archive.extend(ZIP_END.to_le_bytes());
+        archive.extend([0; 4]);
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend([0, 0]);
+        archive
+    }
+
+    fn zip_list(archive: &[u8]) -> Result<Vec<Entry>> {
+        let directory = zip_directory(archive, 0)?;
+        let start = directory.offset as usize;
+        zip_entries(&archive[start..start + directory.size as usize])
+    }
+
+    fn zip_read(archive: &[u8], entry: &Entry) -> Vec<u8> {
+        let header = &archive[entry.offset as usize..];
+        let start = zip_data_offset(entry, header).unwrap() as usize;
+        let compressed_size = entry.zip.as_ref().unwrap().compressed_size as usize;
+        zip_unpack(entry, archive[start..start + compressed_size].to_vec()).unwrap()
+    }
+
+    /// A tar header block for a file of `size` bytes, of type `kind`.
+    fn tar_header(name: &str, size: usize, kind: u8) -> Vec<u8> {
+        let mut header = vec![0; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        header
+    }
+
+    fn tar_file(archive: &mut Vec<u8>, name: &str, kind: u8, data: &[u8]) {
+        archive.extend(tar_header(name, data.len(), kind));
+        archive.extend(data);
+        archive.resize(archive.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+    }
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for &(name, data) in files {
+            tar_file(&mut archive, name, b'0', data);
+        }
+        archive.extend([0; 2 * TAR_BLOCK]);
+        archive
+    }
+
+    /// `data` in a gzip member of stored blocks.
+    fn gzip_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = b"\x1f\x8b\x08\0\0\0\0\0\0\xff".to_vec();
+        let blocks: Vec<_> = data.chunks(0xffff).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            out.push(u8::from(i + 1 == blocks.len()));
+            out.extend((block.len() as u16).to_le_bytes());
+            out.extend((!(block.len() as u16)).to_le_bytes());
+            out.extend(*block);
+        }
+        out.extend([0; 8]);
+        out
+    }
+
+    fn paths(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn zip_files_are_listed_and_read() {
+        let archive = zip(&[("main.tex", b"\\begin{document}"), ("fig/a.png", b"png")]);
+        let entries = zip_list(&archive).unwrap();
+        assert_eq!(paths(&entries), ["main.tex", "fig/a.png"]);
+        let entry = find_entry(&entries, "fig/a.png").unwrap();
+        assert_eq!(entry.size, 3);
+        assert_eq!(zip_read(&archive, entry), b"png");
+        assert_eq!(zip_read(&archive, &entries[0]), b"\\begin{document}");
+    }
+
+    #[test]
+    fn zip_paths_cannot_climb_out() {
+        let archive = zip(&[
+            ("../../etc/passwd", b"root"),
+            ("/abs/file", b"abs"),
+            ("a\\..\\b.tex", b"b"),
+            ("./c//d.tex", b"d"),
+            ("dir/", b""),
+        ]);
+        let entries = zip_list(&archive).unwrap();
+        assert_eq!(
+            paths(&entries),
+            ["etc/passwd", "abs/file", "a/b.tex", "c/d.tex"]
+        );
+        assert!(find_entry(&entries, "../../etc/passwd").is_some());
+        assert!(find_entry(&entries, "dir/").is_none());
+        assert!(find_entry(&entries, "..").is_none());
+    }
+
+    #[test]
+    fn zip_without_end_is_corrupt() {
+        let mut archive = zip(&[("a", b"a")]);
+        archive.truncate(archive.len() - 22);
+        assert!(matches!(zip_list(&archive), Err(ArchiveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn zip_central_directory_must_hold() {
+        let archive = zip(&[("main.tex", b"x")]);
+        let directory = zip_directory(&archive, 0).unwrap();
+        let start = directory.offset as usize;
+        let cut = &archive[start..start + directory.size as usize - 1];
+        assert!(matches!(zip_entries(cut), Err(ArchiveError::Truncated)));
+        assert!(matches!(
+            zip_entries(&archive[..8]),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn zip_encrypted_and_unknown_methods_are_refused() {
+        let mut entry = Entry {
+            path: "a".to_string(),
+            size: 1,
+            offset: 0,
+            zip: Some(ZipStorage {
+                method: 8,
+                compressed_size: 1,
+                encrypted: true,
+            }),
+        };
+        assert!(matches!(
+            zip_unpack(&entry, vec![0]),
+            Err(ArchiveError::Unsupported(_))
+        ));
+        entry.zip = Some(ZipStorage {
+            method: 12,
+            compressed_size: 1,
+            encrypted: false,
+        });
+        assert!(matches!(
+            zip_unpack(&entry, vec![0]),
+            Err(ArchiveError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn tar_files_are_listed_where_their_content_is() {
+        let archive = tar(&[("main.tex", b"tex"), ("refs.bib", &[b'@'; 600])]);
+        assert!(is_tar(&archive));
+        let entries = tar_entries([&archive]).unwrap();
+        assert_eq!(paths(&entries), ["main.tex", "refs.bib"]);
+        let entry = find_entry(&entries, "refs.bib").unwrap();
+        let start = entry.offset as usize;
+        assert_eq!(&archive[start..start + entry.size as usize], &[b'@'; 600]);
+    }
+
+    #[test]
+    fn tar_long_names_are_followed() {
+        let long = format!("{}/main.tex", "d".repeat(120));
+        let mut archive = Vec::new();
+        tar_file(&mut archive, "././@LongLink", b'L', long.as_bytes());
+        tar_file(&mut archive, "short", b'0', b"x");
+        let record = "path=pax/file.tex\n";
+        let record = format!("{} {}", record.len() + 3, record);
+        tar_file(&mut archive, "PaxHeader", b'x', record.as_bytes());
+        tar_file(&mut archive, "ignored", b'0', b"y");
+        tar_file(&mut archive, "dir", b'5', b"");
+        archive.extend([0; 2 * TAR_BLOCK]);
+        assert_eq!(
+            paths(&tar_entries([&archive]).unwrap()),
+            [long.as_str(), "pax/file.tex"]
+        );
+    }
+
+    #[test]
+    fn tar_can_be_fed_in_pieces() {
+        let long = format!("{}/main.tex", "d".repeat(120));
+        let mut archive = Vec::new();
+        tar_file(&mut archive, "././@LongLink", b'L', long.as_bytes());
+        tar_file(&mut archive, "main.tex", b'0', &[b'x'; 700]);
+        tar_file(&mut archive, "refs.bib", b'0', b"@");
+        archive.extend([0; 2 * TAR_BLOCK]);
+        // Whatever follows the end is not read.
+        archive.extend(b"trailing garbage");
+        let whole = tar_entries([&archive]).unwrap();
+        for piece in [1, 100, TAR_BLOCK, TAR_BLOCK + 1] {
+            let entries = tar_entries(archive.chunks(piece)).unwrap();
+            assert_eq!(paths(&entries), paths(&whole));
+            let offsets = |entries: &[Entry]| entries.iter().map(|e| e.offset).collect::<Vec<_>>();
+            assert_eq!(offsets(&entries), offsets(&whole));
+        }
+    }
+
+    #[test]
+    fn tar_gz_of_something_else_is_not_listed() {
+        let unpacked = b"just a gzipped file".repeat(100);
+        let entries = tar_gz_entries(gzip_stored(&unpacked).into_iter(), unpacked.len());
+        assert!(entries.unwrap().is_none());
+        let archive = tar(&[("main.tex", &[b'x'; 100])]);
+        assert!(matches!(
+            tar_gz_entries(gzip_stored(&archive).into_iter(), archive.len() - 1),
+            Err(ArchiveError::Inflate(InflateError::TooLarge(_)))
+        ));
+    }
+
+    #[test]
+    fn tar_gz_paths_cannot_climb_out() {
+        let archive = tar(&[("../../etc/passwd", b"root"), ("/abs/file", b"abs")]);
+        let entries = tar_gz_entries(gzip_stored(&archive).into_iter(), archive.len())
+            .unwrap()
+            .unwrap();
+        assert_eq!(paths(&entries), ["etc/passwd", "abs/file"]);
+        assert!(find_entry(&entries, "/etc/passwd").is_some());
+    }
+
+    #[test]
+    fn tar_must_hold() {
+        let mut archive = tar(&[("main.tex", &[b'x'; 100])]);
+        archive[0] = b'M';
+        assert!(matches!(
+            tar_entries([&archive]),
+            Err(ArchiveError::Corrupt(_))
+        ));
+        let archive = tar(&[("main.tex", &[b'x'; 100])]);
+        assert!(matches!(
+            tar_entries([&archive[..TAR_BLOCK + 50]]),
+            Err(ArchiveError::Truncated)
+        ));
+        assert!(!is_tar(b"not a tar"));
+    }
+
+    #[test]
+    fn tar_numbers_may_be_octal_or_base_256() {
+        assert_eq!(tar_number(b"00000001750\0").unwrap(), 1000);
+        assert_eq!(tar_number(b"   \0").unwrap(), 0);
+        assert_eq!(
+            tar_number(&[0x80, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]).unwrap(),
+            1 << 32
+        );
+        assert!(tar_number(b"0000009\0").is_err());
+    }
+}
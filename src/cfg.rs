@@ -30,6 +30,96 @@ fn default_relay() -> String {
     "localhost".to_string()
 }
 
+fn default_session_ttl() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_session_gc_interval() -> u64 {
+    60 * 60
+}
+
+fn default_verification_ttl() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_reset_ttl() -> u64 {
+    60 * 60
+}
+
+fn default_repair_interval() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_orphan_grace() -> u64 {
+    60 * 60
+}
+
+fn default_purge_interval() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_trash_retention() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+fn default_file_sweep_interval() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_file_grace() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_max_file_size() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_max_request_size() -> u64 {
+    512 * 1024 * 1024
+}
+
+fn default_max_unpacked_size() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_release_types() -> String {
+    "pdf".to_string()
+}
+
+fn default_source_types() -> String {
+    "text,zip,gzip,tar,bzip2,xz,7z,pdf,png,jpeg".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Where sessions are kept.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone, Default)]
+#[derive(Debug)]
+pub(crate) enum SessionStoreKind {
+    /// Lost on restart, so only for tests.
+    Memory,
+    #[default]
+    Mongo,
+    Sql,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone, Default)]
+#[derive(Debug)]
+pub(crate) enum SameSitePolicy {
+    #[default]
+    Strict,
+    Lax,
+    None,
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
@@ -51,6 +141,64 @@ pub struct AppConfig {
     pub(crate) relay: String,
     pub(crate) smtp_username: String,
     pub(crate) smtp_password: String,
+    /// At least 64 bytes, used to sign the session cookie and the tokens sent by email.
+    pub(crate) session_secret: String,
+    #[serde(default)]
+    pub(crate) session_store: SessionStoreKind,
+    /// In seconds.
+    #[serde(default = "default_session_ttl")]
+    pub(crate) session_ttl: u64,
+    /// In seconds, how often expired sessions are removed from the store.
+    #[serde(default = "default_session_gc_interval")]
+    pub(crate) session_gc_interval: u64,
+    #[serde(default = "default_true")]
+    pub(crate) session_secure: bool,
+    #[serde(default)]
+    pub(crate) session_same_site: SameSitePolicy,
+    /// In seconds, how long a new account can be verified by the mailed token.
+    #[serde(default = "default_verification_ttl")]
+    pub(crate) verification_ttl: u64,
+    /// In seconds, how long a forgotten password can be reset by the mailed token.
+    #[serde(default = "default_reset_ttl")]
+    pub(crate) reset_ttl: u64,
+    /// In seconds, how often accounts and profiles left without each other are looked for.
+    #[serde(default = "default_repair_interval")]
+    pub(crate) repair_interval: u64,
+    /// In seconds, how old such an account or profile must be before it is removed.
+    #[serde(default = "default_orphan_grace")]
+    pub(crate) orphan_grace: u64,
+    /// Whether a profile left without an account is removed, by itself and not what it owns,
+    /// rather than only reported.
+    #[serde(default)]
+    pub(crate) remove_orphan_profiles: bool,
+    /// In seconds, how often the trash is purged.
+    #[serde(default = "default_purge_interval")]
+    pub(crate) purge_interval: u64,
+    /// In seconds, how long deleted objects can be restored before they are purged.
+    #[serde(default = "default_trash_retention")]
+    pub(crate) trash_retention: u64,
+    /// In seconds, how often files nothing refers to are deleted.
+    #[serde(default = "default_file_sweep_interval")]
+    pub(crate) file_sweep_interval: u64,
+    /// In seconds, how old such a file must be before it is deleted.
+    #[serde(default = "default_file_grace")]
+    pub(crate) file_grace: u64,
+    /// In bytes, how large an uploaded file can be.
+    #[serde(default = "default_max_file_size")]
+    pub(crate) max_file_size: u64,
+    /// In bytes, how large all files uploaded at once can be.
+    #[serde(default = "default_max_request_size")]
+    pub(crate) max_request_size: u64,
+    /// In bytes, how large a gzipped source can be once unpacked, to be indexed or to get a
+    /// file out of it.
+    #[serde(default = "default_max_unpacked_size")]
+    pub(crate) max_unpacked_size: u64,
+    /// Comma-separated, what a release can be, as told by its first bytes.
+    #[serde(default = "default_release_types")]
+    pub(crate) release_types: String,
+    /// Comma-separated, what a source can be, as told by its first bytes.
+    #[serde(default = "default_source_types")]
+    pub(crate) source_types: String,
 }
 
 impl AppConfig {
@@ -0,0 +1,269 @@
+//! Line diffs in the unified format.
+
+/// How many edits are looked for before giving up and replacing every line instead, since
+/// finding them takes the square of their number in memory.
+const MAX_EDITS: isize = 2000;
+
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+enum Edit {
+    /// A line of the old text, which the new one has as well.
+    Keep(usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// The shortest edits turning `old` into `new`, by the algorithm of Myers.
+fn edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    // `trace[d][k + d]` is how far along diagonal `k` the `d`th round gets.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'rounds: for d in 0..=(n + m).min(MAX_EDITS) {
+        let mut furthest = vec![0; 2 * d as usize + 1];
+        for k in (-d..=d).step_by(2) {
+            let previous = |k: isize| trace[d as usize - 1][(k + d - 1) as usize];
+            let mut x = if d == 0 {
+                0
+            } else if k == -d || (k != d && previous(k - 1) < previous(k + 1)) {
+                previous(k + 1)
+            } else {
+                previous(k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[(k + d) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(furthest);
+                break 'rounds;
+            }
+        }
+        trace.push(furthest);
+        if d == (n + m).min(MAX_EDITS) {
+            let deleted = (0..old.len()).map(Edit::Delete);
+            return deleted.chain((0..new.len()).map(Edit::Insert)).collect();
+        }
+    }
+    let (mut x, mut y) = (n, m);
+    let mut edits = Vec::new();
+    for d in (1..trace.len() as isize).rev() {
+        let previous = |k: isize| trace[d as usize - 1][(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && previous(k - 1) < previous(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = previous(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep(x as usize));
+        }
+        if x == previous_x {
+            y -= 1;
+            edits.push(Edit::Insert(y as usize));
+        } else {
+            x -= 1;
+            edits.push(Edit::Delete(x as usize));
+        }
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        edits.push(Edit::Keep(x as usize));
+    }
+    edits.reverse();
+    edits
+}
+
+/// The changes from `old` to `new`, with `context` lines around them, or nothing if there are
+/// none.
+pub(crate) fn unified(name: &str, old: &str, new: &str, context: usize) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    let edits = edits(&old, &new);
+    let changes: Vec<_> = (0..edits.len())
+        .filter(|&i| !matches!(edits[i], Edit::Keep(_)))
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+    // Where in the old and the new text each edit is.
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut old_at, mut new_at) = (0, 0);
+    for edit in &edits {
+        positions.push((old_at, new_at));
+        match edit {
+            Edit::Keep(_) => {
+                old_at += 1;
+                new_at += 1;
+            }
+            Edit::Delete(_) => old_at += 1,
+            Edit::Insert(_) => new_at += 1,
+        }
+    }
+    let mut out = format!("--- a/{}\n+++ b/{}\n", name, name);
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(context);
+        let mut last = changes[i];
+        // Hunks whose context would touch are joined.
+        while i + 1 < changes.len() && changes[i + 1] - last <= 2 * context + 1 {
+            i += 1;
+            last = changes[i];
+        }
+        let end = (last + context + 1).min(edits.len());
+        let hunk = &edits[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete(_)))
+            .count();
+        let (old_start, new_start) = positions[start];
+        out += &format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_len > 0),
+            old_len,
+            new_start + usize::from(new_len > 0),
+            new_len
+        );
+        for edit in hunk {
+            let (mark, line) = match *edit {
+                Edit::Keep(x) => (' ', old[x]),
+                Edit::Delete(x) => ('-', old[x]),
+                Edit::Insert(y) => ('+', new[y]),
+            };
+            out.push(mark);
+            out += line;
+            out.push('\n');
+        }
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `edits` make of `old`, checking that they go through both texts in order.
+    fn apply<'a>(old: &[&'a str], new: &[&'a str], edits: &[Edit]) -> Vec<&'a str> {
+        let (mut x, mut y) = (0, 0);
+        let mut out = Vec::new();
+        for &edit in edits {
+            match edit {
+                Edit::Keep(at) => {
+                    assert_eq!(at, x);
+                    assert_eq!(old[x], new[y]);
+                    out.push(old[x]);
+                    x += 1;
+                    y += 1;
+                }
+                Edit::Delete(at) => {
+                    assert_eq!(at, x);
+                    x += 1;
+                }
+                Edit::Insert(at) => {
+                    assert_eq!(at, y);
+                    out.push(new[y]);
+                    y += 1;
+                }
+            }
+        }
+        assert_eq!((x, y), (old.len(), new.len()));
+        out
+    }
+
+    fn changes(edits: &[Edit]) -> usize {
+        edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Keep(_)))
+            .count()
+    }
+
+    #[test]
+    fn edits_are_the_shortest() {
+        let cases: [(&[&str], &[&str], usize); 5] = [
+            (
+                &["a", "b", "c", "a", "b", "b", "a"],
+                &["c", "b", "a", "b", "a", "c"],
+                5,
+            ),
+            (&[], &[], 0),
+            (&[], &["a", "b"], 2),
+            (&["a", "b"], &[], 2),
+            (&["a", "b", "c"], &["a", "x", "c"], 2),
+        ];
+        for (old, new, shortest) in cases {
+            let edits = edits(old, new);
+            assert_eq!(apply(old, new, &edits), new);
+            assert_eq!(changes(&edits), shortest, "{:?} to {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn too_many_edits_replace_every_line() {
+        let old: Vec<_> = (0..1001).map(|i| format!("old {}", i)).collect();
+        let new: Vec<_> = (0..1001).map(|i| format!("new {}", i)).collect();
+        let old: Vec<_> = old.iter().map(String::as_str).collect();
+        let new: Vec<_> = new.iter().map(String::as_str).collect();
+        let edits = edits(&old, &new);
+        assert_eq!(apply(&old, &new, &edits), new);
+        assert_eq!(
+            edits[..1001],
+            (0..1001).map(Edit::Delete).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn same_texts_have_no_diff() {
+        assert_eq!(unified("a.tex", "a\nb\n", "a\nb\n", 3), "");
+        assert_eq!(unified("a.tex", "", "", 3), "");
+    }
+
+    #[test]
+    fn changes_are_shown_with_context() {
+        assert_eq!(
+            unified(
+                "a.tex",
+                "1\n2\n3\n4\n5\n6\n7\n",
+                "1\n2\n3\nfour\n5\n6\n7\n",
+                1
+            ),
+            "--- a/a.tex\n+++ b/a.tex\n@@ -3,3 +3,3 @@\n 3\n-4\n+four\n 5\n"
+        );
+    }
+
+    #[test]
+    fn close_changes_share_a_hunk() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "x\n2\n3\n4\n5\n6\n7\n8\n9\ny\n";
+        assert_eq!(unified("a", old, new, 4).matches("@@ -").count(), 1);
+        assert_eq!(unified("a", old, new, 3).matches("@@ -").count(), 2);
+        let far = unified("a", old, new, 1);
+        assert_eq!(
+            far,
+            "--- a/a\n+++ b/a\n@@ -1,2 +1,2 @@\n-1\n+x\n 2\n@@ -9,2 +9,2 @@\n 9\n-10\n+y\n"
+        );
+    }
+
+    #[test]
+    fn files_added_or_removed_start_at_zero() {
+        assert_eq!(
+            unified("new.tex", "", "a\nb\n", 3),
+            "--- a/new.tex\n+++ b/new.tex\n@@ -0,0 +1,2 @@\n+a\n+b\n"
+        );
+        assert_eq!(
+            unified("old.tex", "a\n", "", 3),
+            "--- a/old.tex\n+++ b/old.tex\n@@ -1,1 +0,0 @@\n-a\n"
+        );
+    }
+}
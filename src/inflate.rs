@@ -0,0 +1,606 @@
+//! Decompressing DEFLATE data (RFC 1951), bare as in zip archives, or wrapped in zlib
+//! (RFC 1950) as in PDF streams, or in gzip (RFC 1952).
+
+use thiserror::Error;
+
+#[derive(Error)]
+#[derive(Debug)]
+pub(crate) enum InflateError {
+    #[error("the compressed data ends too early")]
+    Truncated,
+    #[error("the compressed data is corrupt: {0}")]
+    Corrupt(&'static str),
+    #[error("the data is larger than {0} bytes once decompressed")]
+    TooLarge(usize),
+}
+
+type Result<T> = std::result::Result<T, InflateError>;
+
+/// Reads bits from the least significant one, as DEFLATE wants.
+struct Bits<I> {
+    input: I,
+    /// How many bytes have been read.
+    pos: usize,
+    bit: u32,
+    bit_count: u32,
+}
+
+impl<I: Iterator<Item = u8>> Bits<I> {
+    fn new(input: I) -> Self {
+        Self {
+            input,
+            pos: 0,
+            bit: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// The next whole byte, if there is one.
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.input.next()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        self.next_byte().ok_or(InflateError::Truncated)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<()> {
+        for _ in 0..count {
+            self.byte()?;
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, count: u32) -> Result<u32> {
+        while self.bit_count < count {
+            let byte = self.byte()?;
+            self.bit |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit & ((1 << count) - 1);
+        self.bit >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Drops the bits left of the current byte.
+    fn align(&mut self) {
+        self.bit = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// Where decompressed bytes go.
+trait Output {
+    fn push(&mut self, byte: u8) -> Result<()>;
+
+    /// The byte `distance` back from the last one pushed, if it is still at hand.
+    fn back(&self, distance: usize) -> Option<u8>;
+}
+
+/// Keeps everything, up to `limit` bytes.
+struct Bounded<'a> {
+    out: &'a mut Vec<u8>,
+    limit: usize,
+}
+
+impl Output for Bounded<'_> {
+    fn push(&mut self, byte: u8) -> Result<()> {
+        if self.out.len() >= self.limit {
+            return Err(InflateError::TooLarge(self.limit));
+        }
+        self.out.push(byte);
+        Ok(())
+    }
+
+    fn back(&self, distance: usize) -> Option<u8> {
+        let at = self.out.len().checked_sub(distance)?;
+        self.out.get(at).copied()
+    }
+}
+
+/// How far back DEFLATE copies from at most.
+const WINDOW: usize = 32 * 1024;
+
+/// Hands the bytes on to `sink` a piece at a time, up to `limit` bytes, keeping only those which
+/// can still be copied.
+struct Window<F> {
+    kept: Vec<u8>,
+    /// How many of `kept` were handed on already.
+    handed: usize,
+    total: usize,
+    limit: usize,
+    sink: F,
+    /// Whether `sink` wants no more.
+    stopped: bool,
+}
+
+impl<F: FnMut(&[u8]) -> bool> Window<F> {
+    fn hand_on(&mut self) {
+        if !self.stopped && !(self.sink)(&self.kept[self.handed..]) {
+            self.stopped = true;
+        }
+        self.kept.drain(..self.kept.len().saturating_sub(WINDOW));
+        self.handed = self.kept.len();
+    }
+}
+
+impl<F: FnMut(&[u8]) -> bool> Output for Window<F> {
+    fn push(&mut self, byte: u8) -> Result<()> {
+        if self.total >= self.limit {
+            return Err(InflateError::TooLarge(self.limit));
+        }
+        self.kept.push(byte);
+        self.total += 1;
+        if self.kept.len() >= 4 * WINDOW {
+            self.hand_on();
+            if self.stopped {
+                // Only to break off, as [`gunzip_stream`] tells from `stopped`.
+                return Err(InflateError::TooLarge(self.total));
+            }
+        }
+        Ok(())
+    }
+
+    fn back(&self, distance: usize) -> Option<u8> {
+        let at = self.kept.len().checked_sub(distance)?;
+        self.kept.get(at).copied()
+    }
+}
+
+/// A canonical Huffman code, as counts of codes per length and symbols sorted by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err(InflateError::Corrupt("over-subscribed code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[usize::from(offsets[usize::from(length)])] = symbol as u16;
+                offsets[usize::from(length)] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits<impl Iterator<Item = u8>>) -> Result<u16> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for &count in &self.counts[1..] {
+            code |= bits.take(1)? as i32;
+            let count = i32::from(count);
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Corrupt("no such code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order in which the lengths of the code length code are sent.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn fixed_codes() -> Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(bits: &mut Bits<impl Iterator<Item = u8>>) -> Result<(Huffman, Huffman)> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(InflateError::Corrupt("too many codes"));
+    }
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = bits.take(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_code.decode(bits)?;
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or(InflateError::Corrupt("nothing to repeat"))?;
+                (previous, 3 + bits.take(2)? as usize)
+            }
+            17 => (0, 3 + bits.take(3)? as usize),
+            _ => (0, 11 + bits.take(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err(InflateError::Corrupt("too many lengths"));
+        }
+        lengths[index..index + repeat].fill(length);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(InflateError::Corrupt("no end of block"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn codes(
+    bits: &mut Bits<impl Iterator<Item = u8>>,
+    out: &mut impl Output,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<()> {
+    loop {
+        let symbol = usize::from(literals.decode(bits)?);
+        match symbol {
+            0..=255 => out.push(symbol as u8)?,
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(InflateError::Corrupt("bad length symbol"));
+                }
+                let length = usize::from(LENGTH_BASE[symbol])
+                    + bits.take(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+                let symbol = usize::from(distances.decode(bits)?);
+                if symbol >= DISTANCE_BASE.len() {
+                    return Err(InflateError::Corrupt("bad distance symbol"));
+                }
+                let distance = usize::from(DISTANCE_BASE[symbol])
+                    + bits.take(u32::from(DISTANCE_EXTRA[symbol]))? as usize;
+                for _ in 0..length {
+                    let byte = out
+                        .back(distance)
+                        .ok_or(InflateError::Corrupt("distance too far back"))?;
+                    out.push(byte)?;
+                }
+            }
+        }
+    }
+}
+
+/// Decompresses raw DEFLATE `data` into at most `limit` bytes, returning them with how many bytes
+/// of `data` were used.
+pub(crate) fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize)> {
+    let mut out = Vec::new();
+    let mut bits = Bits::new(data.iter().copied());
+    inflate_into(
+        &mut bits,
+        &mut Bounded {
+            out: &mut out,
+            limit,
+        },
+    )?;
+    Ok((out, bits.pos))
+}
+
+/// Decompresses raw DEFLATE data from `bits` into `out`, up to the end of its last block.
+/// Whatever fitted is left in `out` if there is more.
+fn inflate_into(bits: &mut Bits<impl Iterator<Item = u8>>, out: &mut impl Output) -> Result<()> {
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                bits.align();
+                let header = [bits.byte()?, bits.byte()?, bits.byte()?, bits.byte()?];
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(InflateError::Corrupt("bad stored block length"));
+                }
+                for _ in 0..length {
+                    out.push(bits.byte()?)?;
+                }
+            }
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                codes(bits, out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(bits)?;
+                codes(bits, out, &literals, &distances)?;
+            }
+            _ => return Err(InflateError::Corrupt("bad block type")),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+/// Decompresses zlib `data` into at most `limit` bytes, without checking the Adler-32 at the end.
+pub(crate) fn inflate_zlib(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let header = data.get(..2).ok_or(InflateError::Truncated)?;
+    if header[0] & 0x0f != 8 || (u16::from(header[0]) << 8 | u16::from(header[1])) % 31 != 0 {
+        return Err(InflateError::Corrupt("bad zlib header"));
+    }
+    if header[1] & 0x20 != 0 {
+        return Err(InflateError::Corrupt("preset dictionary"));
+    }
+    inflate(&data[2..], limit).map(|(out, _)| out)
+}
+
+/// Decompresses the first `len` bytes of gzip `data`, or all of it if it is shorter, going no
+/// further.
+pub(crate) fn gunzip_head(data: &[u8], len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let bounded = &mut Bounded {
+        out: &mut out,
+        limit: len,
+    };
+    match gunzip_into(data.iter().copied(), bounded) {
+        Ok(()) | Err(InflateError::TooLarge(_)) => Ok(out),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decompresses gzip data read a byte at a time from `input` into at most `limit` bytes, handing
+/// them on to `sink` a piece at a time and keeping no more than DEFLATE may copy from. It goes
+/// no further once `sink` returns false.
+pub(crate) fn gunzip_stream(
+    input: impl Iterator<Item = u8>,
+    limit: usize,
+    sink: impl FnMut(&[u8]) -> bool,
+) -> Result<()> {
+    let mut window = Window {
+        kept: Vec::new(),
+        handed: 0,
+        total: 0,
+        limit,
+        sink,
+        stopped: false,
+    };
+    match gunzip_into(input, &mut window) {
+        Ok(()) => {
+            window.hand_on();
+            Ok(())
+        }
+        Err(_) if window.stopped => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decompresses gzip data (RFC 1952), members after members, without checking the CRC-32 of
+/// each.
+fn gunzip_into(input: impl Iterator<Item = u8>, out: &mut impl Output) -> Result<()> {
+    let mut bits = Bits::new(input);
+    let mut first = true;
+    loop {
+        // Whatever follows the members is left alone, unless there is no member at all.
+        match [bits.next_byte(), bits.next_byte()] {
+            [Some(0x1f), Some(0x8b)] => {}
+            [None, _] => return Ok(()),
+            _ if first => return Err(InflateError::Corrupt("bad gzip header")),
+            _ => return Ok(()),
+        }
+        first = false;
+        if bits.byte()? != 8 {
+            return Err(InflateError::Corrupt("bad gzip compression method"));
+        }
+        let flags = bits.byte()?;
+        // The time, the extra flags and the operating system.
+        bits.skip(6)?;
+        if flags & 0x04 != 0 {
+            let len = u16::from_le_bytes([bits.byte()?, bits.byte()?]);
+            bits.skip(usize::from(len))?;
+        }
+        // The file name, then the comment, each ending with a NUL.
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                while bits.byte()? != 0 {}
+            }
+        }
+        if flags & 0x02 != 0 {
+            bits.skip(2)?;
+        }
+        inflate_into(&mut bits, out)?;
+        // The CRC-32 and the length follow.
+        bits.align();
+        bits.skip(8)?;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    /// Raw DEFLATE data of `n` zeros in a block of fixed codes, a byte and then copies of 258.
+    pub(crate) fn deflate_zeros(n: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let (mut bit, mut count) = (0u32, 0u32);
+        let mut put = |value: u32, length: u32, out: &mut Vec<u8>| {
+            bit |= value << count;
+            count += length;
+            while count >= 8 {
+                out.push(bit as u8);
+                bit >>= 8;
+                count -= 8;
+            }
+        };
+        // Huffman codes go in from their most significant bit.
+        let code = |code: u32, length: u32| code.reverse_bits() >> (32 - length);
+        put(1, 1, &mut out);
+        put(1, 2, &mut out);
+        let mut left = n;
+        if left > 0 {
+            put(code(0x30, 8), 8, &mut out);
+            left -= 1;
+        }
+        while left >= 258 {
+            put(code(0xc5, 8), 8, &mut out);
+            put(0, 5, &mut out);
+            left -= 258;
+        }
+        for _ in 0..left {
+            put(code(0x30, 8), 8, &mut out);
+        }
+        put(0, 7, &mut out);
+        put(0, 7, &mut out);
+        out
+    }
+
+    /// `deflate_zeros(n)` as a gzip member.
+    fn gzip_zeros(n: usize) -> Vec<u8> {
+        let mut out = b"\x1f\x8b\x08\0\0\0\0\0\0\xff".to_vec();
+        out.extend(deflate_zeros(n));
+        out.extend([0; 8]);
+        out
+    }
+
+    /// Decompresses gzip `data` whole, as [`super::gunzip_stream`] hands it on.
+    fn gunzip(data: &[u8], limit: usize) -> super::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        super::gunzip_stream(data.iter().copied(), limit, |piece| {
+            out.extend_from_slice(piece);
+            true
+        })?;
+        Ok(out)
+    }
+
+    #[test]
+    fn gunzip_stream_keeps_copying_across_pieces() {
+        let n = 10 * super::WINDOW + 7;
+        assert_eq!(gunzip(&gzip_zeros(n), n).unwrap(), vec![0; n]);
+        let mut pieces = 0;
+        super::gunzip_stream(gzip_zeros(n).into_iter(), n, |_| {
+            pieces += 1;
+            false
+        })
+        .unwrap();
+        assert_eq!(pieces, 1);
+    }
+
+    #[test]
+    fn gunzip_head_stops_early() {
+        let data = gzip_zeros(1000);
+        assert_eq!(super::gunzip_head(&data, 10).unwrap(), vec![0; 10]);
+        assert_eq!(super::gunzip_head(&data, 5000).unwrap(), vec![0; 1000]);
+        assert!(matches!(
+            gunzip(&data, 10),
+            Err(super::InflateError::TooLarge(10))
+        ));
+    }
+
+    /// `data` as a single stored block.
+    fn deflate_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![1];
+        out.extend((data.len() as u16).to_le_bytes());
+        out.extend((!(data.len() as u16)).to_le_bytes());
+        out.extend(data);
+        out
+    }
+
+    #[test]
+    fn limits_are_kept() {
+        assert!(matches!(
+            super::inflate(&deflate_zeros(1000), 999),
+            Err(super::InflateError::TooLarge(999))
+        ));
+        assert!(matches!(
+            super::inflate(&deflate_stored(b"stored"), 5),
+            Err(super::InflateError::TooLarge(5))
+        ));
+        assert_eq!(
+            super::inflate(&deflate_stored(b"stored"), 6).unwrap(),
+            (b"stored".to_vec(), 11)
+        );
+        let mut zlib = vec![0x78, 0x9c];
+        zlib.extend(deflate_zeros(300));
+        assert_eq!(super::inflate_zlib(&zlib, 300).unwrap(), vec![0; 300]);
+        assert!(matches!(
+            super::inflate_zlib(&zlib, 299),
+            Err(super::InflateError::TooLarge(299))
+        ));
+    }
+
+    #[test]
+    fn limits_hold_over_gzip_members() {
+        let mut data = gzip_zeros(600);
+        data.extend(gzip_zeros(600));
+        assert_eq!(gunzip(&data, 1200).unwrap(), vec![0; 1200]);
+        assert!(matches!(
+            gunzip(&data, 1000),
+            Err(super::InflateError::TooLarge(1000))
+        ));
+        assert_eq!(super::gunzip_head(&data, 1000).unwrap(), vec![0; 1000]);
+    }
+
+    #[test]
+    fn bad_data_is_refused() {
+        let zeros = deflate_zeros(1000);
+        assert!(matches!(
+            super::inflate(&zeros[..zeros.len() / 2], 1000),
+            Err(super::InflateError::Truncated)
+        ));
+        assert!(matches!(
+            super::inflate(&[0x07], 10),
+            Err(super::InflateError::Corrupt(_))
+        ));
+        assert!(matches!(
+            super::inflate(&[1, 3, 0, 0, 0], 10),
+            Err(super::InflateError::Corrupt(_))
+        ));
+        assert!(matches!(
+            super::inflate_zlib(&[0x78, 0x00], 10),
+            Err(super::InflateError::Corrupt(_))
+        ));
+        assert!(matches!(
+            gunzip(b"not gzip", 10),
+            Err(super::InflateError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn zeros_inflate() {
+        for n in [0, 1, 258, 259, 1000] {
+            assert_eq!(super::inflate(&deflate_zeros(n), n).unwrap().0, vec![0; n]);
+        }
+    }
+}
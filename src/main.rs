@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::{net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
 use lettre::{AsyncSmtpTransport, Tokio1Executor};
 use lettre::transport::smtp::authentication::Credentials;
 
@@ -6,31 +6,83 @@ use mongo::MongoClient;
 
 use crate::{cfg::AppConfig, state::AppState};
 
+mod archive;
 mod cfg;
+mod diff;
+mod inflate;
 mod mongo_entities;
+mod pdf;
+mod periodic;
 mod routes;
+mod saga;
+mod session;
+mod sniff;
 mod sql_entities;
 mod state;
+mod trash;
 
 #[tokio::main]
 async fn main() {
+    tracing_subscriber::fmt::init();
     let config = tokio::task::spawn_blocking(AppConfig::new).await.unwrap();
-    let sql_db = sea_orm::Database::connect(config.sql_db_url).await.unwrap();
+    let sql_db = sea_orm::Database::connect(&config.sql_db_url).await.unwrap();
     //let sql_db = sea_orm::DatabaseConnection::default();
-    let mongo_db = MongoClient::with_uri_str(config.mongo_srv_url)
+    let mongo_client = MongoClient::with_uri_str(&config.mongo_srv_url)
         .await
-        .unwrap()
-        .database(&config.mongo_db_nm);
+        .unwrap();
+    let mongo_db = mongo_client.database(&config.mongo_db_nm);
+    mongo_entities::sync_all_indexes(&mongo_db).await.unwrap();
+    mongo_entities::thesis::Thesis::backfill_search_terms(mongo_db.clone())
+        .await
+        .unwrap();
     let hash_cost = config.hash_cost;
+    let sessions = session::Store::new(config.session_store, &mongo_db, &sql_db);
+    tokio::spawn(
+        sessions
+            .clone()
+            .collect_garbage(Duration::from_secs(config.session_gc_interval)),
+    );
+    tokio::spawn(saga::keep_repairing(
+        sql_db.clone(),
+        mongo_db.clone(),
+        Duration::from_secs(config.repair_interval),
+        Duration::from_secs(config.orphan_grace),
+        config.remove_orphan_profiles,
+    ));
+    tokio::spawn(trash::keep_purging(
+        mongo_client.clone(),
+        mongo_db.clone(),
+        Duration::from_secs(config.purge_interval),
+        Duration::from_secs(config.trash_retention),
+    ));
+    tokio::spawn(mongo_entities::file::keep_sweeping(
+        mongo_db.clone(),
+        Duration::from_secs(config.file_sweep_interval),
+        Duration::from_secs(config.file_grace),
+    ));
+    let session_layer = session::layer(&config, sessions.clone());
     let smtp = <AsyncSmtpTransport<Tokio1Executor>>::relay(&config.relay).unwrap().port(465).credentials(Credentials::new(config.smtp_username, config.smtp_password)).build::<Tokio1Executor>();
     assert!(smtp.test_connection().await.unwrap());
-    let app = routes::new().with_state(AppState {
-        sql_db,
-        mongo_db,
-        hash_cost,
-        sender: config.sender,
-        smtp,
-    });
+    let app = routes::new()
+        .layer(session_layer)
+        .with_state(AppState {
+            sql_db,
+            mongo_client,
+            mongo_db,
+            hash_cost,
+            sender: config.sender,
+            smtp,
+            secret: config.session_secret.into_bytes().into(),
+            verification_ttl: config.verification_ttl,
+            reset_ttl: config.reset_ttl,
+            trash_retention: config.trash_retention,
+            max_file_size: config.max_file_size,
+            max_request_size: config.max_request_size,
+            max_unpacked_size: config.max_unpacked_size,
+            release_types: Arc::new(sniff::parse_list(&config.release_types).unwrap()),
+            source_types: Arc::new(sniff::parse_list(&config.source_types).unwrap()),
+            sessions,
+        });
     axum::Server::bind(&SocketAddr::from_str(&config.srv_addr).unwrap())
         .serve(app.into_make_service())
         .await
@@ -0,0 +1,120 @@
+//! GridFS files and what refers to them.
+//!
+//! Files are uploaded before anything refers to them, and GridFS takes no part in transactions,
+//! so a file can be left without references: a commit failed, a thesis was purged, an avatar
+//! was replaced. Those who drop a reference [`release`] the file at once; [`sweep`] catches what
+//! slips through.
+
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use mongo::{
+    attached::Attached,
+    bson::{self, Bson},
+    entity::{doc, field, operator::*, Entity},
+    gridfs,
+    oid::ObjectId,
+    MongoDatabase, MongoResult,
+};
+
+use super::{
+    profile::{Bio, Profile},
+    version::Version,
+};
+use crate::periodic;
+
+/// Whether a version or a profile still refers to the file.
+pub(crate) async fn is_referenced(db: MongoDatabase, id: ObjectId) -> MongoResult<bool> {
+    let used_by_version = <Entity<Attached<Version>>>::try_find_one(
+        db.clone(),
+        doc! {Or: [
+            {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(release_id in Version)): id},
+            {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(source_ids in Version)): id},
+        ]},
+    )
+    .await?
+    .is_some();
+    Ok(used_by_version
+        || <Entity<Profile>>::try_find_one(
+            db,
+            doc! {format!("{}.{}", field!(data in Entity<Profile>), field!(avatar_id in Bio)): id},
+        )
+        .await?
+        .is_some())
+}
+
+/// Deletes those of the files nothing refers to any more.
+pub(crate) async fn release(
+    db: MongoDatabase,
+    ids: impl IntoIterator<Item = ObjectId>,
+) -> MongoResult<u64> {
+    let bucket = db.gridfs_bucket(None);
+    let mut deleted = 0;
+    for id in ids {
+        if !is_referenced(db.clone(), id).await? {
+            gridfs::delete(&bucket, id).await?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// The files some version or profile refers to.
+async fn referenced_ids(db: &MongoDatabase) -> MongoResult<HashSet<ObjectId>> {
+    let mut ids = <Entity<Attached<Version>>>::distinct(
+        db.clone(),
+        field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(release_id in Version)),
+    )
+    .await?;
+    ids.extend(
+        <Entity<Attached<Version>>>::distinct(
+            db.clone(),
+            field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(source_ids in Version)),
+        )
+        .await?,
+    );
+    ids.extend(
+        <Entity<Profile>>::distinct(
+            db.clone(),
+            &format!(
+                "{}.{}",
+                field!(data in Entity<Profile>),
+                field!(avatar_id in Bio)
+            ),
+        )
+        .await?,
+    );
+    Ok(ids.iter().filter_map(Bson::as_object_id).collect())
+}
+
+/// Deletes the files nothing refers to, among those uploaded more than `grace` ago, so that
+/// uploads whose references are being written are left alone.
+pub(crate) async fn sweep(db: &MongoDatabase, grace: Duration) -> MongoResult<u64> {
+    let before = Utc::now() - chrono::Duration::from_std(grace).unwrap_or(chrono::Duration::zero());
+    let bucket = db.gridfs_bucket(None);
+    let mut files =
+        gridfs::find_uploaded_before(&bucket, bson::DateTime::from_chrono(before)).await?;
+    let referenced = referenced_ids(db).await?;
+    let mut deleted = 0;
+    while files.advance().await? {
+        if let Some(id) = files.deserialize_current()?.id.as_object_id() {
+            if !referenced.contains(&id) {
+                gridfs::delete(&bucket, id).await?;
+                deleted += 1;
+            }
+        }
+    }
+    Ok(deleted)
+}
+
+/// Runs [`sweep`] now and then every `period` for ever.
+pub(crate) async fn keep_sweeping(db: MongoDatabase, period: Duration, grace: Duration) {
+    periodic::every(period, || async {
+        match sweep(&db, grace).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::info!("deleted {} files nothing refers to", deleted),
+            Err(e) => tracing::error!("failed to sweep unreferenced files: {}", e),
+        }
+    })
+    .await
+}
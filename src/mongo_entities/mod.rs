@@ -1,6 +1,27 @@
+use mongo::{attached::Attached, entity::sync_indexes, owned::Owned, MongoDatabase, MongoResult};
+
+use self::{
+    paper_collection::{category::Category, magazine::Magazine, PaperCollection},
+    profile::Profile,
+    review::Review,
+    thesis::Thesis,
+    version::Version,
+};
+
 mod examples;
+pub(crate) mod file;
 pub(crate) mod paper_collection;
 pub(crate) mod profile;
 pub(crate) mod review;
+pub(crate) mod text;
 pub(crate) mod thesis;
 pub(crate) mod version;
+
+pub(crate) async fn sync_all_indexes(db: &MongoDatabase) -> MongoResult<()> {
+    sync_indexes::<Profile>(db).await?;
+    sync_indexes::<Owned<Thesis>>(db).await?;
+    sync_indexes::<Owned<PaperCollection<Magazine>>>(db).await?;
+    sync_indexes::<Owned<PaperCollection<Category>>>(db).await?;
+    sync_indexes::<Attached<Version>>(db).await?;
+    sync_indexes::<Attached<Review>>(db).await
+}
@@ -1,10 +1,13 @@
+use std::collections::BTreeSet;
+
 use async_trait::async_trait;
 use crud::Countable;
 use crud_derive::{Countable, Patchable, Postable, Viewable};
 use mongo::{
-    entity::{field, Entity},
+    entity::{doc, field, operator::*, Entity},
     oid::ObjectId,
     owned::Owned,
+    transaction::Transaction,
     MongoDatabase, MongoResult,
 };
 use schemars::JsonSchema;
@@ -33,22 +36,107 @@ impl PaperCollectionDetail for Category {
         Self::singular()
     }
 
+    async fn could_belong_to(
+        db: MongoDatabase,
+        id: ObjectId,
+        category_ids: &BTreeSet<ObjectId>,
+    ) -> MongoResult<bool> {
+        Ok(Self::subtree_ids(db, id).await?.is_disjoint(category_ids))
+    }
+
     async fn windup(
-        db: mongo::MongoDatabase,
+        tx: &mut Transaction,
         entity: &mongo::entity::Entity<mongo::owned::Owned<super::PaperCollection<Self>>>,
     ) -> mongo::MongoResult<()> {
-        <PaperCollection<Self>>::pull_category_ids(db.clone(), entity._id).await?;
-        <PaperCollection<Magazine>>::pull_category_ids(db, entity._id)
+        <PaperCollection<Self>>::pull_category_ids(tx, entity._id).await?;
+        <PaperCollection<Magazine>>::pull_category_ids(tx, entity._id)
             .await
             .map(|_| ())
     }
 }
 
+impl Category {
+    /// This category and all categories under it.
+    pub(crate) async fn subtree_ids(
+        db: MongoDatabase,
+        id: ObjectId,
+    ) -> MongoResult<BTreeSet<ObjectId>> {
+        let mut ids = BTreeSet::from([id]);
+        let mut parent_ids = vec![id];
+        while !parent_ids.is_empty() {
+            let mut children = <Entity<Owned<PaperCollection<Self>>>>::find(
+                db.clone(),
+                doc! {field!((data in Entity<Owned<PaperCollection<Category>>>).(content in Owned<PaperCollection<Category>>).(category_ids in PaperCollection<Category>)): {In: parent_ids}},
+            )
+            .await?;
+            parent_ids = Vec::new();
+            while children.advance().await? {
+                let child = children.deserialize_current()?;
+                if ids.insert(child._id) {
+                    parent_ids.push(child._id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// These paper collections and all categories they are under.
+    pub(crate) async fn ancestor_ids(
+        db: MongoDatabase,
+        ids: BTreeSet<ObjectId>,
+    ) -> MongoResult<BTreeSet<ObjectId>> {
+        let mut child_ids: Vec<_> = ids.iter().copied().collect();
+        let mut ids = ids;
+        while !child_ids.is_empty() {
+            let mut parent_ids =
+                <PaperCollection<Magazine>>::category_ids_of(db.clone(), &child_ids).await?;
+            parent_ids
+                .extend(<PaperCollection<Self>>::category_ids_of(db.clone(), &child_ids).await?);
+            child_ids = parent_ids
+                .into_iter()
+                .filter(|&parent_id| ids.insert(parent_id))
+                .collect();
+        }
+        Ok(ids)
+    }
+}
+
 impl<D: PaperCollectionDetail> PaperCollection<D> {
-    pub(super) async fn pull_category_ids(
+    async fn category_ids_of(
         db: MongoDatabase,
+        ids: &[ObjectId],
+    ) -> MongoResult<BTreeSet<ObjectId>> {
+        let mut found =
+            <Entity<Owned<Self>>>::find(db, doc! {field!(_id in Entity<Owned<()>>): {In: ids}})
+                .await?;
+        let mut category_ids = BTreeSet::new();
+        while found.advance().await? {
+            category_ids.extend(found.deserialize_current()?.data.content.category_ids);
+        }
+        Ok(category_ids)
+    }
+
+    /// IDs of the paper collections belonging to any of these categories.
+    pub(crate) async fn ids_in_categories(
+        db: MongoDatabase,
+        category_ids: &BTreeSet<ObjectId>,
+    ) -> MongoResult<BTreeSet<ObjectId>> {
+        let mut found = <Entity<Owned<Self>>>::find(
+            db,
+            doc! {field!((data in Entity<Owned<PaperCollection<Category>>>).(content in Owned<PaperCollection<Category>>).(category_ids in PaperCollection<Category>)): {In: category_ids.iter().collect::<Vec<_>>()}},
+        )
+        .await?;
+        let mut ids = BTreeSet::new();
+        while found.advance().await? {
+            ids.insert(found.deserialize_current()?._id);
+        }
+        Ok(ids)
+    }
+
+    pub(super) async fn pull_category_ids(
+        tx: &mut Transaction,
         category_id: ObjectId,
     ) -> MongoResult<(u64, u64)> {
-        <Entity<Owned<Self>>>::pull_sets(db, field!((data in Entity<Owned<PaperCollection<Category>>>).(content in Owned<PaperCollection<Category>>).(category_ids in PaperCollection<Category>)), category_id).await
+        <Entity<Owned<Self>>>::pull_sets_in(tx, field!((data in Entity<Owned<PaperCollection<Category>>>).(content in Owned<PaperCollection<Category>>).(category_ids in PaperCollection<Category>)), category_id).await
     }
 }
@@ -1,24 +1,59 @@
 use std::collections::BTreeSet;
 
 use async_trait::async_trait;
-use crud::Countable;
+use crud::{Countable, Patchable, Postable};
 use crud_derive::{Countable, Patchable, Postable, Viewable};
-use mongo::{entity::Entity, owned::Owned, MongoDatabase, MongoResult};
+use mongo::{
+    bson::{self, Bson},
+    entity::{doc, field, operator::*, update::Update, Entity},
+    oid::{ObjectId, ObjectIdDef},
+    owned::Owned,
+    transaction::Transaction,
+    MongoDatabase, MongoResult,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 use super::{
-    super::{examples, thesis::Thesis},
+    super::{
+        examples, review::form::ReviewForm, thesis::Thesis, version::anonymity::ReviewAnonymity,
+    },
     PaperCollection, PaperCollectionDetail,
 };
 
+#[derive(Viewable)]
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct EditorialBoard {
+    #[viewable(serialize_with = "oid::serialize_object_id_option_as_hex_string")]
+    #[schemars(title = "Editor-in-Chief ID", with = "Option<ObjectIdDef>")]
+    pub(crate) chief_id: Option<ObjectId>,
+    #[viewable(serialize_with = "oid::serialize_object_id_collection_as_hex_string")]
+    #[schemars(
+        title = "Associate Editor IDs",
+        description = "Do not repeat.",
+        with = "BTreeSet<ObjectIdDef>"
+    )]
+    pub(crate) associate_ids: BTreeSet<ObjectId>,
+}
+
+impl EditorialBoard {
+    pub(crate) fn member_ids(&self) -> impl Iterator<Item = &ObjectId> {
+        self.chief_id.iter().chain(&self.associate_ids)
+    }
+}
+
 #[derive(Postable)]
 #[derive(Countable)]
 #[derive(Viewable)]
 #[derive(Patchable)]
 #[derive(JsonSchema)]
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 #[derive(Default)]
 #[derive(Clone)]
 #[derive(Debug)]
@@ -55,15 +90,50 @@ pub(crate) struct Magazine {
     #[patchable]
     #[schemars(title = "Other Information")]
     pub(crate) others: String,
+    #[viewable]
+    #[patchable]
+    #[schemars(
+        title = "Review Anonymity",
+        description = "Of the theses filed here, the strictest of their magazines if several, unless set for a version."
+    )]
+    pub(crate) review_anonymity: ReviewAnonymity,
+    #[viewable]
+    #[patchable(serde(with = "::serde_with::rust::double_option"))]
+    #[schemars(
+        title = "Review Form",
+        description = "What reviewers fill in besides their criticism and judgement."
+    )]
+    pub(crate) review_form: Option<ReviewForm>,
+    #[viewable(into)]
+    #[postable(serde(skip))]
+    #[schemars(
+        title = "Editorial Board",
+        description = "Set by the owner through its own endpoint."
+    )]
+    pub(crate) board: EditorialBoard,
 }
 
 #[async_trait]
 impl PaperCollectionDetail for Magazine {
     async fn windup(
-        db: MongoDatabase,
+        tx: &mut Transaction,
         entity: &Entity<Owned<PaperCollection<Self>>>,
     ) -> MongoResult<()> {
-        Thesis::pull_magazine_ids(db, entity._id).await.map(|_| ())
+        Thesis::pull_magazine_ids(tx, entity._id).await.map(|_| ())
+    }
+
+    fn check_post(post: &<Self as Postable>::Post) -> Result<(), String> {
+        match &post.review_form {
+            Some(form) => form.check().map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn check_patch(patch: &<Self as Patchable>::Patch) -> Result<(), String> {
+        match &patch.review_form {
+            Some(Some(form)) => form.check().map_err(|e| e.to_string()),
+            _ => Ok(()),
+        }
     }
 
     fn collection_name() -> &'static str {
@@ -74,3 +144,95 @@ impl PaperCollectionDetail for Magazine {
         Self::singular()
     }
 }
+
+impl Magazine {
+    /// The board sits right in the content, since the detail is flattened.
+    fn board_path() -> String {
+        format!(
+            "{}.{}",
+            field!((data in Entity<Owned<PaperCollection<Magazine>>>).(content in Owned<PaperCollection<Magazine>>)),
+            field!(board in Magazine)
+        )
+    }
+
+    /// A filter for magazines on whose board `member_id` sits.
+    fn edited_by(member_id: ObjectId) -> mongo::bson::Document {
+        let board = Self::board_path();
+        doc! {Or: [
+            {format!("{}.{}", board, field!(chief_id in EditorialBoard)): member_id},
+            {format!("{}.{}", board, field!(associate_ids in EditorialBoard)): member_id},
+        ]}
+    }
+
+    /// IDs of the magazines on whose board `member_id` sits.
+    pub(crate) async fn ids_edited_by(
+        db: MongoDatabase,
+        member_id: ObjectId,
+    ) -> MongoResult<BTreeSet<ObjectId>> {
+        let mut found =
+            <Entity<Owned<PaperCollection<Self>>>>::find(db, Self::edited_by(member_id)).await?;
+        let mut ids = BTreeSet::new();
+        while found.advance().await? {
+            ids.insert(found.deserialize_current()?._id);
+        }
+        Ok(ids)
+    }
+
+    pub(crate) async fn find_by_ids(
+        db: MongoDatabase,
+        ids: &BTreeSet<ObjectId>,
+    ) -> MongoResult<Vec<Entity<Owned<PaperCollection<Self>>>>> {
+        let mut found = <Entity<Owned<PaperCollection<Self>>>>::find(
+            db,
+            doc! {field!(_id in Entity<Owned<()>>): {In: ids.iter().collect::<Vec<_>>()}},
+        )
+        .await?;
+        let mut magazines = Vec::new();
+        while found.advance().await? {
+            magazines.push(found.deserialize_current()?);
+        }
+        Ok(magazines)
+    }
+
+    pub(crate) async fn set_board(
+        db: MongoDatabase,
+        id: ObjectId,
+        board: &EditorialBoard,
+    ) -> MongoResult<Option<Entity<Owned<PaperCollection<Self>>>>> {
+        <Entity<Owned<PaperCollection<Self>>>>::try_find_one_and_update_by_id(
+            db,
+            id,
+            Update {
+                set: doc! {&Self::board_path(): bson::to_bson(board)?},
+                ..Update::default()
+            },
+        )
+        .await
+    }
+
+    /// Takes `member_id` off every editorial board.
+    pub(crate) async fn leave_boards(tx: &mut Transaction, member_id: ObjectId) -> MongoResult<()> {
+        let board = Self::board_path();
+        let chief_id = format!("{}.{}", board, field!(chief_id in EditorialBoard));
+        <Entity<Owned<PaperCollection<Self>>>>::update_many_in(
+            tx,
+            doc! {&chief_id: member_id},
+            Update {
+                set: doc! {&chief_id: Bson::Null},
+                ..Update::default()
+            },
+        )
+        .await?;
+        let associate_ids = format!("{}.{}", board, field!(associate_ids in EditorialBoard));
+        <Entity<Owned<PaperCollection<Self>>>>::update_many_in(
+            tx,
+            doc! {&associate_ids: member_id},
+            Update {
+                pull: doc! {&associate_ids: member_id},
+                ..Update::default()
+            },
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -10,6 +10,7 @@ use mongo::{
     entity::{field, Entity},
     oid::{ObjectId, ObjectIdDef},
     owned::{Owned, OwnedContent},
+    transaction::Transaction,
     MongoDatabase, MongoResult,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -34,8 +35,24 @@ pub(crate) trait PaperCollectionDetail:
 {
     fn collection_name() -> &'static str;
     fn schema_name() -> &'static str;
+    /// Whether the paper collection `id` could be put into these categories.
+    async fn could_belong_to(
+        _db: MongoDatabase,
+        _id: ObjectId,
+        _category_ids: &BTreeSet<ObjectId>,
+    ) -> MongoResult<bool> {
+        Ok(true)
+    }
+    /// Why these details could not be posted, if they could not.
+    fn check_post(_post: &<Self as Postable>::Post) -> Result<(), String> {
+        Ok(())
+    }
+    /// Why these details could not be patched in, if they could not.
+    fn check_patch(_patch: &<Self as Patchable>::Patch) -> Result<(), String> {
+        Ok(())
+    }
     async fn windup(
-        db: MongoDatabase,
+        tx: &mut Transaction,
         entity: &Entity<Owned<PaperCollection<Self>>>,
     ) -> MongoResult<()>;
 }
@@ -94,9 +111,9 @@ impl<D: PaperCollectionDetail> OwnedContent for PaperCollection<D> {
     }
 
     async fn windup(
-        db: MongoDatabase,
+        tx: &mut Transaction,
         entity: &Entity<mongo::owned::Owned<Self>>,
     ) -> MongoResult<()> {
-        D::windup(db, entity).await
+        D::windup(tx, entity).await
     }
 }
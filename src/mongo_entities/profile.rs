@@ -8,15 +8,16 @@ use mongo::{
         Indexes,
     },
     oid::{ObjectId, ObjectIdDef},
-    owned::Owned,
-    MongoDatabase, MongoResult,
+    owned::{Owned, OwnedContent},
+    transaction::Transaction,
+    MongoClient, MongoDatabase, MongoResult,
 };
 use notice::email::{Address, AddressDef};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::{
-    examples,
+    examples, file,
     paper_collection::{category::Category, magazine::Magazine, PaperCollection},
     review::Review,
     thesis::Thesis,
@@ -99,10 +100,16 @@ impl CollectionConfig for Profile {
     }
 
     fn indexes() -> Indexes {
-        Indexes::new().with(
-            Index::new(field!((data in Entity<Profile>).(email in Profile)))
-                .with_option(IndexOption::Unique),
-        )
+        Indexes::new()
+            .with(
+                Index::new(field!((data in Entity<Profile>).(email in Profile)))
+                    .with_option(IndexOption::Unique),
+            )
+            .with(Index::new(format!(
+                "{}.{}",
+                field!(data in Entity<Profile>),
+                field!(avatar_id in Bio)
+            )))
     }
 }
 
@@ -131,15 +138,42 @@ impl Profile {
         .await
     }
 
-    pub(crate) async fn delete(db: MongoDatabase, entity: Entity<Self>) -> MongoResult<u64> {
-        <Entity<Owned<Thesis>>>::delete_owneds_of_owner(db.clone(), entity._id).await?;
-        <Entity<Owned<PaperCollection<Magazine>>>>::delete_owneds_of_owner(db.clone(), entity._id)
-            .await?;
-        <Entity<Owned<PaperCollection<Category>>>>::delete_owneds_of_owner(db.clone(), entity._id)
-            .await?;
-        <Entity<Attached<Review>>>::remove_creator_of_attached(db.clone(), entity._id).await?;
-        <Entity<Attached<Version>>>::remove_creator_of_attached(db.clone(), entity._id).await?;
-        <Entity<Self>>::delete_by_id(db, entity._id).await
+    /// Deletes the profile with everything it owns, all or nothing, and then the files nobody
+    /// else refers to.
+    pub(crate) async fn delete(
+        client: &MongoClient,
+        db: MongoDatabase,
+        entity: Entity<Self>,
+    ) -> MongoResult<u64> {
+        let id = entity._id;
+        let avatar_id = entity.data.bio.avatar_id;
+        let (deleted, file_ids) = Transaction::run(client, db.clone(), |tx| {
+            let db = db.clone();
+            Box::pin(async move {
+                let mut file_ids = Vec::from_iter(avatar_id);
+                for thesis in <Entity<Owned<Thesis>>>::find_with_trashed_in(
+                    tx,
+                    doc! {field!((data in Entity<Owned<Thesis>>).(owner_id in Owned<Thesis>)): id},
+                )
+                .await?
+                {
+                    file_ids.extend(Thesis::file_ids(db.clone(), &thesis).await?);
+                }
+                <Entity<Owned<Thesis>>>::delete_owneds_of_owner_in(tx, id).await?;
+                <Entity<Owned<PaperCollection<Magazine>>>>::delete_owneds_of_owner_in(tx, id)
+                    .await?;
+                <Entity<Owned<PaperCollection<Category>>>>::delete_owneds_of_owner_in(tx, id)
+                    .await?;
+                Magazine::leave_boards(tx, id).await?;
+                <Entity<Attached<Review>>>::remove_creator_of_attached_in(tx, id).await?;
+                <Entity<Attached<Version>>>::remove_creator_of_attached_in(tx, id).await?;
+                let deleted = <Entity<Self>>::delete_by_id_in(tx, id).await?;
+                Ok((deleted, file_ids))
+            })
+        })
+        .await?;
+        file::release(db, file_ids).await?;
+        Ok(deleted)
     }
 }
 
@@ -0,0 +1,408 @@
+//! Review forms, which magazines set for reviewers to fill in, and the scores they add up to.
+
+use std::collections::BTreeSet;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Review;
+
+/// Something a reviewer scores a version on.
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct Criterion {
+    #[schemars(title = "Name", description = "Do not repeat within a form.")]
+    pub(crate) name: String,
+    #[serde(default)]
+    #[schemars(title = "Description")]
+    pub(crate) description: String,
+    #[schemars(title = "Lowest Score")]
+    pub(crate) min: i32,
+    #[schemars(title = "Highest Score")]
+    pub(crate) max: i32,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct ReviewForm {
+    #[schemars(title = "Criteria", description = "Each to be scored in every review.")]
+    pub(crate) criteria: Vec<Criterion>,
+    #[serde(default)]
+    #[schemars(
+        title = "Recommendation",
+        description = "Whether every review is to recommend what to do with the version."
+    )]
+    pub(crate) recommendation: bool,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum Recommendation {
+    Accept,
+    MinorRevision,
+    MajorRevision,
+    Reject,
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum ReviewFormError {
+    #[error("a criterion has no name")]
+    Unnamed,
+    #[error("criterion \"{0}\" is repeated")]
+    Repeated(String),
+    #[error("criterion \"{0}\" has no score between its lowest and highest")]
+    EmptyRange(String),
+    #[error("there is no review form to fill in")]
+    NoForm,
+    #[error("criterion \"{0}\" is not scored")]
+    Unscored(String),
+    #[error("there is no criterion \"{0}\"")]
+    Unknown(String),
+    #[error("criterion \"{name}\" is scored from {min} to {max}, not {score}")]
+    OutOfRange {
+        name: String,
+        score: i32,
+        min: i32,
+        max: i32,
+    },
+    #[error("a recommendation is required")]
+    NoRecommendation,
+    #[error("no recommendation is asked for")]
+    UnaskedRecommendation,
+}
+
+impl ReviewForm {
+    /// Whether the form could be filled in at all.
+    pub(crate) fn check(&self) -> Result<(), ReviewFormError> {
+        let mut names = BTreeSet::new();
+        for criterion in &self.criteria {
+            if criterion.name.trim().is_empty() {
+                return Err(ReviewFormError::Unnamed);
+            }
+            if !names.insert(&criterion.name) {
+                return Err(ReviewFormError::Repeated(criterion.name.clone()));
+            }
+            if criterion.min > criterion.max {
+                return Err(ReviewFormError::EmptyRange(criterion.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `review` fills in exactly this form.
+    pub(crate) fn check_review(&self, review: &Review) -> Result<(), ReviewFormError> {
+        for criterion in &self.criteria {
+            let &score = review
+                .scores
+                .get(&criterion.name)
+                .ok_or_else(|| ReviewFormError::Unscored(criterion.name.clone()))?;
+            if !(criterion.min..=criterion.max).contains(&score) {
+                return Err(ReviewFormError::OutOfRange {
+                    name: criterion.name.clone(),
+                    score,
+                    min: criterion.min,
+                    max: criterion.max,
+                });
+            }
+        }
+        if let Some(name) = review.scores.keys().find(|&name| {
+            !self
+                .criteria
+                .iter()
+                .any(|criterion| &criterion.name == name)
+        }) {
+            return Err(ReviewFormError::Unknown(name.clone()));
+        }
+        match (self.recommendation, review.recommendation) {
+            (true, None) => Err(ReviewFormError::NoRecommendation),
+            (false, Some(_)) => Err(ReviewFormError::UnaskedRecommendation),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How a criterion is scored over the reviews of a version.
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct CriterionScores {
+    #[schemars(title = "Name")]
+    pub(crate) name: String,
+    #[schemars(title = "Mean")]
+    pub(crate) mean: f64,
+    #[schemars(title = "Lowest")]
+    pub(crate) lowest: i32,
+    #[schemars(title = "Highest")]
+    pub(crate) highest: i32,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct Recommendations {
+    #[schemars(title = "Accept")]
+    pub(crate) accept: i32,
+    #[schemars(title = "Minor Revision")]
+    pub(crate) minor_revision: i32,
+    #[schemars(title = "Major Revision")]
+    pub(crate) major_revision: i32,
+    #[schemars(title = "Reject")]
+    pub(crate) reject: i32,
+}
+
+/// What the reviews of a version add up to on its review form.
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct ReviewSummary {
+    #[schemars(title = "Reviews", description = "How many are summed up.")]
+    pub(crate) reviews: i32,
+    #[schemars(
+        title = "Criteria",
+        description = "In the order of the form, leaving out those nobody scored."
+    )]
+    pub(crate) criteria: Vec<CriterionScores>,
+    #[schemars(
+        title = "Recommendations",
+        description = "How many reviews recommend each."
+    )]
+    pub(crate) recommendations: Recommendations,
+}
+
+impl ReviewSummary {
+    pub(crate) fn of<'a>(
+        form: &ReviewForm,
+        reviews: impl IntoIterator<Item = &'a Review> + Clone,
+    ) -> Self {
+        let criteria = form
+            .criteria
+            .iter()
+            .filter_map(|criterion| {
+                let scores: Vec<_> = reviews
+                    .clone()
+                    .into_iter()
+                    .filter_map(|review| review.scores.get(&criterion.name).copied())
+                    .collect();
+                let lowest = *scores.iter().min()?;
+                let highest = *scores.iter().max()?;
+                let sum: f64 = scores.iter().map(|&score| f64::from(score)).sum();
+                Some(CriterionScores {
+                    name: criterion.name.clone(),
+                    mean: sum / scores.len() as f64,
+                    lowest,
+                    highest,
+                })
+            })
+            .collect();
+        let mut recommendations = Recommendations::default();
+        let mut count = 0;
+        for review in reviews {
+            count += 1;
+            match review.recommendation {
+                Some(Recommendation::Accept) => recommendations.accept += 1,
+                Some(Recommendation::MinorRevision) => recommendations.minor_revision += 1,
+                Some(Recommendation::MajorRevision) => recommendations.major_revision += 1,
+                Some(Recommendation::Reject) => recommendations.reject += 1,
+                None => {}
+            }
+        }
+        Self {
+            reviews: count,
+            criteria,
+            recommendations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn criterion(name: &str, min: i32, max: i32) -> Criterion {
+        Criterion {
+            name: name.to_string(),
+            description: String::new(),
+            min,
+            max,
+        }
+    }
+
+    fn form(recommendation: bool) -> ReviewForm {
+        ReviewForm {
+            criteria: vec![criterion("novelty", 1, 5), criterion("clarity", 0, 10)],
+            recommendation,
+        }
+    }
+
+    fn review(scores: &[(&str, i32)], recommendation: Option<Recommendation>) -> Review {
+        Review {
+            scores: scores
+                .iter()
+                .map(|&(name, score)| (name.to_string(), score))
+                .collect(),
+            recommendation,
+            ..Review::default()
+        }
+    }
+
+    #[test]
+    fn forms_must_be_fillable() {
+        assert!(form(true).check().is_ok());
+        assert!(ReviewForm::default().check().is_ok());
+        let check = |criteria| {
+            ReviewForm {
+                criteria,
+                recommendation: false,
+            }
+            .check()
+        };
+        assert!(matches!(
+            check(vec![criterion(" ", 0, 1)]),
+            Err(ReviewFormError::Unnamed)
+        ));
+        assert!(matches!(
+            check(vec![criterion("a", 0, 1), criterion("a", 2, 3)]),
+            Err(ReviewFormError::Repeated(name)) if name == "a"
+        ));
+        assert!(matches!(
+            check(vec![criterion("a", 2, 1)]),
+            Err(ReviewFormError::EmptyRange(name)) if name == "a"
+        ));
+        assert!(check(vec![criterion("a", 3, 3)]).is_ok());
+    }
+
+    #[test]
+    fn reviews_must_fill_in_the_form() {
+        let form = form(true);
+        let accept = Some(Recommendation::Accept);
+        assert!(form
+            .check_review(&review(&[("novelty", 1), ("clarity", 10)], accept))
+            .is_ok());
+        assert!(matches!(
+            form.check_review(&review(&[("novelty", 3)], accept)),
+            Err(ReviewFormError::Unscored(name)) if name == "clarity"
+        ));
+        assert!(matches!(
+            form.check_review(&review(&[("novelty", 6), ("clarity", 5)], accept)),
+            Err(ReviewFormError::OutOfRange { name, score: 6, min: 1, max: 5 }) if name == "novelty"
+        ));
+        assert!(matches!(
+            form.check_review(&review(&[("novelty", 0), ("clarity", 5)], accept)),
+            Err(ReviewFormError::OutOfRange { score: 0, .. })
+        ));
+        assert!(matches!(
+            form.check_review(&review(
+                &[("novelty", 3), ("clarity", 5), ("style", 1)],
+                accept
+            )),
+            Err(ReviewFormError::Unknown(name)) if name == "style"
+        ));
+        assert!(matches!(
+            form.check_review(&review(&[("novelty", 3), ("clarity", 5)], None)),
+            Err(ReviewFormError::NoRecommendation)
+        ));
+    }
+
+    #[test]
+    fn recommendations_are_only_taken_if_asked_for() {
+        let form = form(false);
+        let scores = [("novelty", 3), ("clarity", 5)];
+        assert!(form.check_review(&review(&scores, None)).is_ok());
+        assert!(matches!(
+            form.check_review(&review(&scores, Some(Recommendation::Reject))),
+            Err(ReviewFormError::UnaskedRecommendation)
+        ));
+    }
+
+    #[test]
+    fn nothing_to_summarize() {
+        assert_eq!(
+            ReviewSummary::of(&form(true), &[]),
+            ReviewSummary {
+                reviews: 0,
+                criteria: Vec::new(),
+                recommendations: Recommendations::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn scores_are_summarized_in_the_order_of_the_form() {
+        let reviews = [
+            review(
+                &[("clarity", 4), ("novelty", 2)],
+                Some(Recommendation::Accept),
+            ),
+            review(&[("clarity", 7)], Some(Recommendation::MajorRevision)),
+            review(&[("clarity", 10)], Some(Recommendation::Accept)),
+            review(&[], None),
+        ];
+        let summary = ReviewSummary::of(&form(true), &reviews);
+        assert_eq!(summary.reviews, 4);
+        assert_eq!(
+            summary.criteria,
+            [
+                CriterionScores {
+                    name: "novelty".to_string(),
+                    mean: 2.0,
+                    lowest: 2,
+                    highest: 2,
+                },
+                CriterionScores {
+                    name: "clarity".to_string(),
+                    mean: 7.0,
+                    lowest: 4,
+                    highest: 10,
+                },
+            ]
+        );
+        assert_eq!(
+            summary.recommendations,
+            Recommendations {
+                accept: 2,
+                minor_revision: 0,
+                major_revision: 1,
+                reject: 0,
+            }
+        );
+    }
+
+    /// Scores given before the form was changed are summed up as they are, and those of
+    /// criteria taken out of the form are left out.
+    #[test]
+    fn scores_out_of_the_form_are_summarized_as_they_are() {
+        let reviews = [
+            review(&[("novelty", 9), ("style", 3)], None),
+            review(&[("novelty", 1)], None),
+        ];
+        let summary = ReviewSummary::of(&form(false), &reviews);
+        assert_eq!(
+            summary.criteria,
+            [CriterionScores {
+                name: "novelty".to_string(),
+                mean: 5.0,
+                lowest: 1,
+                highest: 9,
+            }]
+        );
+    }
+}
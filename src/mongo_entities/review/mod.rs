@@ -1,3 +1,7 @@
+pub(crate) mod form;
+
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
 use crud::Countable;
 use crud_derive::{Countable, Viewable};
@@ -5,11 +9,14 @@ use mongo::{
     attached::{Attached, AttachedContent},
     entity::{field, Entity, Index, Indexes},
     oid::{ObjectId, ObjectIdDef},
-    MongoDatabase, MongoResult,
+    transaction::Transaction,
+    MongoResult,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use self::form::Recommendation;
+
 #[derive(Countable)]
 #[derive(Viewable)]
 #[derive(JsonSchema)]
@@ -33,6 +40,28 @@ pub(crate) struct Review {
         description = "Do this reviewer think that this version should be passed or not?"
     )]
     pub(crate) judgement: bool,
+    #[viewable]
+    #[serde(default)]
+    #[schemars(
+        title = "Scores",
+        description = "By the names of the criteria of the review form of the version."
+    )]
+    pub(crate) scores: BTreeMap<String, i32>,
+    #[viewable]
+    #[serde(default)]
+    #[schemars(
+        title = "Recommendation",
+        description = "If the review form asks for one."
+    )]
+    pub(crate) recommendation: Option<Recommendation>,
+}
+
+impl Review {
+    /// Puts the review on `version_id`, the version it is posted to, whatever version the
+    /// reviewer says it is for.
+    pub(crate) fn posted_to(self, version_id: ObjectId) -> Self {
+        Self { version_id, ..self }
+    }
 }
 
 #[async_trait]
@@ -49,7 +78,26 @@ impl AttachedContent for Review {
         Indexes::new().with(Index::new(field!((data in Entity<Attached<Review>>).(content in Attached<Review>).(version_id in Review))).with_key(field!(created_at in Entity<Attached<Review>>)))
     }
 
-    async fn windup(_db: MongoDatabase, _id: &Entity<Attached<Self>>) -> MongoResult<()> {
+    async fn windup(_tx: &mut Transaction, _id: &Entity<Attached<Self>>) -> MongoResult<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reviews_stay_on_the_version_they_are_posted_to() {
+        let elsewhere = ObjectId::new();
+        let review: Review = serde_json::from_value(serde_json::json!({
+            "version_id": elsewhere.to_hex(),
+            "criticism": "",
+            "judgement": true,
+        }))
+        .unwrap();
+        assert_eq!(review.version_id, elsewhere);
+        let posted_to = ObjectId::new();
+        assert_eq!(review.posted_to(posted_to).version_id, posted_to);
+    }
+}
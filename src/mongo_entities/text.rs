@@ -0,0 +1,208 @@
+//! Tokenization for full-text search.
+//!
+//! The MongoDB text index only splits on spaces and punctuation, which leaves a whole Chinese
+//! sentence as one term. So CJK runs are cut into bigrams here, and the index is built on the
+//! tokens instead of the raw text.
+
+use std::{collections::BTreeSet, ops::Range};
+
+const SNIPPET_RADIUS: usize = 24;
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+/// Splits `text` into lowercase words and CJK bigrams, with their ranges in chars.
+fn tokenize(text: &str) -> Vec<(Range<usize>, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let c = chars[start];
+        if is_cjk(c) {
+            let end = (start..chars.len())
+                .find(|&i| !is_cjk(chars[i]))
+                .unwrap_or(chars.len());
+            if end - start == 1 {
+                tokens.push((start..end, c.to_string()));
+            } else {
+                tokens
+                    .extend((start..end - 1).map(|i| (i..i + 2, chars[i..i + 2].iter().collect())));
+            }
+            start = end;
+        } else if c.is_alphanumeric() {
+            let end = (start..chars.len())
+                .find(|&i| !chars[i].is_alphanumeric() || is_cjk(chars[i]))
+                .unwrap_or(chars.len());
+            tokens.push((
+                start..end,
+                chars[start..end].iter().collect::<String>().to_lowercase(),
+            ));
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+    tokens
+}
+
+/// Distinct tokens of all these texts, ready to be stored for a text index.
+pub(crate) fn terms<'a>(texts: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    texts
+        .into_iter()
+        .flat_map(tokenize)
+        .map(|(_, token)| token)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Which chars of `text` are covered by one of `terms`.
+fn marks(text: &str, terms: &BTreeSet<String>) -> Vec<bool> {
+    let mut marks = vec![false; text.chars().count()];
+    for (range, token) in tokenize(text) {
+        if terms.contains(&token) {
+            marks[range].iter_mut().for_each(|m| *m = true);
+        }
+    }
+    marks
+}
+
+fn emphasize(chars: &[char], marks: &[bool]) -> String {
+    let mut emphasized = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if marks[i] && (i == 0 || !marks[i - 1]) {
+            emphasized.push_str("<em>");
+        }
+        emphasized.push(c);
+        if marks[i] && (i + 1 == marks.len() || !marks[i + 1]) {
+            emphasized.push_str("</em>");
+        }
+    }
+    emphasized
+}
+
+/// Wraps the parts of `text` matching `terms` in `<em>` tags, or `None` if nothing matches.
+pub(crate) fn highlight(text: &str, terms: &BTreeSet<String>) -> Option<String> {
+    let marks = marks(text, terms);
+    marks
+        .contains(&true)
+        .then(|| emphasize(&text.chars().collect::<Vec<_>>(), &marks))
+}
+
+/// At most `count` highlighted pieces of `text` around the matched parts.
+pub(crate) fn snippets(text: &str, terms: &BTreeSet<String>, count: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let marks = marks(text, terms);
+    let mut windows: Vec<Range<usize>> = Vec::new();
+    for (i, _) in marks.iter().enumerate().filter(|(_, &m)| m) {
+        let window = i.saturating_sub(SNIPPET_RADIUS)..(i + SNIPPET_RADIUS + 1).min(chars.len());
+        match windows.last_mut() {
+            Some(last)
+                if last.end >= window.start && window.end - last.start <= 4 * SNIPPET_RADIUS =>
+            {
+                last.end = window.end
+            }
+            _ => windows.push(window),
+        }
+    }
+    windows
+        .into_iter()
+        .take(count)
+        .map(|window| {
+            format!(
+                "{}{}{}",
+                if window.start > 0 { "…" } else { "" },
+                emphasize(&chars[window.clone()], &marks[window.clone()]),
+                if window.end < chars.len() { "…" } else { "" },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term_set(query: &str) -> BTreeSet<String> {
+        terms([query]).into_iter().collect()
+    }
+
+    #[test]
+    fn words_are_lowercased_and_split_on_punctuation() {
+        assert_eq!(
+            terms(["Rust, the Language—of SYSTEMS!"]),
+            ["language", "of", "rust", "systems", "the"]
+        );
+    }
+
+    #[test]
+    fn words_are_kept_whole() {
+        assert_eq!(terms(["Straße café3"]), ["café3", "straße"]);
+    }
+
+    #[test]
+    fn cjk_runs_are_cut_into_bigrams() {
+        assert_eq!(terms(["深度学习"]), ["学习", "度学", "深度"]);
+        assert_eq!(terms(["学"]), ["学"]);
+    }
+
+    #[test]
+    fn cjk_and_latin_are_split_apart() {
+        assert_eq!(terms(["GPU加速"]), ["gpu", "加速"]);
+    }
+
+    #[test]
+    fn terms_are_distinct_over_all_texts() {
+        assert_eq!(terms(["a b", "B c", ""]), ["a", "b", "c"]);
+        assert!(terms(["  ,.!  "]).is_empty());
+    }
+
+    #[test]
+    fn token_ranges_are_in_chars() {
+        let ranges: Vec<_> = tokenize("é 深度x").into_iter().map(|(r, _)| r).collect();
+        assert_eq!(ranges, [0..1, 2..4, 4..5]);
+    }
+
+    #[test]
+    fn matches_are_highlighted() {
+        assert_eq!(
+            highlight("Deep Learning for deep sea", &term_set("deep")).as_deref(),
+            Some("<em>Deep</em> Learning for <em>deep</em> sea")
+        );
+        assert_eq!(
+            highlight("深度学习", &term_set("深度学习")).as_deref(),
+            Some("<em>深度学习</em>")
+        );
+        assert_eq!(highlight("nothing here", &term_set("deep")), None);
+    }
+
+    #[test]
+    fn snippets_are_cut_around_matches() {
+        let filler = "x ".repeat(50);
+        let text = format!("{filler}needle {filler}needle {filler}");
+        let snippets = snippets(&text, &term_set("needle"), 3);
+        assert_eq!(snippets.len(), 2);
+        for snippet in &snippets {
+            assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+            assert!(snippet.contains("<em>needle</em>"));
+        }
+        assert_eq!(super::snippets(&text, &term_set("needle"), 1).len(), 1);
+    }
+
+    #[test]
+    fn short_texts_are_not_elided() {
+        assert_eq!(
+            snippets("a needle", &term_set("needle"), 3),
+            ["a <em>needle</em>"]
+        );
+        assert!(snippets("a needle", &term_set("hay"), 3).is_empty());
+    }
+}
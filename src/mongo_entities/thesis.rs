@@ -5,14 +5,28 @@ use crud::{Countable, Patchable, Postable};
 use crud_derive::{Countable, Patchable, Postable, Viewable};
 use mongo::{
     attached::{Attached, AttachedContent},
-    entity::{doc, field, Entity},
+    bson::{self, Document},
+    entity::{
+        doc, field, is_duplicate_key, operator::*, update::Update, Entity, Index,
+        IndexOption, Indexes,
+    },
     oid::{ObjectId, ObjectIdDef},
     owned::{Owned, OwnedContent},
+    transaction::Transaction,
     MongoDatabase, MongoResult,
 };
 use serde::{Deserialize, Serialize};
 
-use super::{examples, version::Version};
+use super::{
+    examples, text,
+    version::{
+        workflow::{Action, TransitionError},
+        PdfInfo, Version, VersionState,
+    },
+};
+
+/// How many numbers a commit claims before giving up on finding a free one.
+const COMMIT_ATTEMPTS: usize = 8;
 
 #[derive(Viewable)]
 #[derive(Patchable)]
@@ -72,6 +86,27 @@ pub(crate) struct ThesisIntroduction {
     pub(crate) language: BTreeSet<String>,
 }
 
+/// Tokens of the introduction, see [`text`].
+#[derive(Serialize, Deserialize)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct SearchTerms {
+    title: Vec<String>,
+    abstraction: Vec<String>,
+    keywords: Vec<String>,
+}
+
+impl From<&ThesisIntroduction> for SearchTerms {
+    fn from(intro: &ThesisIntroduction) -> Self {
+        Self {
+            title: text::terms([intro.title.as_str()]),
+            abstraction: text::terms([intro.abstraction.as_str()]),
+            keywords: text::terms(intro.keywords.iter().map(String::as_str)),
+        }
+    }
+}
+
 #[derive(Countable)]
 #[derive(Viewable)]
 #[derive(Serialize, Deserialize)]
@@ -84,6 +119,52 @@ pub(crate) struct Thesis {
     #[viewable]
     #[schemars(title = "Downloads", description = "Just count the release files.")]
     pub(crate) downloads: i32,
+    #[serde(default)]
+    pub(crate) search_terms: SearchTerms,
+    /// The number of the version committed last, see [`Thesis::claim_number`].
+    #[serde(default)]
+    pub(crate) numbering: Option<VersionNumber>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Default)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) struct VersionNumber {
+    pub(crate) major: i32,
+    pub(crate) minor: i32,
+}
+
+impl VersionNumber {
+    /// The number of the version to commit after `last`, with the counter of the thesis at
+    /// `counter`. The first version is 1.0, and a revision requested of `last` starts the next
+    /// round, unless a commit after it has started it already.
+    pub(crate) fn after(counter: Option<Self>, last: Option<&Version>) -> Self {
+        let counter = match (counter, last) {
+            (Some(counter), _) => counter,
+            (None, None) => return Self { major: 1, minor: 0 },
+            // Theses committed to before there was a counter pick up from their last version.
+            (None, Some(last)) => Self {
+                major: last.major_number,
+                minor: last.minor_number,
+            },
+        };
+        match last {
+            Some(last)
+                if last.state == VersionState::RevisionRequested
+                    && counter.major == last.major_number =>
+            {
+                Self {
+                    major: counter.major + 1,
+                    minor: 0,
+                }
+            }
+            _ => Self {
+                major: counter.major,
+                minor: counter.minor + 1,
+            },
+        }
+    }
 }
 
 #[async_trait]
@@ -100,8 +181,10 @@ impl OwnedContent for Thesis {
     }
 
     fn new(submitted: Self::Post) -> Self {
+        let intro: ThesisIntroduction = submitted.into();
         Self {
-            intro: submitted.into(),
+            search_terms: (&intro).into(),
+            intro,
             ..Self::default()
         }
     }
@@ -110,25 +193,105 @@ impl OwnedContent for Thesis {
         field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis))
     }
 
+    fn indexes() -> Indexes {
+        let title = field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(search_terms in Thesis).(title in SearchTerms));
+        let abstraction = field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(search_terms in Thesis).(abstraction in SearchTerms));
+        let keywords = field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(search_terms in Thesis).(keywords in SearchTerms));
+        let mut index = Index::new_with_text(title);
+        index.add_key_with_text(abstraction);
+        index.add_key_with_text(keywords);
+        Indexes::new().with(
+            index
+                .with_option(IndexOption::Weights(vec![
+                    (title.to_string(), 10),
+                    (keywords.to_string(), 5),
+                    (abstraction.to_string(), 1),
+                ]))
+                .with_option(IndexOption::Custom {
+                    name: "default_language".to_string(),
+                    value: "none".into(),
+                }),
+        )
+    }
+
+    fn derived(&self) -> MongoResult<Document> {
+        Ok(doc! {field!(search_terms in Thesis): bson::to_bson(&SearchTerms::from(&self.intro))?})
+    }
+
     async fn windup(
-        db: MongoDatabase,
+        tx: &mut Transaction,
         entity: &Entity<mongo::owned::Owned<Self>>,
     ) -> MongoResult<()> {
-        let mut found = <Entity<Attached<Version>>>::find(db.clone(), doc! { field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): entity._id}).await?;
+        let filter = doc! { field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): entity._id};
+        for version in <Entity<Attached<Version>>>::find_in(tx, filter.clone()).await? {
+            Version::windup(tx, &version).await?;
+        }
+        <Entity<Attached<Version>>>::delete_in(tx, filter).await.map(|_| ())
+    }
+
+    async fn file_ids(
+        db: MongoDatabase,
+        entity: &Entity<mongo::owned::Owned<Self>>,
+    ) -> MongoResult<Vec<ObjectId>> {
+        let mut found = <Entity<Attached<Version>>>::find(db, doc! { field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): entity._id}).await?;
+        let mut ids = Vec::new();
         while found.advance().await? {
-            let version = found.deserialize_current()?;
-            Version::windup(db.clone(), &version).await?;
+            let version = found.deserialize_current()?.data.content;
+            ids.push(version.release_id);
+            ids.extend(version.source_ids);
         }
-        Ok(())
+        Ok(ids)
     }
 }
 
 impl Thesis {
+    /// Derives the search terms of the theses stored before there were any, so that search finds
+    /// them too.
+    pub(crate) async fn backfill_search_terms(db: MongoDatabase) -> MongoResult<u64> {
+        <Entity<Owned<Self>>>::rederive(
+            db,
+            doc! {field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(search_terms in Thesis)): {Exists: false}},
+        )
+        .await
+    }
+
     pub(super) async fn pull_magazine_ids(
-        db: MongoDatabase,
+        tx: &mut Transaction,
         magazine_ids: ObjectId,
     ) -> MongoResult<(u64, u64)> {
-        <Entity<Owned<Self>>>::pull_sets(db, field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(magazine_ids in ThesisIntroduction)), magazine_ids).await
+        <Entity<Owned<Self>>>::pull_sets_in(tx, field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(magazine_ids in ThesisIntroduction)), magazine_ids).await
+    }
+
+    /// Claims the number of the version to commit after `last`, see [`VersionNumber::after`].
+    /// The counter is only moved on from where it was read, so that commits racing each other
+    /// get different numbers.
+    pub(crate) async fn claim_number(
+        db: MongoDatabase,
+        thesis_id: ObjectId,
+        last: Option<&Version>,
+    ) -> MongoResult<Option<VersionNumber>> {
+        let numbering = field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(numbering in Thesis));
+        loop {
+            let Some(thesis) =
+                <Entity<Owned<Thesis>>>::try_find_one_by_id(db.clone(), thesis_id).await?
+            else {
+                return Ok(None);
+            };
+            let counter = thesis.data.content.numbering;
+            let number = VersionNumber::after(counter, last);
+            let unchanged = doc! {field!(_id in Entity<Owned<Thesis>>): thesis_id, numbering: bson::to_bson(&counter)?};
+            let update = Update {
+                set: doc! {numbering: bson::to_bson(&number)?},
+                ..Default::default()
+            };
+            // Otherwise a commit racing this one has claimed a number meanwhile.
+            if <Entity<Owned<Thesis>>>::try_find_one_and_update(db.clone(), unchanged, update)
+                .await?
+                .is_some()
+            {
+                return Ok(Some(number));
+            }
+        }
     }
 
     pub(crate) async fn commit(
@@ -137,22 +300,379 @@ impl Thesis {
         thesis_id: ObjectId,
         release_id: ObjectId,
         source_ids: Vec<ObjectId>,
-    ) -> MongoResult<Option<ObjectId>> {
-        let last_version = <Entity<Attached<Version>>>::find_peak(db.clone(), doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): thesis_id}, doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(major_number in Version)): -1, field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(minor_number in Version)): -1}).await?.deserialize_current()?;
-        <Entity<Attached<Version>>>::insert_one(
-            db,
-            Attached {
-                creator_id: Some(committer_id),
-                content: Version {
-                    thesis_id,
-                    release_id,
-                    source_ids,
-                    major_number: last_version.data.content.major_number,
-                    minor_number: last_version.data.content.minor_number + 1,
-                    ..Default::default()
+        pdf: Option<PdfInfo>,
+    ) -> Result<Option<ObjectId>, TransitionError> {
+        let mut found = <Entity<Attached<Version>>>::find_peak(db.clone(), doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): thesis_id}, doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(major_number in Version)): -1, field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(minor_number in Version)): -1}).await?;
+        let last_version = if found.advance().await? {
+            Some(found.deserialize_current()?)
+        } else {
+            None
+        };
+        let last = last_version.as_ref().map(|version| &version.data.content);
+        let mut attempts = 0;
+        let (version_id, number) = loop {
+            let Some(number) = Self::claim_number(db.clone(), thesis_id, last).await? else {
+                return Ok(None);
+            };
+            let version = Version {
+                thesis_id,
+                release_id,
+                source_ids: source_ids.clone(),
+                pdf: pdf.clone(),
+                major_number: number.major,
+                minor_number: number.minor,
+                ..Default::default()
+            };
+            let inserted = <Entity<Attached<Version>>>::insert_one(
+                db.clone(),
+                Attached {
+                    creator_id: Some(committer_id),
+                    content: version,
                 },
+            )
+            .await;
+            attempts += 1;
+            match inserted {
+                // The counter is behind the versions there are, so claim the next number.
+                Err(e) if is_duplicate_key(&e) && attempts < COMMIT_ATTEMPTS => continue,
+                inserted => break (inserted?, number),
+            }
+        };
+        if version_id.is_some() {
+            Self::supersede_below(db, committer_id, thesis_id, number).await?;
+        }
+        Ok(version_id)
+    }
+
+    /// Supersedes the versions of the thesis `thesis_id` numbered below `number` which are
+    /// still undecided, those the last version known to a commit racing this one included.
+    async fn supersede_below(
+        db: MongoDatabase,
+        committer_id: ObjectId,
+        thesis_id: ObjectId,
+        number: VersionNumber,
+    ) -> Result<(), TransitionError> {
+        let major = field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(major_number in Version));
+        let minor = field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(minor_number in Version));
+        let state = field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(state in Version));
+        let open_states = [VersionState::Uploaded, VersionState::RevisionRequested]
+            .iter()
+            .map(bson::to_bson)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(mongo::MongoError::from)?;
+        let undecided = doc! {
+            field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): thesis_id,
+            Or: [
+                {major: {LesserThan: number.major}},
+                {major: number.major, minor: {LesserThan: number.minor}},
+            ],
+            And: [{Or: [
+                {state: {In: open_states}},
+                {format!("{}.Reviewing", state): {Exists: true}},
+            ]}],
+        };
+        // A version whose state changes meanwhile is looked at again in the next pass.
+        for _ in 0..COMMIT_ATTEMPTS {
+            let mut found = <Entity<Attached<Version>>>::find(db.clone(), undecided.clone()).await?;
+            let mut versions = Vec::new();
+            while found.advance().await? {
+                versions.push(found.deserialize_current()?);
+            }
+            if versions.is_empty() {
+                break;
+            }
+            for version in &versions {
+                let superseded = Version::transit(
+                    db.clone(),
+                    version,
+                    Action::Supersede,
+                    Some(committer_id),
+                    Update::default(),
+                )
+                .await;
+                match superseded {
+                    Ok(_) | Err(TransitionError::Illegal { .. } | TransitionError::Conflict) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Those but the numbering rules need a MongoDB server, at `TEST_MONGO_URL` or on localhost,
+/// and each works in a database of its own which is dropped afterwards. Run them with
+/// `cargo test -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use mongo::{owned::Owned, MongoClient};
+
+    use super::*;
+    use crate::mongo_entities::sync_all_indexes;
+
+    async fn test_db() -> MongoDatabase {
+        let url = std::env::var("TEST_MONGO_URL")
+            .unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
+        let client = MongoClient::with_uri_str(&url).await.unwrap();
+        let db = client.database(&format!("prepublish_test_{}", ObjectId::new()));
+        sync_all_indexes(&db).await.unwrap();
+        db
+    }
+
+    async fn new_thesis(db: &MongoDatabase) -> ObjectId {
+        <Entity<Owned<Thesis>>>::insert_one(
+            db.clone(),
+            Owned {
+                owner_id: ObjectId::new(),
+                is_public: false,
+                content: Thesis::default(),
             },
         )
         .await
+        .unwrap()
+        .unwrap()
+    }
+
+    async fn commit(db: &MongoDatabase, thesis_id: ObjectId) -> Entity<Attached<Version>> {
+        let id = Thesis::commit(
+            db.clone(),
+            ObjectId::new(),
+            thesis_id,
+            ObjectId::new(),
+            Vec::new(),
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        version(db, id).await
+    }
+
+    async fn version(db: &MongoDatabase, id: ObjectId) -> Entity<Attached<Version>> {
+        <Entity<Attached<Version>>>::try_find_one_by_id(db.clone(), id)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    fn number(version: &Entity<Attached<Version>>) -> (i32, i32) {
+        let version = &version.data.content;
+        (version.major_number, version.minor_number)
+    }
+
+    async fn request_revision(db: &MongoDatabase, version: &Entity<Attached<Version>>) {
+        let action = Action::RequestRevision;
+        Version::transit(db.clone(), version, action, None, Update::default())
+            .await
+            .unwrap();
+    }
+
+    fn last(major: i32, minor: i32, state: VersionState) -> Version {
+        Version {
+            major_number: major,
+            minor_number: minor,
+            state,
+            ..Default::default()
+        }
+    }
+
+    /// [`VersionNumber::after`] in pairs of numbers.
+    fn after(counter: Option<(i32, i32)>, last: Option<&Version>) -> (i32, i32) {
+        let counter = counter.map(|(major, minor)| VersionNumber { major, minor });
+        let number = VersionNumber::after(counter, last);
+        (number.major, number.minor)
+    }
+
+    #[test]
+    fn numbering_starts_at_one() {
+        assert_eq!(after(None, None), (1, 0));
+        // Another first commit got there before.
+        assert_eq!(after(Some((1, 0)), None), (1, 1));
+    }
+
+    #[test]
+    fn minor_numbers_go_up_within_a_round() {
+        let uploaded = last(1, 2, VersionState::Uploaded);
+        assert_eq!(after(Some((1, 2)), Some(&uploaded)), (1, 3));
+        // Commits racing this one have claimed numbers already.
+        assert_eq!(after(Some((1, 4)), Some(&uploaded)), (1, 5));
+        let withdrawn = last(2, 0, VersionState::Withdrawn);
+        assert_eq!(after(Some((2, 0)), Some(&withdrawn)), (2, 1));
+    }
+
+    #[test]
+    fn revisions_start_the_next_round_once() {
+        let revised = last(1, 3, VersionState::RevisionRequested);
+        assert_eq!(after(Some((1, 3)), Some(&revised)), (2, 0));
+        // A commit racing this one has started the round.
+        assert_eq!(after(Some((2, 0)), Some(&revised)), (2, 1));
+    }
+
+    #[test]
+    fn theses_without_a_counter_pick_up_from_their_last_version() {
+        let uploaded = last(3, 1, VersionState::Uploaded);
+        assert_eq!(after(None, Some(&uploaded)), (3, 2));
+        let revised = last(3, 1, VersionState::RevisionRequested);
+        assert_eq!(after(None, Some(&revised)), (4, 0));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MongoDB server"]
+    async fn first_commit() {
+        let db = test_db().await;
+        let thesis_id = new_thesis(&db).await;
+        let first = commit(&db, thesis_id).await;
+        assert_eq!(number(&first), (1, 0));
+        assert_eq!(first.data.content.state, VersionState::Uploaded);
+        let second = commit(&db, thesis_id).await;
+        assert_eq!(number(&second), (1, 1));
+        let first = version(&db, first._id).await;
+        assert_eq!(first.data.content.state, VersionState::Superseded);
+        assert_eq!(first.data.content.transitions.len(), 1);
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MongoDB server"]
+    async fn commit_to_missing_thesis() {
+        let db = test_db().await;
+        let committed = Thesis::commit(
+            db.clone(),
+            ObjectId::new(),
+            ObjectId::new(),
+            ObjectId::new(),
+            Vec::new(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(committed.is_none());
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "needs a MongoDB server"]
+    async fn concurrent_commits() {
+        let db = test_db().await;
+        let thesis_id = new_thesis(&db).await;
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move { number(&commit(&db, thesis_id).await) })
+            })
+            .collect();
+        let mut numbers = Vec::new();
+        for task in tasks {
+            numbers.push(task.await.unwrap());
+        }
+        numbers.sort();
+        assert_eq!(numbers, (0..8).map(|minor| (1, minor)).collect::<Vec<_>>());
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MongoDB server"]
+    async fn numbering_across_rounds() {
+        let db = test_db().await;
+        let thesis_id = new_thesis(&db).await;
+        commit(&db, thesis_id).await;
+        let revised = commit(&db, thesis_id).await;
+        request_revision(&db, &revised).await;
+        let next_round = commit(&db, thesis_id).await;
+        assert_eq!(number(&next_round), (2, 0));
+        let revised = version(&db, revised._id).await;
+        assert_eq!(revised.data.content.state, VersionState::History);
+        assert_eq!(number(&commit(&db, thesis_id).await), (2, 1));
+        let rounds = Version::rounds(db.clone(), thesis_id).await.unwrap();
+        let sizes: Vec<_> = rounds
+            .iter()
+            .map(|round| (round.major_number, round.versions.len()))
+            .collect();
+        assert_eq!(sizes, [(1, 2), (2, 2)]);
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "needs a MongoDB server"]
+    async fn concurrent_commits_leave_only_the_last_undecided() {
+        let db = test_db().await;
+        let thesis_id = new_thesis(&db).await;
+        commit(&db, thesis_id).await;
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move { commit(&db, thesis_id).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let rounds = Version::rounds(db.clone(), thesis_id).await.unwrap();
+        let states: Vec<_> = rounds[0]
+            .versions
+            .iter()
+            .map(|version| version.data.content.state.clone())
+            .collect();
+        let mut expected = vec![VersionState::Superseded; 8];
+        expected.push(VersionState::Uploaded);
+        assert_eq!(states, expected);
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "needs a MongoDB server"]
+    async fn concurrent_commits_after_revision() {
+        let db = test_db().await;
+        let thesis_id = new_thesis(&db).await;
+        let revised = commit(&db, thesis_id).await;
+        request_revision(&db, &revised).await;
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move { number(&commit(&db, thesis_id).await) })
+            })
+            .collect();
+        let mut numbers = Vec::new();
+        for task in tasks {
+            numbers.push(task.await.unwrap());
+        }
+        numbers.sort();
+        assert_eq!(numbers, [(2, 0), (2, 1), (2, 2), (2, 3)]);
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MongoDB server"]
+    async fn counter_behind_versions() {
+        let db = test_db().await;
+        let thesis_id = new_thesis(&db).await;
+        for minor in 0..3 {
+            let version = Version {
+                thesis_id,
+                major_number: 1,
+                minor_number: minor,
+                ..Default::default()
+            };
+            <Entity<Attached<Version>>>::insert_one(
+                db.clone(),
+                Attached {
+                    creator_id: None,
+                    content: version,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        // As if the counter had been reset, so that the next numbers are taken already.
+        let numbering = field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(numbering in Thesis));
+        let update = Update {
+            set: doc! {numbering: bson::to_bson(&VersionNumber { major: 1, minor: 0 }).unwrap()},
+            ..Default::default()
+        };
+        <Entity<Owned<Thesis>>>::try_find_one_and_update_by_id(db.clone(), thesis_id, update)
+            .await
+            .unwrap();
+        assert_eq!(number(&commit(&db, thesis_id).await), (1, 3));
+        db.drop(None).await.unwrap();
     }
 }
@@ -0,0 +1,198 @@
+//! Who may know who reviews a version, and who wrote it.
+//!
+//! Only what is stored about a version or a thesis is redacted. Its files are served as they
+//! were uploaded, so the metadata of a release, such as the author in the Info dictionary of a
+//! PDF, is left for the authors to take out before a double-blind review.
+
+use mongo::{
+    attached::Attached,
+    bson::{self, Document},
+    entity::{doc, field, operator::*, Entity},
+    oid::ObjectId,
+    owned::Owned,
+    MongoDatabase, MongoResult,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    super::{
+        paper_collection::{magazine::Magazine, PaperCollection},
+        review::Review,
+        thesis::{Thesis, ThesisIntroduction},
+    },
+    Version, VersionState,
+};
+
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Default)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum ReviewAnonymity {
+    /// Whoever sees a version or a review sees who wrote it.
+    #[default]
+    Open,
+    /// Only the editors know who the reviewers are.
+    SingleBlind,
+    /// Besides, only the editors and the authors know who the authors are.
+    DoubleBlind,
+}
+
+/// Where someone stands to a version, as far as anonymity goes.
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum Viewer {
+    /// An editor of the thesis, who sees everything.
+    Editor,
+    /// An author or the owner of the thesis.
+    Author(ObjectId),
+    /// Anyone else, a reviewer or not, logged in or not.
+    Other(Option<ObjectId>),
+}
+
+/// Stands for an owner kept from whoever views what they own.
+pub(crate) const HIDDEN_ID: ObjectId = ObjectId::from_bytes([0; 12]);
+
+impl Viewer {
+    fn id(self) -> Option<ObjectId> {
+        match self {
+            Viewer::Editor => None,
+            Viewer::Author(id) => Some(id),
+            Viewer::Other(id) => id,
+        }
+    }
+}
+
+impl ReviewAnonymity {
+    fn hides_reviewers_from(self, viewer: Viewer) -> bool {
+        self >= ReviewAnonymity::SingleBlind && viewer != Viewer::Editor
+    }
+
+    fn hides_authors_from(self, viewer: Viewer) -> bool {
+        self == ReviewAnonymity::DoubleBlind && matches!(viewer, Viewer::Other(_))
+    }
+
+    /// Takes out of `version` whom `viewer` may not know, leaving them only themselves.
+    pub(crate) fn redact_version(self, viewer: Viewer, version: &mut Attached<Version>) {
+        let me = viewer.id();
+        let hides_reviewers = self.hides_reviewers_from(viewer);
+        let hides_authors = self.hides_authors_from(viewer);
+        if hides_reviewers {
+            if let VersionState::Reviewing(reviewing) = &mut version.content.state {
+                reviewing.remainder_ids.retain(|&id| Some(id) == me);
+            }
+        }
+        if hides_authors {
+            version.creator_id = None;
+            if let Some(pdf) = &mut version.content.pdf {
+                pdf.author = None;
+            }
+        }
+        for transition in &mut version.content.transitions {
+            if transition.by != me
+                && (hides_reviewers && transition.is_by_reviewer()
+                    || hides_authors && transition.is_by_author())
+            {
+                transition.by = None;
+            }
+        }
+    }
+
+    /// Takes the authors and the owner out of `thesis`, unless `viewer` may know them.
+    pub(crate) fn redact_thesis(self, viewer: Viewer, thesis: &mut Owned<Thesis>) {
+        if self.hides_authors_from(viewer) {
+            thesis.owner_id = HIDDEN_ID;
+            thesis.content.intro.author_ids.clear();
+        }
+    }
+
+    /// Takes the reviewer out of `review`, unless `viewer` may know or is them.
+    pub(crate) fn redact_review(self, viewer: Viewer, review: &mut Attached<Review>) {
+        if self.hides_reviewers_from(viewer) && review.creator_id != viewer.id() {
+            review.creator_id = None;
+        }
+    }
+}
+
+impl Version {
+    /// How anonymous the review of this version of `thesis` is: as set for the version, or else
+    /// the strictest setting of the magazines of the thesis.
+    pub(crate) async fn anonymity(
+        &self,
+        db: MongoDatabase,
+        thesis: &Entity<Owned<Thesis>>,
+    ) -> MongoResult<ReviewAnonymity> {
+        match self.anonymity {
+            Some(anonymity) => Ok(anonymity),
+            None => Thesis::magazine_anonymity(db, thesis).await,
+        }
+    }
+}
+
+impl Thesis {
+    /// The strictest review anonymity of the magazines of `thesis`.
+    async fn magazine_anonymity(
+        db: MongoDatabase,
+        thesis: &Entity<Owned<Thesis>>,
+    ) -> MongoResult<ReviewAnonymity> {
+        Ok(
+            Magazine::find_by_ids(db, &thesis.data.content.intro.magazine_ids)
+                .await?
+                .into_iter()
+                .map(|magazine| magazine.data.content.detail.review_anonymity)
+                .max()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// How anonymous the authors of `thesis` are: as the strictest review of any of its
+    /// versions.
+    pub(crate) async fn anonymity(
+        db: MongoDatabase,
+        thesis: &Entity<Owned<Thesis>>,
+    ) -> MongoResult<ReviewAnonymity> {
+        let mut anonymity = Self::magazine_anonymity(db.clone(), thesis).await?;
+        let mut versions = <Entity<Attached<Version>>>::find(
+            db,
+            doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): thesis._id},
+        )
+        .await?;
+        while versions.advance().await? {
+            if let Some(set) = versions.deserialize_current()?.data.content.anonymity {
+                anonymity = anonymity.max(set);
+            }
+        }
+        Ok(anonymity)
+    }
+
+    /// A filter for the theses whose authors no review of theirs keeps from anyone, that is,
+    /// those neither filed in a double-blind magazine nor with a double-blind version.
+    pub(crate) async fn authors_shown(db: MongoDatabase) -> MongoResult<Document> {
+        let double_blind = bson::to_bson(&ReviewAnonymity::DoubleBlind)?;
+        let mut found = <Entity<Owned<PaperCollection<Magazine>>>>::find(
+            db.clone(),
+            doc! {format!("{}.{}", field!((data in Entity<Owned<PaperCollection<Magazine>>>).(content in Owned<PaperCollection<Magazine>>)), field!(review_anonymity in Magazine)): double_blind.clone()},
+        )
+        .await?;
+        let mut magazine_ids = Vec::new();
+        while found.advance().await? {
+            magazine_ids.push(found.deserialize_current()?._id);
+        }
+        let mut found = <Entity<Attached<Version>>>::find(
+            db,
+            doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(anonymity in Version)): double_blind},
+        )
+        .await?;
+        let mut thesis_ids = Vec::new();
+        while found.advance().await? {
+            thesis_ids.push(found.deserialize_current()?.data.content.thesis_id);
+        }
+        Ok(doc! {
+            field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(magazine_ids in ThesisIntroduction)): {NoneIn: magazine_ids},
+            field!(_id in Entity<Owned<Thesis>>): {NoneIn: thesis_ids},
+        })
+    }
+}
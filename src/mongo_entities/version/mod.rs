@@ -0,0 +1,313 @@
+pub(crate) mod anonymity;
+pub(crate) mod workflow;
+
+use std::collections::{BTreeSet, HashMap};
+
+use async_trait::async_trait;
+use crud::Countable;
+use crud_derive::{Countable, Viewable};
+use mongo::{
+    attached::{Attached, AttachedContent},
+    entity::{doc, field, operator::*, Entity, Index, IndexOption, Indexes},
+    oid::{ObjectId, ObjectIdDef},
+    owned::Owned,
+    transaction::Transaction,
+    MongoDatabase, MongoResult,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use self::{anonymity::ReviewAnonymity, workflow::Transition};
+use super::{
+    review::{
+        form::{ReviewForm, ReviewSummary},
+        Review,
+    },
+    thesis::Thesis,
+};
+use crate::pdf::{Exhausted, Pdf};
+
+#[derive(Viewable)]
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) enum ReviewPattern {
+    #[default]
+    Editor,
+    Reviewer,
+}
+
+#[derive(Countable)]
+#[derive(Viewable)]
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct Reviewing {
+    #[viewable(serialize_with = "oid::serialize_object_id_collection_as_hex_string")]
+    #[schemars(
+        title = "Review IDs",
+        description = "Could not repeat.",
+        with = "BTreeSet<ObjectIdDef>"
+    )]
+    pub(crate) remainder_ids: BTreeSet<ObjectId>,
+    #[viewable(into)]
+    pub(crate) pattern: ReviewPattern,
+}
+
+#[derive(Viewable)]
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) enum VersionState {
+    #[default]
+    Uploaded,
+    Reviewing(#[viewable(into)] Reviewing),
+    Passed(#[viewable] bool),
+    /// The review round is over, and the authors are to commit a revision.
+    RevisionRequested,
+    Withdrawn,
+    /// A newer version was committed before this one was decided.
+    Superseded,
+    /// A version of an earlier review round.
+    History,
+}
+
+/// What a PDF release says about itself.
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct PdfInfo {
+    #[schemars(title = "Pages")]
+    pub(crate) pages: i32,
+    #[schemars(
+        title = "Title",
+        description = "From the document information, which may differ from the first page."
+    )]
+    pub(crate) title: Option<String>,
+    #[schemars(title = "Author")]
+    pub(crate) author: Option<String>,
+    #[schemars(title = "Fonts", description = "Without the tags of subsets.")]
+    pub(crate) fonts: BTreeSet<String>,
+}
+
+impl PdfInfo {
+    /// Reads `content`, or nothing if it is not a PDF whose pages can be counted.
+    pub(crate) fn read(content: &[u8]) -> Result<Option<Self>, Exhausted> {
+        let Some(pdf) = Pdf::parse(content) else {
+            return Ok(None);
+        };
+        let info = pdf.page_count().and_then(|pages| {
+            Some(Self {
+                pages: pages.try_into().ok()?,
+                title: pdf.title(),
+                author: pdf.author(),
+                fonts: pdf.fonts(),
+            })
+        });
+        if pdf.exhausted() {
+            return Err(Exhausted);
+        }
+        Ok(info)
+    }
+}
+
+#[derive(Countable)]
+#[derive(Viewable)]
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct Version {
+    #[viewable(serialize_with = "oid::serialize_object_id_as_hex_string")]
+    #[schemars(title = "Thesis ID", with = "ObjectIdDef")]
+    pub(crate) thesis_id: ObjectId,
+    #[viewable(serialize_with = "oid::serialize_object_id_as_hex_string")]
+    #[schemars(
+        title = "Release File ID",
+        description = "Of a type allowed for releases, which is PDF unless configured otherwise.",
+        with = "ObjectIdDef"
+    )]
+    pub(crate) release_id: ObjectId,
+    #[viewable(serialize_with = "oid::serialize_object_id_as_hex_string")]
+    #[schemars(
+        title = "Source File IDs",
+        description = "file_0.tex, file_1.zip, file_2.tar.gz... of the types allowed for sources.",
+        with = "Vec<ObjectIdDef>"
+    )]
+    pub(crate) source_ids: Vec<ObjectId>,
+    #[viewable]
+    #[serde(default)]
+    #[schemars(
+        title = "PDF Information",
+        description = "Read from the release on commit, unless it is no readable PDF."
+    )]
+    pub(crate) pdf: Option<PdfInfo>,
+    #[viewable]
+    #[schemars(
+        title = "Major Number.",
+        description = "The review round, a new one after a revision is requested."
+    )]
+    pub(crate) major_number: i32,
+    #[viewable]
+    #[schemars(title = "Minor Number.", description = "A little improvement within the round.")]
+    pub(crate) minor_number: i32,
+    #[viewable(into)]
+    pub(crate) state: VersionState,
+    #[viewable]
+    #[serde(default)]
+    #[schemars(
+        title = "Review Anonymity",
+        description = "Set by the editor on assigning, otherwise that of the magazines."
+    )]
+    pub(crate) anonymity: Option<ReviewAnonymity>,
+    #[viewable]
+    #[serde(default)]
+    #[schemars(
+        title = "Review Form",
+        description = "Taken from the magazine on assigning, to be filled in by every review."
+    )]
+    pub(crate) review_form: Option<ReviewForm>,
+    #[viewable]
+    #[serde(default)]
+    #[schemars(
+        title = "Review Summary",
+        description = "What the reviews so far add up to on the review form."
+    )]
+    pub(crate) review_summary: Option<ReviewSummary>,
+    #[viewable]
+    #[serde(default)]
+    #[schemars(title = "Transitions", description = "Every change of state, oldest first.")]
+    pub(crate) transitions: Vec<Transition>,
+    #[viewable(serialize_with = "oid::serialize_object_id_collection_as_hex_string")]
+    #[schemars(
+        title = "Review IDs",
+        description = "Could not repeat.",
+        with = "BTreeSet<ObjectIdDef>"
+    )]
+    pub(crate) review_ids: BTreeSet<ObjectId>,
+    #[viewable]
+    #[schemars(title = "Downloads", description = "Just count the release.")]
+    pub(crate) downloads: i32,
+}
+
+#[async_trait]
+impl AttachedContent for Version {
+    fn collection_name() -> &'static str {
+        Self::plural()
+    }
+
+    fn schema_name() -> &'static str {
+        Self::singular()
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new().with(Index::new(field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version))).with_key(field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(major_number in Version))).with_key(field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(minor_number in Version))).with_option(IndexOption::Unique))
+            .with(Index::new(field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(release_id in Version))))
+            .with(Index::new(field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(source_ids in Version))))
+    }
+
+    async fn windup(tx: &mut Transaction, entity: &Entity<Attached<Self>>) -> MongoResult<()> {
+        <Entity<Attached<Review>>>::delete_in(tx, doc! {field!((data in Entity<Attached<Review>>).(content in Attached<Review>).(version_id in Review)): entity._id}).await.map(|_|())
+    }
+}
+
+impl Version {
+    pub(crate) async fn thesis(
+        &self,
+        db: MongoDatabase,
+    ) -> MongoResult<Option<Entity<Owned<Thesis>>>> {
+        <Entity<Owned<Thesis>>>::try_find_one_by_id(db, self.thesis_id).await
+    }
+
+    pub(crate) async fn downloads(
+        db: MongoDatabase,
+        model: &Entity<Attached<Version>>,
+    ) -> MongoResult<Option<Entity<Attached<Version>>>> {
+        if let Some(thesis) =
+            <Entity<Owned<Thesis>>>::try_find_one_by_id(db.clone(), model.data.content.thesis_id)
+                .await?
+        {
+            <Entity<Owned<Thesis>>>::try_find_one_and_update_by_id(db.clone(), thesis._id, mongo::entity::update::Update { inc: doc! {field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(downloads in Thesis)): 1}, ..Default::default() }).await?;
+            <Entity<Attached<Version>>>::try_find_one_and_update_by_id(db, model._id, mongo::entity::update::Update { inc: doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(downloads in Version)): 1}, ..Default::default() }).await
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Whether some version of the thesis `thesis_id` passed review and was published, which
+    /// makes its earlier rounds public too.
+    pub(crate) async fn published(db: MongoDatabase, thesis_id: ObjectId) -> MongoResult<bool> {
+        Ok(<Entity<Attached<Version>>>::try_find_one(
+            db,
+            doc! {
+                field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): thesis_id,
+                format!("{}.Passed", field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(state in Version))): true,
+            },
+        )
+        .await?
+        .is_some())
+    }
+
+    /// The versions of the thesis `thesis_id` with their reviews, round by round.
+    pub(crate) async fn rounds(db: MongoDatabase, thesis_id: ObjectId) -> MongoResult<Vec<Round>> {
+        let mut found = <Entity<Attached<Version>>>::find_peak(db.clone(), doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): thesis_id}, doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(major_number in Version)): 1, field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(minor_number in Version)): 1}).await?;
+        let mut versions = Vec::new();
+        while found.advance().await? {
+            versions.push(found.deserialize_current()?);
+        }
+        let review_ids: Vec<_> = versions
+            .iter()
+            .flat_map(|version| version.data.content.review_ids.iter().copied())
+            .collect();
+        let mut found = <Entity<Attached<Review>>>::find(db, doc! {field!(_id in Entity<Attached<Review>>): {In: review_ids}}).await?;
+        let mut reviews = HashMap::new();
+        while found.advance().await? {
+            let review = found.deserialize_current()?;
+            reviews.insert(review._id, review);
+        }
+        let mut rounds: Vec<Round> = Vec::new();
+        for version in versions {
+            let major_number = version.data.content.major_number;
+            if rounds.last().is_none_or(|round| round.major_number != major_number) {
+                rounds.push(Round {
+                    major_number,
+                    versions: Vec::new(),
+                    reviews: Vec::new(),
+                });
+            }
+            let round = rounds.last_mut().expect("a round was just pushed");
+            let mut version_reviews: Vec<_> = version
+                .data
+                .content
+                .review_ids
+                .iter()
+                .filter_map(|id| reviews.remove(id))
+                .collect();
+            version_reviews.sort_by_key(|review| review.created_at);
+            round.reviews.extend(version_reviews);
+            round.versions.push(version);
+        }
+        Ok(rounds)
+    }
+}
+
+/// A round of review, which ends when a revision is requested.
+pub(crate) struct Round {
+    pub(crate) major_number: i32,
+    /// Oldest first.
+    pub(crate) versions: Vec<Entity<Attached<Version>>>,
+    /// Of all the versions in the round, version by version.
+    pub(crate) reviews: Vec<Entity<Attached<Review>>>,
+}
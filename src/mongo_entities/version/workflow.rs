@@ -0,0 +1,325 @@
+//! Where a version can go from each state, and the log of where it has been.
+//!
+//! Every change of [`VersionState`] goes through [`Version::transit`], which only takes the
+//! transitions [`VersionState::next`] allows.
+
+use chrono::{DateTime, Utc};
+use mongo::{
+    attached::Attached,
+    bson::to_bson,
+    entity::{doc, field, update::Update, Entity},
+    oid::{ObjectId, ObjectIdDef},
+    MongoDatabase, MongoError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{ReviewPattern, Reviewing, Version, VersionState};
+
+/// What moves a version from one state to another.
+#[derive(Debug)]
+pub(crate) enum Action {
+    /// An editor sends an uploaded version out to reviewers.
+    Assign(Reviewing),
+    /// A reviewer hands a review in. `approve` is whether it and every review before it are
+    /// for passing, which decides the version once the last reviewer of a
+    /// [`ReviewPattern::Reviewer`] round is done.
+    Review {
+        reviewer_id: ObjectId,
+        approve: bool,
+    },
+    /// An editor passes or rejects a version.
+    Adjudge(bool),
+    /// An editor closes the review round, asking the authors for a revision.
+    RequestRevision,
+    /// The authors take the version back.
+    Withdraw,
+    /// A newer version of the thesis is committed.
+    Supersede,
+}
+
+impl Action {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Action::Assign(_) => "assign",
+            Action::Review { .. } => "review",
+            Action::Adjudge(_) => "adjudge",
+            Action::RequestRevision => "request revision",
+            Action::Withdraw => "withdraw",
+            Action::Supersede => "supersede",
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum TransitionError {
+    #[error("cannot {action}: the version is {state}")]
+    Illegal {
+        action: &'static str,
+        state: &'static str,
+    },
+    #[error("the version has been changed meanwhile, try again")]
+    Conflict,
+    #[error("MongoDB error: {0}")]
+    Mongo(#[from] MongoError),
+}
+
+impl VersionState {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            VersionState::Uploaded => "uploaded",
+            VersionState::Reviewing(_) => "under review",
+            VersionState::Passed(true) => "passed",
+            VersionState::Passed(false) => "rejected",
+            VersionState::RevisionRequested => "waiting for a revision",
+            VersionState::Withdrawn => "withdrawn",
+            VersionState::Superseded => "superseded",
+            VersionState::History => "history",
+        }
+    }
+
+    /// The state `action` moves a version in this state to, if it may.
+    pub(crate) fn next(&self, action: &Action) -> Result<Self, TransitionError> {
+        use VersionState::*;
+        Ok(match (self, action) {
+            (Uploaded, Action::Assign(reviewing)) => Reviewing(reviewing.clone()),
+            (
+                Reviewing(reviewing),
+                Action::Review {
+                    reviewer_id,
+                    approve,
+                },
+            ) if reviewing.remainder_ids.contains(reviewer_id) => {
+                let mut reviewing = reviewing.clone();
+                reviewing.remainder_ids.remove(reviewer_id);
+                match reviewing.pattern {
+                    ReviewPattern::Reviewer if reviewing.remainder_ids.is_empty() => {
+                        Passed(*approve)
+                    }
+                    _ => Reviewing(reviewing),
+                }
+            }
+            (Uploaded, Action::Adjudge(judgement)) => Passed(*judgement),
+            (Reviewing(reviewing), Action::Adjudge(judgement))
+                if reviewing.pattern == ReviewPattern::Editor
+                    && reviewing.remainder_ids.is_empty() =>
+            {
+                Passed(*judgement)
+            }
+            (Uploaded | Reviewing(_), Action::RequestRevision) => RevisionRequested,
+            (Uploaded | Reviewing(_) | RevisionRequested, Action::Withdraw) => Withdrawn,
+            (Uploaded | Reviewing(_), Action::Supersede) => Superseded,
+            // The revision asked for has come, so the round is over.
+            (RevisionRequested, Action::Supersede) => History,
+            _ => {
+                return Err(TransitionError::Illegal {
+                    action: action.name(),
+                    state: self.name(),
+                })
+            }
+        })
+    }
+}
+
+/// A change of state in the log of a version.
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct Transition {
+    #[schemars(title = "From")]
+    pub(crate) from: String,
+    #[schemars(title = "To")]
+    pub(crate) to: String,
+    #[schemars(title = "Action")]
+    pub(crate) action: String,
+    #[schemars(
+        title = "By",
+        description = "Nobody if the system did it, or if the review anonymity hides them.",
+        with = "Option<ObjectIdDef>"
+    )]
+    pub(crate) by: Option<ObjectId>,
+    #[schemars(title = "At")]
+    pub(crate) at: DateTime<Utc>,
+}
+
+impl Transition {
+    pub(crate) fn is_by_reviewer(&self) -> bool {
+        self.action == "review"
+    }
+
+    /// Whether an author did it, who withdraws a version or commits a newer one.
+    pub(crate) fn is_by_author(&self) -> bool {
+        self.action == "withdraw" || self.action == "supersede"
+    }
+}
+
+impl Version {
+    /// Moves `version` on by `action` of `actor_id`, along with whatever else `update` does, and
+    /// logs it. Fails if the version has moved on since it was read.
+    pub(crate) async fn transit(
+        db: MongoDatabase,
+        version: &Entity<Attached<Version>>,
+        action: Action,
+        actor_id: Option<ObjectId>,
+        mut update: Update,
+    ) -> Result<Entity<Attached<Version>>, TransitionError> {
+        let state = &version.data.content.state;
+        let next = state.next(&action)?;
+        let transition = Transition {
+            from: state.name().to_string(),
+            to: next.name().to_string(),
+            action: action.name().to_string(),
+            by: actor_id,
+            at: Utc::now(),
+        };
+        let state_path = field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(state in Version));
+        update
+            .set
+            .insert(state_path, to_bson(&next).map_err(MongoError::from)?);
+        update.push.insert(
+            field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(transitions in Version)),
+            to_bson(&transition).map_err(MongoError::from)?,
+        );
+        let unchanged = doc! {
+            field!(_id in Entity<Attached<Version>>): version._id,
+            state_path: to_bson(state).map_err(MongoError::from)?,
+        };
+        <Entity<Attached<Version>>>::try_find_one_and_update(db, unchanged, update)
+            .await?
+            .ok_or(TransitionError::Conflict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    fn reviewing(pattern: ReviewPattern, remainder_ids: &[ObjectId]) -> VersionState {
+        VersionState::Reviewing(Reviewing {
+            remainder_ids: remainder_ids.iter().copied().collect::<BTreeSet<_>>(),
+            pattern,
+        })
+    }
+
+    #[test]
+    fn every_state_goes_only_where_it_may() {
+        use ReviewPattern::{Editor, Reviewer};
+        use VersionState::*;
+        let (me, other, stranger) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let states = [
+            Uploaded,
+            reviewing(Editor, &[me, other]),
+            reviewing(Editor, &[]),
+            reviewing(Reviewer, &[me]),
+            reviewing(Reviewer, &[me, other]),
+            Passed(true),
+            Passed(false),
+            RevisionRequested,
+            Withdrawn,
+            Superseded,
+            History,
+        ];
+        let review = |reviewer_id, approve| Action::Review {
+            reviewer_id,
+            approve,
+        };
+        let assigned = || match reviewing(Editor, &[me, other]) {
+            Reviewing(reviewing) => reviewing,
+            _ => unreachable!(),
+        };
+        let actions = || {
+            [
+                Action::Assign(assigned()),
+                review(me, true),
+                review(me, false),
+                review(stranger, true),
+                Action::Adjudge(true),
+                Action::Adjudge(false),
+                Action::RequestRevision,
+                Action::Withdraw,
+                Action::Supersede,
+            ]
+        };
+        // (state, action, where it goes), by their places above; anything else is illegal.
+        let allowed = [
+            (0, 0, reviewing(Editor, &[me, other])),
+            (0, 4, Passed(true)),
+            (0, 5, Passed(false)),
+            (0, 6, RevisionRequested),
+            (0, 7, Withdrawn),
+            (0, 8, Superseded),
+            (1, 1, reviewing(Editor, &[other])),
+            (1, 2, reviewing(Editor, &[other])),
+            (1, 6, RevisionRequested),
+            (1, 7, Withdrawn),
+            (1, 8, Superseded),
+            (2, 4, Passed(true)),
+            (2, 5, Passed(false)),
+            (2, 6, RevisionRequested),
+            (2, 7, Withdrawn),
+            (2, 8, Superseded),
+            (3, 1, Passed(true)),
+            (3, 2, Passed(false)),
+            (3, 6, RevisionRequested),
+            (3, 7, Withdrawn),
+            (3, 8, Superseded),
+            (4, 1, reviewing(Reviewer, &[other])),
+            (4, 2, reviewing(Reviewer, &[other])),
+            (4, 6, RevisionRequested),
+            (4, 7, Withdrawn),
+            (4, 8, Superseded),
+            (7, 7, Withdrawn),
+            (7, 8, History),
+        ];
+        for (i, state) in states.iter().enumerate() {
+            for (j, action) in actions().iter().enumerate() {
+                let expected = allowed
+                    .iter()
+                    .find(|(state, action, _)| (*state, *action) == (i, j))
+                    .map(|(_, _, next)| next);
+                match (state.next(action), expected) {
+                    (Ok(next), Some(expected)) => assert_eq!(&next, expected),
+                    (Err(TransitionError::Illegal { .. }), None) => {}
+                    (next, _) => panic!("{:?} by {:?} went to {:?}", state, action, next),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn illegal_transitions_say_why() {
+        let e = VersionState::Withdrawn
+            .next(&Action::RequestRevision)
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "cannot request revision: the version is withdrawn"
+        );
+    }
+
+    #[test]
+    fn transitions_tell_who_did_them() {
+        let transition = |action: &Action| Transition {
+            from: String::new(),
+            to: String::new(),
+            action: action.name().to_string(),
+            by: None,
+            at: Utc::now(),
+        };
+        let review = transition(&Action::Review {
+            reviewer_id: ObjectId::new(),
+            approve: true,
+        });
+        assert!(review.is_by_reviewer() && !review.is_by_author());
+        for action in [Action::Withdraw, Action::Supersede] {
+            let by_author = transition(&action);
+            assert!(by_author.is_by_author() && !by_author.is_by_reviewer());
+        }
+        let adjudged = transition(&Action::Adjudge(true));
+        assert!(!adjudged.is_by_author() && !adjudged.is_by_reviewer());
+    }
+}
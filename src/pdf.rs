@@ -0,0 +1,962 @@
+//! Reading the objects of a PDF file, just enough to tell what it says about itself.
+//!
+//! The file is scanned from the start for `n g obj` definitions, so a broken cross-reference
+//! table does not matter, and objects packed in object streams are unpacked as well. Only the
+//! FlateDecode filter is understood.
+
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::Range,
+};
+
+use thiserror::Error;
+
+use crate::inflate::{self, InflateError};
+
+/// How large a file is read at most.
+pub(crate) const SIZE_LIMIT: usize = 64 * 1024 * 1024;
+
+/// How large a single stream can get once decompressed.
+const STREAM_LIMIT: usize = 64 * 1024 * 1024;
+
+/// How much all the streams of a file can get to once decompressed, however often each is read.
+const INFLATE_LIMIT: usize = 256 * 1024 * 1024;
+
+/// How many references are followed in a row before giving up on a cycle.
+const DEPTH_LIMIT: usize = 32;
+
+/// How deep arrays and dictionaries can be nested in one another.
+const NESTING_LIMIT: usize = 64;
+
+type Dict = BTreeMap<Vec<u8>, Object>;
+
+/// What is read of a file with a stream too large to decompress, given the limits.
+#[derive(Error)]
+#[derive(Debug)]
+#[error("the PDF has more in its streams than can be decompressed")]
+pub(crate) struct Exhausted;
+
+#[derive(Clone)]
+#[derive(Debug)]
+enum Object {
+    /// Also stands for booleans, which matter to nothing read here.
+    Null,
+    Int(i64),
+    Real(f64),
+    Name(Vec<u8>),
+    String(Vec<u8>),
+    Array(Vec<Object>),
+    Dict(Dict),
+    /// The data is where it sits in the file, still encoded.
+    Stream(Dict, Range<usize>),
+    Ref(u32),
+}
+
+impl Object {
+    fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Object::Dict(dict) | Object::Stream(dict, _) => Some(dict),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Object::Int(int) => Some(*int),
+            Object::Real(real) => Some(*real as i64),
+            _ => None,
+        }
+    }
+
+    fn as_real(&self) -> Option<f64> {
+        match self {
+            Object::Int(int) => Some(*int as f64),
+            Object::Real(real) => Some(*real),
+            _ => None,
+        }
+    }
+
+    fn as_name(&self) -> Option<&[u8]> {
+        match self {
+            Object::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn is_type(&self, type_name: &[u8]) -> bool {
+        self.as_dict()
+            .and_then(|dict| dict.get(b"Type".as_slice()))
+            .and_then(Object::as_name)
+            == Some(type_name)
+    }
+}
+
+enum Token<'a> {
+    Int(i64),
+    Real(f64),
+    Name(Vec<u8>),
+    String(Vec<u8>),
+    DictStart,
+    DictEnd,
+    ArrayStart,
+    ArrayEnd,
+    Keyword(&'a [u8]),
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, 0 | b'\t' | b'\n' | 0x0c | b'\r' | b' ')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+fn is_regular(byte: u8) -> bool {
+    !is_whitespace(byte) && !is_delimiter(byte)
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) {
+                self.pos += 1;
+            } else if byte == b'%' {
+                while self
+                    .peek()
+                    .is_some_and(|byte| byte != b'\r' && byte != b'\n')
+                {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn regular(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self.peek().is_some_and(is_regular) {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    fn name(&mut self) -> Vec<u8> {
+        let raw = self.regular();
+        let mut name = Vec::with_capacity(raw.len());
+        let mut i = 0;
+        while i < raw.len() {
+            match (
+                raw[i],
+                raw.get(i + 1).copied().and_then(hex_value),
+                raw.get(i + 2).copied().and_then(hex_value),
+            ) {
+                (b'#', Some(high), Some(low)) => {
+                    name.push(high << 4 | low);
+                    i += 3;
+                }
+                (byte, _, _) => {
+                    name.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        name
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut string = Vec::new();
+        let mut depth = 0;
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+            match byte {
+                b'(' => {
+                    depth += 1;
+                    string.push(byte);
+                }
+                b')' if depth == 0 => break,
+                b')' => {
+                    depth -= 1;
+                    string.push(byte);
+                }
+                b'\\' => {
+                    let Some(escaped) = self.peek() else {
+                        break;
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => string.push(b'\n'),
+                        b'r' => string.push(b'\r'),
+                        b't' => string.push(b'\t'),
+                        b'b' => string.push(0x08),
+                        b'f' => string.push(0x0c),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(digit - b'0');
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            string.push(value as u8);
+                        }
+                        // A backslash at the end of a line joins it with the next one.
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        _ => string.push(escaped),
+                    }
+                }
+                _ => string.push(byte),
+            }
+        }
+        string
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+            if byte == b'>' {
+                break;
+            }
+            digits.extend(hex_value(byte));
+        }
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+            .collect()
+    }
+
+    fn token(&mut self) -> Option<Token<'a>> {
+        self.skip_whitespace();
+        let byte = self.peek()?;
+        if !is_regular(byte) {
+            self.pos += 1;
+        }
+        Some(match byte {
+            b'/' => Token::Name(self.name()),
+            b'(' => Token::String(self.literal_string()),
+            b'<' if self.peek() == Some(b'<') => {
+                self.pos += 1;
+                Token::DictStart
+            }
+            b'<' => Token::String(self.hex_string()),
+            b'>' if self.peek() == Some(b'>') => {
+                self.pos += 1;
+                Token::DictEnd
+            }
+            b'[' => Token::ArrayStart,
+            b']' => Token::ArrayEnd,
+            _ if !is_regular(byte) => Token::Keyword(&self.data[self.pos - 1..self.pos]),
+            _ => {
+                let word = self.regular();
+                let number = std::str::from_utf8(word).ok();
+                if let Some(int) = number.and_then(|number| number.parse().ok()) {
+                    Token::Int(int)
+                } else if let Some(real) = number
+                    .filter(|number| !number.contains(['e', 'E', 'i', 'n']))
+                    .and_then(|number| number.parse().ok())
+                {
+                    Token::Real(real)
+                } else {
+                    Token::Keyword(word)
+                }
+            }
+        })
+    }
+
+    fn object(&mut self) -> Option<Object> {
+        self.nested_object(0)
+    }
+
+    fn nested_object(&mut self, depth: usize) -> Option<Object> {
+        let token = self.token()?;
+        self.object_from(token, depth)
+    }
+
+    /// The object starting with `token`, `depth` arrays and dictionaries deep, or nothing if it
+    /// is broken or nested too deeply.
+    fn object_from(&mut self, token: Token, depth: usize) -> Option<Object> {
+        if matches!(token, Token::DictStart | Token::ArrayStart) && depth >= NESTING_LIMIT {
+            return None;
+        }
+        Some(match token {
+            Token::Int(int) => {
+                let after = self.pos;
+                match (self.token(), self.token()) {
+                    (Some(Token::Int(_)), Some(Token::Keyword(b"R"))) if int >= 0 => {
+                        Object::Ref(int as u32)
+                    }
+                    _ => {
+                        self.pos = after;
+                        Object::Int(int)
+                    }
+                }
+            }
+            Token::Real(real) => Object::Real(real),
+            Token::Name(name) => Object::Name(name),
+            Token::String(string) => Object::String(string),
+            Token::DictStart => {
+                let mut dict = Dict::new();
+                loop {
+                    match self.token()? {
+                        Token::DictEnd => break,
+                        Token::Name(key) => {
+                            let value = self.nested_object(depth + 1)?;
+                            dict.insert(key, value);
+                        }
+                        _ => {}
+                    }
+                }
+                Object::Dict(dict)
+            }
+            Token::ArrayStart => {
+                let mut array = Vec::new();
+                loop {
+                    match self.token()? {
+                        Token::ArrayEnd => break,
+                        token => array.push(self.object_from(token, depth + 1)?),
+                    }
+                }
+                Object::Array(array)
+            }
+            Token::DictEnd | Token::ArrayEnd | Token::Keyword(_) => Object::Null,
+        })
+    }
+}
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|at| from + at)
+}
+
+/// A PDF file with its objects read.
+pub(crate) struct Pdf<'a> {
+    data: &'a [u8],
+    objects: HashMap<u32, Object>,
+    /// All trailers merged, the later ones winning.
+    trailer: Dict,
+    /// How much has been decompressed so far.
+    inflated: Cell<usize>,
+    /// Whether a stream was left unread for decompressing to too much.
+    exhausted: Cell<bool>,
+}
+
+impl<'a> Pdf<'a> {
+    /// Reads the objects of `data`, or nothing if it has none.
+    pub(crate) fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut pdf = Self {
+            data,
+            objects: HashMap::new(),
+            trailer: Dict::new(),
+            inflated: Cell::new(0),
+            exhausted: Cell::new(false),
+        };
+        let mut lexer = Lexer::new(data, 0);
+        let mut numbers = (None, None);
+        while let Some(token) = lexer.token() {
+            match token {
+                Token::Int(int) => {
+                    numbers = (numbers.1, Some(int));
+                    continue;
+                }
+                Token::Keyword(b"obj") => {
+                    if let (Some(id), Some(_)) = numbers {
+                        if let Some(object) = pdf.definition(&mut lexer) {
+                            if object.is_type(b"XRef") {
+                                pdf.trailer
+                                    .extend(object.as_dict().cloned().unwrap_or_default());
+                            }
+                            pdf.objects.insert(id as u32, object);
+                        }
+                    }
+                }
+                Token::Keyword(b"trailer") => {
+                    if let Some(Object::Dict(dict)) = lexer.object() {
+                        pdf.trailer.extend(dict);
+                    }
+                }
+                _ => {}
+            }
+            numbers = (None, None);
+        }
+        pdf.unpack_object_streams();
+        (!pdf.objects.is_empty()).then_some(pdf)
+    }
+
+    /// The object after `n g obj`, with the data following it if it is a stream.
+    fn definition(&self, lexer: &mut Lexer) -> Option<Object> {
+        let object = lexer.object()?;
+        let Object::Dict(dict) = object else {
+            return Some(object);
+        };
+        let after = lexer.pos;
+        if !matches!(lexer.token(), Some(Token::Keyword(b"stream"))) {
+            lexer.pos = after;
+            return Some(Object::Dict(dict));
+        }
+        let mut start = lexer.pos;
+        if self.data.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if self.data.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let length = dict
+            .get(b"Length".as_slice())
+            .and_then(|length| self.resolve(length))
+            .and_then(Object::as_int)
+            .and_then(|length| usize::try_from(length).ok());
+        let end = length
+            .map(|length| start.saturating_add(length))
+            .filter(|&end| {
+                let mut after = Lexer::new(self.data, end);
+                matches!(after.token(), Some(Token::Keyword(b"endstream")))
+            })
+            .or_else(|| {
+                let mut end = find(self.data, b"endstream", start)?;
+                for eol in [b'\n', b'\r'] {
+                    if end > start && self.data[end - 1] == eol {
+                        end -= 1;
+                    }
+                }
+                Some(end)
+            })?;
+        lexer.pos = end;
+        Some(Object::Stream(dict, start..end))
+    }
+
+    fn unpack_object_streams(&mut self) {
+        let packs: Vec<_> = self
+            .objects
+            .values()
+            .filter(|object| object.is_type(b"ObjStm"))
+            .filter_map(|object| {
+                let dict = object.as_dict()?;
+                let count = dict.get(b"N".as_slice())?.as_int()?;
+                let first = usize::try_from(dict.get(b"First".as_slice())?.as_int()?).ok()?;
+                Some((count, first, self.stream_data(object)?))
+            })
+            .collect();
+        for (count, first, data) in packs {
+            let mut header = Lexer::new(&data, 0);
+            for _ in 0..count {
+                let (Some(Token::Int(id)), Some(Token::Int(offset))) =
+                    (header.token(), header.token())
+                else {
+                    break;
+                };
+                let Ok(offset) = usize::try_from(offset) else {
+                    break;
+                };
+                let mut lexer = Lexer::new(&data, first.saturating_add(offset));
+                if let Some(object) = lexer.object() {
+                    // Objects redefined directly by a later update win.
+                    self.objects.entry(id as u32).or_insert(object);
+                }
+            }
+        }
+    }
+
+    fn resolve<'o>(&'o self, mut object: &'o Object) -> Option<&'o Object> {
+        for _ in 0..DEPTH_LIMIT {
+            match object {
+                Object::Ref(id) => object = self.objects.get(id)?,
+                _ => return Some(object),
+            }
+        }
+        None
+    }
+
+    fn get<'o>(&'o self, dict: &'o Dict, key: &[u8]) -> Option<&'o Object> {
+        self.resolve(dict.get(key)?)
+    }
+
+    /// The decoded data of a stream, if its filters are understood.
+    fn stream_data(&self, stream: &Object) -> Option<Vec<u8>> {
+        let Object::Stream(dict, range) = stream else {
+            return None;
+        };
+        let mut data = self.data.get(range.clone())?.to_vec();
+        let filters = match self.get(dict, b"Filter") {
+            None => Vec::new(),
+            Some(Object::Array(filters)) => filters.iter().collect(),
+            Some(filter) => vec![filter],
+        };
+        for filter in filters {
+            data = match self.resolve(filter)?.as_name()? {
+                b"FlateDecode" | b"Fl" => {
+                    let left = INFLATE_LIMIT.saturating_sub(self.inflated.get());
+                    match inflate::inflate_zlib(&data, STREAM_LIMIT.min(left)) {
+                        Ok(data) => {
+                            self.inflated.set(self.inflated.get() + data.len());
+                            data
+                        }
+                        Err(InflateError::TooLarge(_)) => {
+                            self.exhausted.set(true);
+                            return None;
+                        }
+                        Err(_) => return None,
+                    }
+                }
+                _ => return None,
+            };
+        }
+        Some(data)
+    }
+
+    /// Whether some stream was too large to decompress, so that what was read of the file may
+    /// be missing things.
+    pub(crate) fn exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    fn info(&self) -> Option<&Dict> {
+        // Strings are encrypted along with the rest, and this does not decrypt.
+        if self.trailer.contains_key(b"Encrypt".as_slice()) {
+            return None;
+        }
+        self.get(&self.trailer, b"Info")?.as_dict()
+    }
+
+    fn info_text(&self, key: &[u8]) -> Option<String> {
+        match self.get(self.info()?, key)? {
+            Object::String(string) => text_string(string),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn page_count(&self) -> Option<i64> {
+        let root = self.get(&self.trailer, b"Root").and_then(Object::as_dict);
+        root.and_then(|root| self.get(root, b"Pages"))
+            .and_then(Object::as_dict)
+            .and_then(|pages| self.get(pages, b"Count"))
+            .and_then(Object::as_int)
+            .or_else(|| {
+                // Without a usable catalog, count the pages lying around instead.
+                let pages = self
+                    .objects
+                    .values()
+                    .filter(|object| object.is_type(b"Page"));
+                Some(pages.count() as i64).filter(|&count| count > 0)
+            })
+    }
+
+    pub(crate) fn title(&self) -> Option<String> {
+        self.info_text(b"Title")
+    }
+
+    pub(crate) fn author(&self) -> Option<String> {
+        self.info_text(b"Author")
+    }
+
+    /// Names of the fonts used, without the tags of subsets such as `ABCDEF+`.
+    pub(crate) fn fonts(&self) -> BTreeSet<String> {
+        self.objects
+            .values()
+            .filter(|object| object.is_type(b"Font"))
+            .filter_map(|font| self.get(font.as_dict()?, b"BaseFont")?.as_name())
+            .map(|name| {
+                let name = String::from_utf8_lossy(name);
+                match name.split_once('+') {
+                    Some((tag, base))
+                        if tag.len() == 6 && tag.bytes().all(|b| b.is_ascii_uppercase()) =>
+                    {
+                        base.to_string()
+                    }
+                    _ => name.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// The pages in order, as dictionaries with the resources they inherit.
+    fn pages(&self) -> Vec<(&Dict, Option<&Dict>)> {
+        let mut pages = Vec::new();
+        let root = self.get(&self.trailer, b"Root").and_then(Object::as_dict);
+        if let Some(tree) = root.and_then(|root| root.get(b"Pages".as_slice())) {
+            self.collect_pages(tree, None, 0, &mut HashSet::new(), &mut pages);
+        }
+        pages
+    }
+
+    /// Collects the pages under `node`, skipping the objects in `visited` so that each is gone
+    /// through once however often it is referred to.
+    fn collect_pages<'o>(
+        &'o self,
+        node: &'o Object,
+        resources: Option<&'o Dict>,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+        pages: &mut Vec<(&'o Dict, Option<&'o Dict>)>,
+    ) {
+        if let Object::Ref(id) = node {
+            if !visited.insert(*id) {
+                return;
+            }
+        }
+        let Some(node) = self.resolve(node).and_then(Object::as_dict) else {
+            return;
+        };
+        let resources = self
+            .get(node, b"Resources")
+            .and_then(Object::as_dict)
+            .or(resources);
+        match self.get(node, b"Kids") {
+            Some(Object::Array(kids)) if depth < DEPTH_LIMIT => {
+                for kid in kids {
+                    self.collect_pages(kid, resources, depth + 1, visited, pages);
+                }
+            }
+            Some(_) => {}
+            None => pages.push((node, resources)),
+        }
+    }
+
+    /// The text of each page, a line for each line of text as far as the positioning operators
+    /// tell, in the order it is drawn rather than read.
+    pub(crate) fn page_texts(&self) -> Vec<String> {
+        if self.trailer.contains_key(b"Encrypt".as_slice()) {
+            return Vec::new();
+        }
+        self.pages()
+            .into_iter()
+            .map(|(page, resources)| self.page_text(page, resources))
+            .collect()
+    }
+
+    fn page_text(&self, page: &Dict, resources: Option<&Dict>) -> String {
+        let streams = match self.get(page, b"Contents") {
+            Some(Object::Array(streams)) => streams.iter().collect(),
+            Some(stream) => vec![stream],
+            None => Vec::new(),
+        };
+        let mut content = Vec::new();
+        for stream in streams {
+            if let Some(data) = self.resolve(stream).and_then(|s| self.stream_data(s)) {
+                content.extend(data);
+                content.push(b'\n');
+            }
+        }
+        let fonts = resources
+            .and_then(|resources| self.get(resources, b"Font"))
+            .and_then(Object::as_dict);
+        let mut maps: HashMap<Vec<u8>, Option<ToUnicode>> = HashMap::new();
+        let mut font = Vec::new();
+        let mut line_y = None;
+        let mut text = String::new();
+        let mut operands = Vec::new();
+        let mut lexer = Lexer::new(&content, 0);
+        while let Some(token) = lexer.token() {
+            let Token::Keyword(operator) = token else {
+                if let Some(operand) = lexer.object_from(token, 0) {
+                    operands.push(operand);
+                }
+                continue;
+            };
+            let map = if matches!(operator, b"Tj" | b"'" | b"\"" | b"TJ") {
+                let map = maps.entry(font.clone()).or_insert_with(|| {
+                    let font = self.get(fonts?, &font)?.as_dict()?;
+                    ToUnicode::parse(&self.stream_data(self.get(font, b"ToUnicode")?)?)
+                });
+                map.as_ref()
+            } else {
+                None
+            };
+            match operator {
+                b"Tf" => {
+                    if let Some(name) = operands.first().and_then(Object::as_name) {
+                        font = name.to_vec();
+                    }
+                }
+                b"Tj" | b"'" | b"\"" => {
+                    if operator != b"Tj" {
+                        new_line(&mut text);
+                    }
+                    if let Some(Object::String(string)) = operands.last() {
+                        show(&mut text, map, string);
+                    }
+                }
+                b"TJ" => {
+                    let Some(Object::Array(items)) = operands.last() else {
+                        continue;
+                    };
+                    for item in items {
+                        match item {
+                            Object::String(string) => show(&mut text, map, string),
+                            // Moving on by more than a fifth of the font size is taken for a
+                            // space between words.
+                            _ if item.as_real().is_some_and(|shift| shift < -200.0) => {
+                                text.push(' ')
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                b"Td" | b"TD" if operands.get(1).and_then(Object::as_real) != Some(0.0) => {
+                    new_line(&mut text)
+                }
+                b"Tm" => {
+                    let y = operands.get(5).and_then(Object::as_real);
+                    if y != line_y {
+                        new_line(&mut text);
+                    } else if !text.ends_with([' ', '\n']) {
+                        text.push(' ');
+                    }
+                    line_y = y;
+                }
+                b"T*" | b"ET" => new_line(&mut text),
+                // The data of an inline image, which runs until `EI` on its own.
+                b"ID" => {
+                    let end = (lexer.pos..content.len().saturating_sub(1)).find(|&at| {
+                        content[at..].starts_with(b"EI")
+                            && content
+                                .get(at.wrapping_sub(1))
+                                .copied()
+                                .is_some_and(is_whitespace)
+                            && content.get(at + 2).copied().is_none_or(is_whitespace)
+                    });
+                    lexer.pos = end.map_or(content.len(), |end| end + 2);
+                }
+                _ => {}
+            }
+            operands.clear();
+        }
+        let lines: Vec<_> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        lines.join("\n")
+    }
+}
+
+fn new_line(text: &mut String) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+/// Appends the text a string shows, through the ToUnicode map of its font if there is one, or
+/// taking each byte for a Latin-1 character otherwise, which fits simple fonts in the standard
+/// encodings well enough.
+fn show(text: &mut String, map: Option<&ToUnicode>, string: &[u8]) {
+    match map {
+        Some(map) => {
+            for code in string.chunks(map.code_len) {
+                let code = code
+                    .iter()
+                    .fold(0, |code, &byte| code << 8 | u32::from(byte));
+                if let Some(mapped) = map.codes.get(&code) {
+                    text.push_str(mapped);
+                }
+            }
+        }
+        None => text.extend(
+            string
+                .iter()
+                .filter(|&&byte| byte >= 0x20)
+                .map(|&byte| byte as char),
+        ),
+    }
+}
+
+/// How many codes a single range of a ToUnicode map can cover.
+const RANGE_LIMIT: u32 = 0x10000;
+
+/// What the character codes of a font stand for, from its ToUnicode CMap.
+struct ToUnicode {
+    /// How many bytes each code takes, told by the first mapping.
+    code_len: usize,
+    codes: HashMap<u32, String>,
+}
+
+impl ToUnicode {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut map = Self {
+            code_len: 0,
+            codes: HashMap::new(),
+        };
+        let mut lexer = Lexer::new(data, 0);
+        // How many operands each mapping takes, within `beginbfchar` or `beginbfrange`.
+        let mut arity = 0;
+        let mut operands = Vec::new();
+        while let Some(token) = lexer.token() {
+            match token {
+                Token::Keyword(b"beginbfchar") => arity = 2,
+                Token::Keyword(b"beginbfrange") => arity = 3,
+                Token::Keyword(_) => arity = 0,
+                token if arity > 0 => {
+                    operands.push(lexer.object_from(token, 0)?);
+                    if operands.len() == arity {
+                        map.add(&operands);
+                        operands.clear();
+                    }
+                }
+                _ => {}
+            }
+        }
+        (!map.codes.is_empty()).then_some(map)
+    }
+
+    fn add(&mut self, operands: &[Object]) {
+        let Object::String(low) = &operands[0] else {
+            return;
+        };
+        if self.code_len == 0 {
+            self.code_len = low.len().clamp(1, 4);
+        }
+        let code = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .fold(0u32, |code, &byte| code << 8 | u32::from(byte))
+        };
+        let low = code(low);
+        let high = match operands {
+            [_, _] => low,
+            [_, Object::String(high), _] => code(high),
+            _ => return,
+        };
+        if high < low || high - low >= RANGE_LIMIT {
+            return;
+        }
+        match operands.last() {
+            Some(Object::String(target)) => {
+                let mut units: Vec<_> = target
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                for code in low..=high {
+                    self.codes.insert(code, String::from_utf16_lossy(&units));
+                    // Each code in a range stands for the one before it, its last unit plus one.
+                    if let Some(last) = units.last_mut() {
+                        *last = last.wrapping_add(1);
+                    }
+                }
+            }
+            Some(Object::Array(targets)) => {
+                for (code, target) in (low..=high).zip(targets) {
+                    if let Object::String(target) = target {
+                        let units: Vec<_> = target
+                            .chunks_exact(2)
+                            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                            .collect();
+                        self.codes.insert(code, String::from_utf16_lossy(&units));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decodes a text string, which is UTF-16BE or UTF-8 with a byte order mark, or else
+/// PDFDocEncoding, taken as Latin-1 here.
+fn text_string(bytes: &[u8]) -> Option<String> {
+    let text = if let Some(utf16) = bytes.strip_prefix(b"\xfe\xff") {
+        let units: Vec<_> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        bytes.iter().map(|&byte| byte as char).collect()
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        for open in ["[", "<< /A "] {
+            let mut data = b"%PDF-1.4\n1 0 obj\n".to_vec();
+            data.extend(open.repeat(1_000_000).as_bytes());
+            data.extend(b"\nendobj\n2 0 obj\n<< /Type /Page >>\nendobj\n");
+            let pdf = Pdf::parse(&data).expect("the second object is fine");
+            assert!(!pdf.objects.contains_key(&1));
+            assert!(pdf.objects.contains_key(&2));
+        }
+    }
+
+    #[test]
+    fn streams_inflating_too_much_exhaust_the_file() {
+        let mut stream = vec![0x78, 0x01];
+        stream.extend(inflate::tests::deflate_zeros(STREAM_LIMIT + 1));
+        let mut data = format!(
+            "%PDF-1.4\n1 0 obj\n<< /Type /ObjStm /N 1 /First 4 /Filter /FlateDecode /Length {} >>\nstream\n",
+            stream.len()
+        )
+        .into_bytes();
+        data.extend(stream);
+        data.extend(b"\nendstream\nendobj\n");
+        let pdf = Pdf::parse(&data).unwrap();
+        assert!(pdf.exhausted());
+    }
+
+    #[test]
+    fn pages_referred_to_repeatedly_are_gone_through_once() {
+        let mut data =
+            "%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 10 0 R >>\nendobj\n".to_string();
+        // Each node of the tree lists the next one twice, and the last one the first page twice.
+        for id in 10..10 + DEPTH_LIMIT as u32 {
+            let next = id + 1;
+            data += &format!(
+                "{} 0 obj\n<< /Type /Pages /Kids [{} 0 R {} 0 R {} 0 R] >>\nendobj\n",
+                id, next, next, id
+            );
+        }
+        data += &format!(
+            "{} 0 obj\n<< /Type /Page >>\nendobj\ntrailer\n<< /Root 1 0 R >>\n",
+            10 + DEPTH_LIMIT
+        );
+        let pdf = Pdf::parse(data.as_bytes()).unwrap();
+        assert_eq!(pdf.pages().len(), 1);
+    }
+
+    #[test]
+    fn nesting_within_the_limit_is_read() {
+        let data = format!(
+            "%PDF-1.4\n1 0 obj\n{}{}\nendobj\n",
+            "[".repeat(NESTING_LIMIT),
+            "]".repeat(NESTING_LIMIT)
+        );
+        let pdf = Pdf::parse(data.as_bytes()).unwrap();
+        assert!(matches!(pdf.objects.get(&1), Some(Object::Array(_))));
+    }
+}
@@ -0,0 +1,16 @@
+//! Chores the server does in the background for as long as it runs.
+
+use std::{future::Future, time::Duration};
+
+/// Runs `chore` now and then every `period` for ever.
+pub(crate) async fn every<F, Fut>(period: Duration, mut chore: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        chore().await;
+    }
+}
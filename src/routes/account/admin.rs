@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ::notice::email::{Address, AddressDef};
+use aide::axum::{routing, ApiRouter};
+use axum::{
+    debug_handler,
+    extract::{Path, Query, State},
+};
+use axum_jsonschema::Json;
+use chrono::{NaiveDateTime, Utc};
+use mongo::entity::page::{Page, Paging};
+use schemars::JsonSchema;
+use sea_orm::{
+    sea_query::{OnConflict, Query as SqlQuery},
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, EntityTrait, IntoActiveModel,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mongo_entities::profile::Profile,
+    session,
+    sql_entities::{
+        account, account_grant,
+        prelude::{Account, AccountGrant},
+    },
+    state::AppState,
+};
+
+use super::{
+    super::common::{
+        auth::{Action, AuthInfo, Grant, Resource, Role, Scope},
+        docs,
+        err::{Error, Result},
+    },
+    tools,
+};
+
+const ADMINISTRATOR: Grant = Grant {
+    role: Role::Administrator,
+    scope: Scope::Global,
+};
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct AccountView {
+    #[schemars(with = "AddressDef")]
+    email: String,
+    grants: BTreeSet<Grant>,
+    verified_at: Option<NaiveDateTime>,
+    locked_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl AccountView {
+    fn new(account: account::Model, grants: BTreeSet<Grant>) -> Self {
+        Self {
+            email: account.email,
+            grants,
+            verified_at: account.verified_at,
+            locked_at: account.locked_at,
+            created_at: account.created_at,
+            updated_at: account.updated_at,
+        }
+    }
+}
+
+type Res = Json<AccountView>;
+
+/// Accounts are administrated only from a session, never with a personal access token.
+fn ensure_administrator(auth_info: AuthInfo) -> Result<()> {
+    if auth_info.is_by_token() {
+        return Err(Error::Forbidden(
+            "accounts cannot be administrated with an access token".to_string(),
+        ));
+    }
+    if !auth_info.can(Action::Manage, &Resource::global()) {
+        return Err(Error::Forbidden("you are not an administrator".to_string()));
+    }
+    Ok(())
+}
+
+fn parse_email(email: String) -> Result<Address> {
+    email
+        .parse()
+        .map_err(|_| Error::BadReqest(format!("invalid email {}", email)))
+}
+
+async fn find(state: &AppState, email: &Address) -> Result<account::Model> {
+    tools::try_find_account(&state.sql_db, &email.to_string())
+        .await?
+        .ok_or(Error::NotFound(format!("no account with email {}", email)))
+}
+
+async fn view(state: &AppState, account: account::Model) -> Result<Res> {
+    let grants = Grant::load(&state.sql_db, &account.email).await?;
+    Ok(Json(AccountView::new(account, grants)))
+}
+
+/// Refuses to leave the site without any usable administrator account.
+async fn keep_an_administrator(state: &AppState, account: &account::Model) -> Result<()> {
+    if account.locked_at.is_some()
+        || !Grant::load(&state.sql_db, &account.email)
+            .await?
+            .contains(&ADMINISTRATOR)
+    {
+        return Ok(());
+    }
+    let others = Account::find()
+        .filter(
+            account::Column::Email.in_subquery(
+                SqlQuery::select()
+                    .column(account_grant::Column::Email)
+                    .from(AccountGrant)
+                    .cond_where(ADMINISTRATOR.filter())
+                    .to_owned(),
+            ),
+        )
+        .filter(account::Column::LockedAt.is_null())
+        .filter(account::Column::Email.ne(account.email.clone()))
+        .count(&state.sql_db)
+        .await
+        .map_err(Error::from)?;
+    if others == 0 {
+        return Err(Error::Conflict(
+            "this is the last administrator".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Puts the current grants of `email` into all sessions of that user.
+async fn refresh_sessions(state: &AppState, email: &Address) -> Result<()> {
+    if let Some(profile) = Profile::get(state.mongo_db.clone(), email).await? {
+        let grants = Grant::load(&state.sql_db, email.as_ref()).await?;
+        state
+            .sessions
+            .rewrite_owned_by(
+                profile._id,
+                session::AUTH_INFO_KEY,
+                &AuthInfo::new(profile._id, grants),
+            )
+            .await
+            .map_err(|e| Error::Common(e.to_string()))?;
+    }
+    Ok(())
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct AccountFilter {
+    #[schemars(title = "Only Accounts with This Role in Some Scope")]
+    role: Option<Role>,
+    #[schemars(title = "Only Locked Accounts or Not")]
+    locked: Option<bool>,
+}
+
+type ListRes = Json<Page<AccountView>>;
+
+#[debug_handler]
+async fn list(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Query(paging): Query<Paging>,
+    Query(filter): Query<AccountFilter>,
+) -> Result<ListRes> {
+    ensure_administrator(auth_info)?;
+    let mut condition = Condition::all();
+    if let Some(role) = filter.role {
+        condition = condition.add(
+            account::Column::Email.in_subquery(
+                SqlQuery::select()
+                    .column(account_grant::Column::Email)
+                    .from(AccountGrant)
+                    .and_where(account_grant::Column::Role.eq(role.as_str()))
+                    .to_owned(),
+            ),
+        );
+    }
+    if let Some(locked) = filter.locked {
+        condition = condition.add(if locked {
+            account::Column::LockedAt.is_not_null()
+        } else {
+            account::Column::LockedAt.is_null()
+        });
+    }
+    let query = Account::find().filter(condition);
+    let total = query
+        .clone()
+        .count(&state.sql_db)
+        .await
+        .map_err(Error::from)?;
+    let accounts = query
+        .order_by_asc(account::Column::CreatedAt)
+        .offset(paging.skip())
+        .limit(paging.limit())
+        .all(&state.sql_db)
+        .await
+        .map_err(Error::from)?;
+    let mut grants = <BTreeMap<String, BTreeSet<Grant>>>::new();
+    for model in AccountGrant::find()
+        .filter(
+            account_grant::Column::Email
+                .is_in(accounts.iter().map(|account| account.email.clone())),
+        )
+        .all(&state.sql_db)
+        .await
+        .map_err(Error::from)?
+    {
+        grants
+            .entry(model.email.clone())
+            .or_default()
+            .insert(model.try_into()?);
+    }
+    Ok(Json(Page {
+        items: accounts
+            .into_iter()
+            .map(|account| {
+                let grants = grants.remove(&account.email).unwrap_or_default();
+                AccountView::new(account, grants)
+            })
+            .collect(),
+        page: paging.page,
+        per_page: paging.limit(),
+        total,
+    }))
+}
+
+#[debug_handler]
+async fn grant(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(grant): Json<Grant>,
+) -> Result<Res> {
+    ensure_administrator(auth_info)?;
+    let email = parse_email(email)?;
+    let account = find(&state, &email).await?;
+    AccountGrant::insert(grant.into_active_model(account.email.clone()))
+        .on_conflict(
+            OnConflict::columns([
+                account_grant::Column::Email,
+                account_grant::Column::Role,
+                account_grant::Column::Scope,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(&state.sql_db)
+        .await
+        .map_err(Error::from)?;
+    refresh_sessions(&state, &email).await?;
+    view(&state, account).await
+}
+
+#[debug_handler]
+async fn revoke(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(grant): Json<Grant>,
+) -> Result<Res> {
+    ensure_administrator(auth_info)?;
+    let email = parse_email(email)?;
+    let account = find(&state, &email).await?;
+    if grant == ADMINISTRATOR {
+        keep_an_administrator(&state, &account).await?;
+    }
+    AccountGrant::delete_many()
+        .filter(account_grant::Column::Email.eq(account.email.clone()))
+        .filter(grant.filter())
+        .exec(&state.sql_db)
+        .await
+        .map_err(Error::from)?;
+    refresh_sessions(&state, &email).await?;
+    view(&state, account).await
+}
+
+#[debug_handler]
+async fn lock(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Res> {
+    ensure_administrator(auth_info)?;
+    let email = parse_email(email)?;
+    let account = find(&state, &email).await?;
+    if account.locked_at.is_some() {
+        return view(&state, account).await;
+    }
+    keep_an_administrator(&state, &account).await?;
+    let now = Utc::now().naive_utc();
+    let mut account = account.into_active_model();
+    account.locked_at = ActiveValue::Set(Some(now));
+    account.updated_at = ActiveValue::Set(now);
+    let account = account.update(&state.sql_db).await.map_err(Error::from)?;
+    if let Some(profile) = Profile::get(state.mongo_db.clone(), &email).await? {
+        state
+            .sessions
+            .destroy_owned_by(profile._id)
+            .await
+            .map_err(|e| Error::Common(e.to_string()))?;
+    }
+    view(&state, account).await
+}
+
+#[debug_handler]
+async fn unlock(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Res> {
+    ensure_administrator(auth_info)?;
+    let email = parse_email(email)?;
+    let mut account = find(&state, &email).await?.into_active_model();
+    account.locked_at = ActiveValue::Set(None);
+    account.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+    let account = account.update(&state.sql_db).await.map_err(Error::from)?;
+    view(&state, account).await
+}
+
+fn tag(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
+    op.tag("administrate accounts")
+}
+
+fn with_email(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
+    docs::add_one_parameter(
+        tag(op),
+        "email".to_string(),
+        Some("email address of the account".to_string()),
+        None,
+    )
+}
+
+pub(super) fn route() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route_with(
+            "/",
+            routing::get_with(list, |op| {
+                op.summary("list accounts")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<ListRes>()
+            }),
+            tag,
+        )
+        .api_route_with(
+            "/:email/grants",
+            routing::put_with(grant, |op| {
+                op.summary("grant a role to an account")
+                    .description("sessions of the account get the new grants at once")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<Res>()
+            })
+            .delete_with(revoke, |op| {
+                op.summary("revoke a role from an account")
+                    .description("sessions of the account get the new grants at once")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<Res>()
+            }),
+            with_email,
+        )
+        .api_route_with(
+            "/:email/lock",
+            routing::post_with(lock, |op| {
+                op.summary("lock an account")
+                    .description("the account is logged out and cannot log in until unlocked")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<Res>()
+            })
+            .delete_with(unlock, |op| {
+                op.summary("unlock an account")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<Res>()
+            }),
+            with_email,
+        )
+}
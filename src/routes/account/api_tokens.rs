@@ -0,0 +1,207 @@
+//! Personal access tokens of the logged-in user.
+//!
+//! The handlers read the session through [`AuthInfoStorage`], which ignores bearer tokens, so
+//! that a leaked token cannot make more of itself.
+
+use std::collections::BTreeSet;
+
+use aide::axum::{routing, ApiRouter};
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use axum_jsonschema::Json;
+use chrono::{Duration, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use sea_orm::{ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sql_entities::{api_token, prelude::ApiToken},
+    state::AppState,
+};
+
+use super::{
+    super::common::{
+        auth::AuthInfoStorage,
+        bearer::{self, TokenScope},
+        docs,
+        err::{Error, Result},
+    },
+    tools,
+};
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct TokenView {
+    id: i32,
+    name: String,
+    scopes: BTreeSet<TokenScope>,
+    expires_at: Option<NaiveDateTime>,
+    last_used_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl TryFrom<api_token::Model> for TokenView {
+    type Error = Error;
+
+    fn try_from(model: api_token::Model) -> Result<Self> {
+        Ok(Self {
+            id: model.id,
+            scopes: bearer::split_scopes(&model.scopes)?,
+            name: model.name,
+            expires_at: model.expires_at,
+            last_used_at: model.last_used_at,
+            created_at: model.created_at,
+        })
+    }
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct CreateBody {
+    #[schemars(
+        title = "Name",
+        description = "What the token is for.",
+        length(min = 1)
+    )]
+    name: String,
+    #[schemars(
+        title = "Scopes",
+        description = "What the token may be used for. Everything if empty."
+    )]
+    #[serde(default)]
+    scopes: BTreeSet<TokenScope>,
+    #[schemars(
+        title = "Time to Live",
+        description = "In seconds. The token never expires if absent."
+    )]
+    ttl: Option<u32>,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct CreatedToken {
+    #[schemars(
+        title = "Token",
+        description = "Send it as `Authorization: Bearer <token>`. It is shown only this time."
+    )]
+    token: String,
+    #[serde(flatten)]
+    view: TokenView,
+}
+
+#[debug_handler]
+async fn create(
+    auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+    Json(body): Json<CreateBody>,
+) -> Result<(StatusCode, Json<CreatedToken>)> {
+    let email = tools::email_of(&state, auth_info_storage.load()?.id).await?;
+    if body.name.is_empty() {
+        return Err(Error::BadReqest("the token needs a name".to_string()));
+    }
+    let now = Utc::now().naive_utc();
+    let (token, token_hash) = bearer::generate();
+    let model = ApiToken::insert(api_token::ActiveModel {
+        email: ActiveValue::Set(email),
+        name: ActiveValue::Set(body.name),
+        token_hash: ActiveValue::Set(token_hash),
+        scopes: ActiveValue::Set(bearer::join_scopes(&body.scopes)),
+        expires_at: ActiveValue::Set(body.ttl.map(|ttl| now + Duration::seconds(ttl.into()))),
+        last_used_at: ActiveValue::Set(None),
+        created_at: ActiveValue::Set(now),
+        ..Default::default()
+    })
+    .exec_with_returning(&state.sql_db)
+    .await
+    .map_err(Error::from)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedToken {
+            token,
+            view: model.try_into()?,
+        }),
+    ))
+}
+
+type ListRes = Json<Vec<TokenView>>;
+
+#[debug_handler]
+async fn list(
+    auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+) -> Result<ListRes> {
+    let email = tools::email_of(&state, auth_info_storage.load()?.id).await?;
+    ApiToken::find()
+        .filter(api_token::Column::Email.eq(email))
+        .order_by_asc(api_token::Column::CreatedAt)
+        .all(&state.sql_db)
+        .await
+        .map_err(Error::from)?
+        .into_iter()
+        .map(TokenView::try_from)
+        .collect::<Result<_>>()
+        .map(Json)
+}
+
+#[debug_handler]
+async fn revoke(
+    auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<StatusCode> {
+    let email = tools::email_of(&state, auth_info_storage.load()?.id).await?;
+    let deleted = ApiToken::delete_many()
+        .filter(api_token::Column::Id.eq(id))
+        .filter(api_token::Column::Email.eq(email))
+        .exec(&state.sql_db)
+        .await
+        .map_err(Error::from)?
+        .rows_affected;
+    if deleted == 0 {
+        return Err(Error::NotFound(format!("no token with id {}", id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn tag(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
+    op.tag("personal access tokens")
+}
+
+pub(super) fn route() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route_with(
+            "/",
+            routing::post_with(create, |op| {
+                op.summary("create a personal access token")
+                    .description("for scripts and CI jobs, which cannot keep a cookie")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .response::<201, Json<CreatedToken>>()
+            })
+            .get_with(list, |op| {
+                op.summary("list my personal access tokens")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<ListRes>()
+            }),
+            tag,
+        )
+        .api_route_with(
+            "/:id",
+            routing::delete_with(revoke, |op| {
+                op.summary("revoke a personal access token")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .response_with::<204, (), _>(|res| res.description("the token is revoked"))
+            }),
+            |op| {
+                docs::add_one_parameter(
+                    tag(op),
+                    "id".to_string(),
+                    Some("token id".to_string()),
+                    None,
+                )
+            },
+        )
+}
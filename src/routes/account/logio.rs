@@ -12,7 +12,7 @@ use crate::{mongo_entities::profile::Profile, state::AppState};
 
 use super::{
     super::common::{
-        auth::AuthInfoStorage,
+        auth::{AuthInfo, AuthInfoStorage, Grant},
         docs,
         err::{Error, Result},
     },
@@ -41,22 +41,20 @@ async fn login(
     let account = tools::try_find_account(&state.sql_db, &email.to_string())
         .await?
         .ok_or(Error::NotFound(format!("no account with email {}", email)))?;
-    let salt = account.salt.into_bytes();
-    if tools::get_hash(state.hash_cost, salt, body.password)
-        .await?
-        .to_vec()
-        != account.password_hash
-    {
-        return Err(Error::BadReqest("wrong password".to_string()));
+    tools::check_password(state.hash_cost, &account, body.password).await?;
+    if account.locked_at.is_some() {
+        return Err(Error::Forbidden(format!("{} is locked", email)));
+    }
+    if account.verified_at.is_none() {
+        return Err(Error::Forbidden(format!("{} is not verified yet", email)));
     }
     let model_profile = Profile::get(state.mongo_db, &email)
         .await?
         .ok_or(Error::NotFound(format!("no profile with email {}", email)))?;
-    auth_info_storage.store(
+    auth_info_storage.store(&AuthInfo::new(
         model_profile._id,
-        account.is_administrator,
-        account.is_editor,
-    )?;
+        Grant::load(&state.sql_db, &account.email).await?,
+    ))?;
     Ok((StatusCode::CREATED, Json(model_profile.into())))
 }
 
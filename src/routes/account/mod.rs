@@ -7,20 +7,32 @@ use mongo::{entity::Entity, oid::ObjectIdDef};
 use notice::email::{Address, AddressDef};
 use passwords::hasher;
 use schemars::JsonSchema;
-use sea_orm::{prelude::Uuid, ActiveValue, EntityTrait, ModelTrait};
+use sea_orm::{prelude::Uuid, ActiveValue, EntityTrait, ModelTrait, TransactionTrait};
 use serde::Deserialize;
 
 use crate::{
     mongo_entities::profile::{Bio, Notification, Profile},
-    sql_entities::{account, prelude::Account},
+    saga::Saga,
+    sql_entities::{
+        account,
+        prelude::{Account, AccountGrant},
+    },
     state::AppState,
 };
 
-use super::common::err::{Error, Result};
+use super::common::{
+    auth::{Grant, Role, Scope},
+    err::{Error, Result},
+};
 
+mod admin;
+mod api_tokens;
 mod logio;
+mod password;
 mod profile;
+mod token;
 mod tools;
+mod verify;
 
 #[derive(JsonSchema)]
 #[derive(Deserialize)]
@@ -43,56 +55,87 @@ async fn signup(
 ) -> Result<(StatusCode, ObjectIdDef)> {
     let email = body.email;
     let account = tools::try_find_account(&state.sql_db, &email.to_string()).await?;
-    if account.is_some() {
-        return Err(Error::Conflict(format!(
-            "account with {} already exists",
-            email
-        )));
-    }
-    if Profile::get(state.mongo_db.clone(), &email)
-        .await?
-        .is_some()
-    {
+    let profile = Profile::get(state.mongo_db.clone(), &email).await?;
+    if let Some(account) = &account {
+        if !verify::is_stale(&state, account) {
+            return Err(Error::Conflict(format!(
+                "account with {} already exists",
+                email
+            )));
+        }
+    } else if profile.is_some() {
         return Err(Error::Conflict(format!(
             "profile with {} already exists",
             email
         )));
     }
     let salt = hasher::gen_salt();
-    let account = account::ActiveModel {
-        email: ActiveValue::Set(email.clone().to_string()),
-        salt: ActiveValue::Set(Uuid::from_bytes(salt)),
-        password_hash: ActiveValue::Set(
-            tools::get_hash(state.hash_cost, salt, body.password)
-                .await?
-                .into(),
-        ),
-        is_administrator: ActiveValue::Set(
-            Account::find()
-                .all(&state.sql_db)
+    let password_hash = tools::get_hash(state.hash_cost, salt, body.password).await?;
+    let mut saga = Saga::new("signup");
+    let result = async {
+        let txn = state.sql_db.begin().await?;
+        // Nobody has proven to own this address, so it can be taken again.
+        if let Some(account) = account {
+            account.delete(&txn).await?;
+        }
+        if let Some(profile) = profile {
+            Profile::delete(&state.mongo_client, state.mongo_db.clone(), profile.clone()).await?;
+            let db = state.mongo_db.clone();
+            saga.compensate(async move {
+                <Entity<Profile>>::reinsert(db, &profile)
+                    .await
+                    .map_err(Error::from)
+            });
+        }
+        let is_first = Account::find().one(&txn).await?.is_none();
+        let account = Account::insert(account::ActiveModel {
+            email: ActiveValue::Set(email.to_string()),
+            salt: ActiveValue::Set(Uuid::from_bytes(salt)),
+            password_hash: ActiveValue::Set(password_hash.into()),
+            created_at: ActiveValue::Set(Utc::now().naive_utc()),
+            updated_at: ActiveValue::Set(Utc::now().naive_utc()),
+            verified_at: ActiveValue::Set(None),
+            locked_at: ActiveValue::Set(None),
+        })
+        .exec_with_returning(&txn)
+        .await?;
+        if is_first {
+            AccountGrant::insert(
+                Grant {
+                    role: Role::Administrator,
+                    scope: Scope::Global,
+                }
+                .into_active_model(account.email.clone()),
+            )
+            .exec(&txn)
+            .await?;
+        }
+        let oid = <Entity<Profile>>::insert_one(
+            state.mongo_db.clone(),
+            Profile {
+                email,
+                notice: Notification::default(),
+                bio: body.bio.into(),
+            },
+        )
+        .await?
+        .ok_or(Error::NotFound("no inserted id".to_string()))?;
+        let db = state.mongo_db.clone();
+        saga.compensate(async move {
+            <Entity<Profile>>::delete_by_id(db, oid)
                 .await
-                .map_err(Error::from)?
-                .is_empty(),
-        ),
-        created_at: ActiveValue::Set(Utc::now().naive_utc()),
-        updated_at: ActiveValue::Set(Utc::now().naive_utc()),
-        is_editor: ActiveValue::Set(false),
-    };
-    Account::insert(account)
-        .exec(&state.sql_db)
-        .await
-        .map_err(Error::from)?;
-    let oid = <Entity<Profile>>::insert_one(
-        state.mongo_db,
-        Profile {
-            email,
-            notice: Notification::default(),
-            bio: body.bio.into(),
-        },
-    )
-    .await
-    .map_err(Error::from)?
-    .ok_or(Error::NotFound("no inserted id".to_string()))?;
+                .map(|_| ())
+                .map_err(Error::from)
+        });
+        txn.commit().await?;
+        Ok((account, oid))
+    }
+    .await;
+    let (account, oid) = saga.finish(result).await?;
+    let profile = <Entity<Profile>>::try_find_one_by_id(state.mongo_db.clone(), oid)
+        .await?
+        .ok_or(Error::NotFound("no inserted profile".to_string()))?;
+    verify::send_token(state, &account, profile);
     Ok((StatusCode::CREATED, ObjectIdDef::pack(oid)))
 }
 
@@ -129,11 +172,13 @@ async fn dropout(
     let profile = Profile::get(state.mongo_db.clone(), &email)
         .await?
         .ok_or(Error::NotFound(format!("no profile with email {}", email)))?;
-    account.delete(&state.sql_db).await.map_err(Error::from)?;
-    Profile::delete(state.mongo_db, profile)
-        .await
-        .map_err(Error::from)
-        .map(Json)
+    let txn = state.sql_db.begin().await?;
+    account.delete(&txn).await?;
+    // The account comes back if the profile cannot be deleted. The profile cannot come back,
+    // so if committing fails after all, the account is left for `saga::repair_orphans`.
+    let deleted = Profile::delete(&state.mongo_client, state.mongo_db, profile).await?;
+    txn.commit().await?;
+    Ok(Json(deleted))
 }
 
 fn tag(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
@@ -146,6 +191,7 @@ pub(super) fn route() -> ApiRouter<AppState> {
             "/signup",
             routing::post_with(signup, |op| {
                 op.summary("account register")
+                    .description("the account can log in after its email address is verified")
                     .response::<201, ObjectIdDef>()
             }),
             tag,
@@ -161,5 +207,9 @@ pub(super) fn route() -> ApiRouter<AppState> {
             tag,
         )
         .nest("/", logio::route())
+        .nest("/accounts", admin::route())
+        .nest("/password", password::route())
         .nest("/profile", profile::route())
+        .nest("/tokens", api_tokens::route())
+        .nest("/verify", verify::route())
 }
@@ -0,0 +1,183 @@
+use ::notice::email::{Address, AddressDef};
+use aide::axum::{routing, ApiRouter};
+use axum::{debug_handler, extract::State, http::StatusCode};
+use axum_jsonschema::Json;
+use mongo::oid::ObjectId;
+use schemars::JsonSchema;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::Deserialize;
+
+use crate::{
+    mongo_entities::profile::Profile,
+    routes::common::notice,
+    sql_entities::{account, api_token, prelude::ApiToken},
+    state::AppState,
+};
+
+use super::{
+    super::common::{
+        auth::AuthInfoStorage,
+        docs,
+        err::{Error, Result},
+    },
+    token::{Purpose, Token},
+    tools,
+};
+
+/// Changes whenever the password is set, so a reset token works only once.
+fn stamp(account: &account::Model) -> Vec<u8> {
+    let mut stamp = account.password_hash.clone();
+    stamp.extend(account.updated_at.timestamp_micros().to_be_bytes());
+    stamp
+}
+
+async fn log_out_everywhere(state: &AppState, id: ObjectId) -> Result<()> {
+    state
+        .sessions
+        .destroy_owned_by(id)
+        .await
+        .map_err(|e| Error::Common(e.to_string()))
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct ChangeBody {
+    #[schemars(title = "Old Password", length(min = 1, max = 72))]
+    old_password: String,
+    #[schemars(
+        title = "New Password",
+        description = "Hash by yourself!",
+        length(min = 1, max = 72)
+    )]
+    new_password: String,
+}
+
+#[debug_handler]
+async fn change(
+    mut auth_info_storage: AuthInfoStorage,
+    State(state): State<AppState>,
+    Json(body): Json<ChangeBody>,
+) -> Result<StatusCode> {
+    let auth_info = auth_info_storage.load()?;
+    let email = tools::email_of(&state, auth_info.id).await?;
+    let account = tools::try_find_account(&state.sql_db, &email)
+        .await?
+        .ok_or(Error::NotFound(format!("no account with email {}", email)))?;
+    tools::check_password(state.hash_cost, &account, body.old_password).await?;
+    tools::set_password(&state, account, body.new_password).await?;
+    log_out_everywhere(&state, auth_info.id).await?;
+    // This session goes on under a new id, since the old one has just been destroyed.
+    auth_info_storage.regenerate();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct ForgotBody {
+    #[schemars(with = "AddressDef")]
+    email: Address,
+}
+
+/// Answers alike whether there is an account with the email address or not, so that nobody can
+/// tell which addresses have one.
+#[debug_handler]
+async fn forgot(State(state): State<AppState>, Json(body): Json<ForgotBody>) -> Result<StatusCode> {
+    let email = body.email;
+    let Some(account) = tools::try_find_account(&state.sql_db, &email.to_string()).await? else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+    let Some(profile) = Profile::get(state.mongo_db.clone(), &email).await? else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+    let (token, expires_at) = Token::issue(
+        &state.secret,
+        Purpose::PasswordReset,
+        &account.email,
+        state.reset_ttl,
+        &stamp(&account),
+    );
+    tokio::spawn(notice::send_email(
+        state,
+        profile,
+        "reset your password",
+        format!(
+            "Your password reset token is\n\n{}\n\nIt can be used once until {} UTC.",
+            token, expires_at
+        ),
+    ));
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct ResetBody {
+    #[schemars(title = "Token", description = "Sent to the email address.")]
+    token: String,
+    #[schemars(
+        title = "New Password",
+        description = "Hash by yourself!",
+        length(min = 1, max = 72)
+    )]
+    password: String,
+}
+
+#[debug_handler]
+async fn reset(State(state): State<AppState>, Json(body): Json<ResetBody>) -> Result<StatusCode> {
+    let token = Token::parse(&body.token, Purpose::PasswordReset)?;
+    let account = tools::try_find_account(&state.sql_db, &token.email.to_string())
+        .await?
+        .ok_or(Error::NotFound(format!(
+            "no account with email {}",
+            token.email
+        )))?;
+    token.verify(&state.secret, &stamp(&account))?;
+    let account = tools::set_password(&state, account, body.password).await?;
+    // Whoever knew the old password may have made tokens with it.
+    ApiToken::delete_many()
+        .filter(api_token::Column::Email.eq(account.email))
+        .exec(&state.sql_db)
+        .await
+        .map_err(Error::from)?;
+    if let Some(profile) = Profile::get(state.mongo_db.clone(), &token.email).await? {
+        log_out_everywhere(&state, profile._id).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn tag(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
+    op.tag("password")
+}
+
+pub(super) fn route() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route_with(
+            "/",
+            routing::patch_with(change, |op| {
+                op.summary("change my password")
+                    .description("other sessions of the account are logged out")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .response_with::<204, (), _>(|res| res.description("the password is changed"))
+            }),
+            tag,
+        )
+        .api_route_with(
+            "/forgot",
+            routing::post_with(forgot, |op| {
+                op.summary("mail a token to reset a forgotten password")
+                    .description("nothing is sent unless the address has an account, though the answer is the same")
+                    .response_with::<202, (), _>(|res| {
+                        res.description("the token is being sent, if there is anyone to send it to")
+                    })
+            }),
+            tag,
+        )
+        .api_route_with(
+            "/reset",
+            routing::post_with(reset, |op| {
+                op.summary("reset a forgotten password by the mailed token")
+                    .description("all sessions of the account are logged out, and its personal access tokens revoked")
+                    .response_with::<204, (), _>(|res| res.description("the password is changed"))
+            }),
+            tag,
+        )
+}
@@ -15,7 +15,10 @@ use super::super::common::{
     handlers::{self, ShowCfg},
 };
 use crate::{
-    mongo_entities::profile::{Profile, PublicProfile},
+    mongo_entities::{
+        file,
+        profile::{Profile, PublicProfile},
+    },
     state::AppState,
 };
 
@@ -53,12 +56,18 @@ async fn update(
     State(state): State<AppState>,
     Json(body): Json<<Profile as Patchable>::Patch>,
 ) -> Result<Res> {
-    Ok(Json(
-        Entity::set_by_id(state.mongo_db, auth_info.id, body)
-            .await?
-            .ok_or(Error::NotFound("no object id after update".to_string()))?
-            .into(),
-    ))
+    let old_avatar_id = <Entity<Profile>>::try_find_one_by_id(state.mongo_db.clone(), auth_info.id)
+        .await?
+        .and_then(|profile| profile.data.bio.avatar_id);
+    let profile = <Entity<Profile>>::set_by_id(state.mongo_db.clone(), auth_info.id, body)
+        .await?
+        .ok_or(Error::NotFound("no object id after update".to_string()))?;
+    if let Some(old_avatar_id) = old_avatar_id {
+        if profile.data.bio.avatar_id != Some(old_avatar_id) {
+            file::release(state.mongo_db, [old_avatar_id]).await?;
+        }
+    }
+    Ok(Json(profile.into()))
 }
 
 pub(super) fn route() -> ApiRouter<AppState> {
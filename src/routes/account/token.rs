@@ -0,0 +1,105 @@
+//! Signed tokens mailed to account owners.
+//!
+//! A token carries its purpose, the email address and an expiry time, and is signed with the
+//! server secret together with a stamp taken from the account. Changing what the stamp is made
+//! of, e.g. the password hash, invalidates every token issued before.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use notice::email::Address;
+use sha2::Sha256;
+
+use super::super::common::err::{Error, Result};
+
+#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(super) enum Purpose {
+    Verification,
+    PasswordReset,
+}
+
+impl Purpose {
+    fn as_str(self) -> &'static str {
+        match self {
+            Purpose::Verification => "verify",
+            Purpose::PasswordReset => "reset",
+        }
+    }
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub(super) struct Token {
+    payload: String,
+    mac: Vec<u8>,
+    pub(super) email: Address,
+}
+
+fn mac(key: &[u8], payload: &str, stamp: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256>>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    mac.update(&[0]);
+    mac.update(stamp);
+    mac
+}
+
+impl Token {
+    pub(super) fn issue(
+        key: &[u8],
+        purpose: Purpose,
+        email: &str,
+        ttl: u64,
+        stamp: &[u8],
+    ) -> (String, NaiveDateTime) {
+        let expires_at = Utc::now().timestamp() + ttl as i64;
+        let payload = format!("{}:{}:{}", purpose.as_str(), expires_at, email);
+        let signature = mac(key, &payload, stamp).finalize().into_bytes();
+        (
+            format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(&payload),
+                URL_SAFE_NO_PAD.encode(signature)
+            ),
+            NaiveDateTime::from_timestamp_opt(expires_at, 0).unwrap_or_default(),
+        )
+    }
+
+    /// Reads a token for `purpose` without checking its signature yet, since the stamp has to
+    /// be looked up by the email in it.
+    pub(super) fn parse(token: &str, purpose: Purpose) -> Result<Self> {
+        let invalid = || Error::BadReqest("invalid token".to_string());
+        let (payload, mac) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?)
+            .map_err(|_| invalid())?;
+        let mac = URL_SAFE_NO_PAD.decode(mac).map_err(|_| invalid())?;
+        let mut fields = payload.splitn(3, ':');
+        if fields.next() != Some(purpose.as_str()) {
+            return Err(invalid());
+        }
+        let expires_at = fields
+            .next()
+            .and_then(|expires_at| expires_at.parse().ok())
+            .and_then(|expires_at| NaiveDateTime::from_timestamp_opt(expires_at, 0))
+            .ok_or_else(invalid)?;
+        let email = fields
+            .next()
+            .and_then(|email| email.parse().ok())
+            .ok_or_else(invalid)?;
+        if expires_at < Utc::now().naive_utc() {
+            return Err(Error::BadReqest("token expired".to_string()));
+        }
+        Ok(Self {
+            payload,
+            mac,
+            email,
+        })
+    }
+
+    pub(super) fn verify(&self, key: &[u8], stamp: &[u8]) -> Result<()> {
+        mac(key, &self.payload, stamp)
+            .verify_slice(&self.mac)
+            .map_err(|_| Error::BadReqest("invalid token".to_string()))
+    }
+}
@@ -1,7 +1,16 @@
+use chrono::Utc;
 use passwords::hasher;
-use sea_orm::{DatabaseConnection, EntityTrait};
+use sea_orm::{
+    prelude::Uuid, ActiveModelTrait, ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel,
+};
 
-use crate::sql_entities::{account, prelude::Account};
+use mongo::{entity::Entity, oid::ObjectId};
+
+use crate::{
+    mongo_entities::profile::Profile,
+    sql_entities::{account, prelude::Account},
+    state::AppState,
+};
 
 use super::super::common::err::{Error, Result};
 
@@ -21,3 +30,45 @@ pub(super) async fn try_find_account(
         .await
         .map_err(Error::from)
 }
+
+/// The email address of the account owning the profile `id`.
+pub(super) async fn email_of(state: &AppState, id: ObjectId) -> Result<String> {
+    Ok(
+        <Entity<Profile>>::try_find_one_by_id(state.mongo_db.clone(), id)
+            .await?
+            .ok_or(Error::NotFound("no profile".to_string()))?
+            .data
+            .email
+            .to_string(),
+    )
+}
+
+pub(super) async fn check_password(
+    cost: u8,
+    account: &account::Model,
+    password: String,
+) -> Result<()> {
+    if get_hash(cost, account.salt.into_bytes(), password)
+        .await?
+        .to_vec()
+        != account.password_hash
+    {
+        return Err(Error::BadReqest("wrong password".to_string()));
+    }
+    Ok(())
+}
+
+/// Hashes `password` with a new salt into `account`.
+pub(super) async fn set_password(
+    state: &AppState,
+    account: account::Model,
+    password: String,
+) -> Result<account::Model> {
+    let salt = hasher::gen_salt();
+    let mut account = account.into_active_model();
+    account.salt = ActiveValue::Set(Uuid::from_bytes(salt));
+    account.password_hash =
+        ActiveValue::Set(get_hash(state.hash_cost, salt, password).await?.into());
+    account.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+    account.update(&state.sql_db).await.map_err(Error::from)
+}
@@ -0,0 +1,133 @@
+use ::notice::email::{Address, AddressDef};
+use aide::axum::{routing, ApiRouter};
+use axum::{debug_handler, extract::State, http::StatusCode};
+use axum_jsonschema::Json;
+use chrono::Utc;
+use mongo::entity::Entity;
+use schemars::JsonSchema;
+use sea_orm::{ActiveModelTrait, ActiveValue, IntoActiveModel};
+use serde::Deserialize;
+
+use crate::{
+    mongo_entities::profile::Profile, routes::common::notice, sql_entities::account,
+    state::AppState,
+};
+
+use super::{
+    super::common::err::{Error, Result},
+    token::{Purpose, Token},
+    tools,
+};
+
+fn stamp(account: &account::Model) -> [u8; 8] {
+    account.created_at.timestamp().to_be_bytes()
+}
+
+/// Mails a new verification token to the owner of `account`.
+pub(super) fn send_token(state: AppState, account: &account::Model, profile: Entity<Profile>) {
+    let (token, expires_at) = Token::issue(
+        &state.secret,
+        Purpose::Verification,
+        &account.email,
+        state.verification_ttl,
+        &stamp(account),
+    );
+    tokio::spawn(notice::send_email(
+        state,
+        profile,
+        "verify your email",
+        format!(
+            "Your verification token is\n\n{}\n\nIt expires at {} UTC.",
+            token, expires_at
+        ),
+    ));
+}
+
+/// Whether `account` was never verified and its last token has expired.
+pub(super) fn is_stale(state: &AppState, account: &account::Model) -> bool {
+    account.verified_at.is_none()
+        && account.updated_at.timestamp() + (state.verification_ttl as i64) < Utc::now().timestamp()
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct VerifyBody {
+    #[schemars(title = "Token", description = "Sent to the email address at signup.")]
+    token: String,
+}
+
+#[debug_handler]
+async fn verify(State(state): State<AppState>, Json(body): Json<VerifyBody>) -> Result<StatusCode> {
+    let token = Token::parse(&body.token, Purpose::Verification)?;
+    let account = tools::try_find_account(&state.sql_db, &token.email.to_string())
+        .await?
+        .ok_or(Error::NotFound(format!(
+            "no account with email {}",
+            token.email
+        )))?;
+    token.verify(&state.secret, &stamp(&account))?;
+    if account.verified_at.is_some() {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    let now = Utc::now().naive_utc();
+    let mut account = account.into_active_model();
+    account.verified_at = ActiveValue::Set(Some(now));
+    account.updated_at = ActiveValue::Set(now);
+    account.update(&state.sql_db).await.map_err(Error::from)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct ResendBody {
+    #[schemars(with = "AddressDef")]
+    email: Address,
+}
+
+/// Answers alike whether there is an unverified account with the email address or not, so that
+/// nobody can tell which addresses have one.
+#[debug_handler]
+async fn resend(State(state): State<AppState>, Json(body): Json<ResendBody>) -> Result<StatusCode> {
+    let email = body.email;
+    let Some(account) = tools::try_find_account(&state.sql_db, &email.to_string()).await? else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+    if account.verified_at.is_some() {
+        return Ok(StatusCode::ACCEPTED);
+    }
+    let Some(profile) = Profile::get(state.mongo_db.clone(), &email).await? else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+    let mut account = account.into_active_model();
+    account.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+    let account = account.update(&state.sql_db).await.map_err(Error::from)?;
+    send_token(state, &account, profile);
+    Ok(StatusCode::ACCEPTED)
+}
+
+fn tag(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
+    op.tag("email verification")
+}
+
+pub(super) fn route() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route_with(
+            "/",
+            routing::post_with(verify, |op| {
+                op.summary("verify the email address of a new account")
+                    .response_with::<204, (), _>(|res| res.description("the account is verified"))
+            }),
+            tag,
+        )
+        .api_route_with(
+            "/resend",
+            routing::post_with(resend, |op| {
+                op.summary("mail a new verification token")
+                    .description("tokens sent before are still valid until they expire; nothing is sent unless the address has an unverified account, though the answer is the same")
+                    .response_with::<202, (), _>(|res| {
+                        res.description("the token is being sent, if there is anyone to send it to")
+                    })
+            }),
+            tag,
+        )
+}
@@ -1,85 +1,384 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+    ops::{Deref, DerefMut},
+    str::FromStr,
+};
 
-use aide::OperationIo;
+use aide::{gen::GenContext, openapi::Operation, OperationInput, OperationIo};
 use async_trait::async_trait;
 use axum::{extract::FromRequestParts, http::request::Parts};
 use axum_sessions::{
     async_session::Session,
     extractors::{ReadableSession, WritableSession},
 };
-use mongo::oid::ObjectId;
+use chrono::Utc;
+use mongo::{
+    oid::{serialize_object_id_as_hex_string, ObjectId, ObjectIdDef},
+    MongoDatabase, MongoResult,
+};
+use schemars::JsonSchema;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
 use tokio::sync::OwnedRwLockWriteGuard;
 
-use super::err::{Error, Result};
+use crate::{
+    mongo_entities::paper_collection::{category::Category, magazine::Magazine, PaperCollection},
+    session,
+    sql_entities::{account_grant, prelude::AccountGrant},
+    state::AppState,
+};
+
+use super::{
+    bearer, docs,
+    err::{Error, Result},
+};
 
+/// What a [`Role`] allows to do.
 #[derive(Eq, PartialEq)]
 #[derive(Copy, Clone)]
 #[derive(Debug)]
-#[repr(usize)]
-pub(crate) enum Permission {
-    Managing = 0,
-    Publishing = 1,
-    Nonexistent = 2,
+pub(crate) enum Action {
+    /// Setting up magazines and categories, and administrating accounts when global.
+    Manage,
+    /// Seeing every thesis and its versions. Editing and adjudging them is left to the boards of
+    /// the magazines.
+    Publish,
+    /// Being assigned to review versions.
+    Review,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum Role {
+    Administrator,
+    Editor,
+    Reviewer,
+}
+
+impl Role {
+    fn allows(self, action: Action) -> bool {
+        matches!(
+            (self, action),
+            (Role::Administrator, Action::Manage)
+                | (Role::Editor, Action::Publish)
+                | (Role::Reviewer, Action::Review)
+        )
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Role::Administrator => "administrator",
+            Role::Editor => "editor",
+            Role::Reviewer => "reviewer",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [Role::Administrator, Role::Editor, Role::Reviewer]
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or(Error::Common(format!("unknown role {}", s)))
+    }
+}
+
+/// Where a [`Role`] applies.
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "lowercase")]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum Scope {
+    Global,
+    Magazine(
+        #[schemars(with = "ObjectIdDef")]
+        #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+        ObjectId,
+    ),
+    /// The category, its subcategories and their magazines.
+    Category(
+        #[schemars(with = "ObjectIdDef")]
+        #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+        ObjectId,
+    ),
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Global => write!(f, "global"),
+            Scope::Magazine(id) => write!(f, "magazine:{}", id),
+            Scope::Category(id) => write!(f, "category:{}", id),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let unknown = || Error::Common(format!("unknown scope {}", s));
+        Ok(match s.split_once(':') {
+            None if s == "global" => Scope::Global,
+            Some(("magazine", id)) => Scope::Magazine(id.parse().map_err(|_| unknown())?),
+            Some(("category", id)) => Scope::Category(id.parse().map_err(|_| unknown())?),
+            _ => return Err(unknown()),
+        })
+    }
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) struct Grant {
+    pub(crate) role: Role,
+    pub(crate) scope: Scope,
+}
+
+impl TryFrom<account_grant::Model> for Grant {
+    type Error = Error;
+
+    fn try_from(model: account_grant::Model) -> Result<Self> {
+        Ok(Self {
+            role: model.role.parse()?,
+            scope: model.scope.parse()?,
+        })
+    }
+}
+
+impl Grant {
+    pub(crate) fn into_active_model(self, email: String) -> account_grant::ActiveModel {
+        account_grant::ActiveModel {
+            email: ActiveValue::Set(email),
+            role: ActiveValue::Set(self.role.as_str().to_string()),
+            scope: ActiveValue::Set(self.scope.to_string()),
+            created_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn filter(self) -> Condition {
+        Condition::all()
+            .add(account_grant::Column::Role.eq(self.role.as_str()))
+            .add(account_grant::Column::Scope.eq(self.scope.to_string()))
+    }
+
+    pub(crate) async fn load(sql_db: &DatabaseConnection, email: &str) -> Result<BTreeSet<Self>> {
+        AccountGrant::find()
+            .filter(account_grant::Column::Email.eq(email))
+            .all(sql_db)
+            .await?
+            .into_iter()
+            .map(Self::try_from)
+            .collect()
+    }
+}
+
+/// The magazines and categories an object is filed under, to be matched with scoped grants.
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub(crate) struct Resource {
+    collection_ids: BTreeSet<ObjectId>,
+}
+
+impl Resource {
+    /// Something only global grants apply to.
+    pub(crate) fn global() -> Self {
+        Self::default()
+    }
+
+    /// Something filed under these magazines or categories, and so under all categories above.
+    pub(crate) async fn under(
+        db: MongoDatabase,
+        collection_ids: impl IntoIterator<Item = ObjectId>,
+    ) -> MongoResult<Self> {
+        Ok(Self {
+            collection_ids: Category::ancestor_ids(db, collection_ids.into_iter().collect())
+                .await?,
+        })
+    }
 }
 
-#[derive(OperationIo)]
 #[derive(Serialize, Deserialize)]
+#[serde(from = "StoredAuthInfo")]
 #[derive(Eq, PartialEq)]
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 #[derive(Debug)]
 pub(crate) struct AuthInfo {
     pub(crate) id: ObjectId,
-    roles: [bool; 2],
+    grants: BTreeSet<Grant>,
+    /// Whether it was read from a bearer token rather than the session.
+    #[serde(skip)]
+    by_token: bool,
 }
 
 impl AuthInfo {
-    pub(crate) fn permitted(&self, role: Permission) -> bool {
-        self.roles
-            .get(role as usize)
-            .map(bool::to_owned)
-            .unwrap_or_default()
+    pub(crate) fn new(id: ObjectId, grants: BTreeSet<Grant>) -> Self {
+        Self {
+            id,
+            grants,
+            by_token: false,
+        }
+    }
+
+    /// Marks it as read from a bearer token.
+    pub(crate) fn by_token(self) -> Self {
+        Self {
+            by_token: true,
+            ..self
+        }
+    }
+
+    pub(crate) fn is_by_token(&self) -> bool {
+        self.by_token
+    }
+
+    pub(crate) fn can(&self, action: Action, resource: &Resource) -> bool {
+        self.grants.iter().any(|grant| {
+            grant.role.allows(action)
+                && match grant.scope {
+                    Scope::Global => true,
+                    Scope::Magazine(id) | Scope::Category(id) => {
+                        resource.collection_ids.contains(&id)
+                    }
+                }
+        })
+    }
+
+    /// The magazines and categories where `action` is allowed, each as [`AuthInfo::can`] tells
+    /// for it alone, or `None` if it is allowed everywhere.
+    pub(crate) async fn collection_ids_for(
+        &self,
+        db: MongoDatabase,
+        action: Action,
+    ) -> MongoResult<Option<BTreeSet<ObjectId>>> {
+        if self.can(action, &Resource::global()) {
+            return Ok(None);
+        }
+        let mut collection_ids = BTreeSet::new();
+        let mut category_ids = BTreeSet::new();
+        for grant in self.grants.iter().filter(|grant| grant.role.allows(action)) {
+            match grant.scope {
+                Scope::Global => {}
+                Scope::Magazine(id) => {
+                    collection_ids.insert(id);
+                }
+                Scope::Category(id) => {
+                    category_ids.extend(Category::subtree_ids(db.clone(), id).await?);
+                }
+            }
+        }
+        collection_ids
+            .extend(<PaperCollection<Magazine>>::ids_in_categories(db, &category_ids).await?);
+        collection_ids.extend(category_ids);
+        Ok(Some(collection_ids))
+    }
+}
+
+/// [`AuthInfo`] as kept in a session, now or before there were grants.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredAuthInfo {
+    Granted {
+        id: ObjectId,
+        grants: BTreeSet<Grant>,
+    },
+    /// Whether the account administrated and edited everything, back when anyone could review.
+    Flagged { id: ObjectId, roles: [bool; 2] },
+}
+
+impl From<StoredAuthInfo> for AuthInfo {
+    fn from(stored: StoredAuthInfo) -> Self {
+        match stored {
+            StoredAuthInfo::Granted { id, grants } => Self::new(id, grants),
+            StoredAuthInfo::Flagged {
+                id,
+                roles: [is_administrator, is_editor],
+            } => {
+                let roles = [
+                    (is_administrator, Role::Administrator),
+                    (is_editor, Role::Editor),
+                    (true, Role::Reviewer),
+                ];
+                let grants = roles
+                    .into_iter()
+                    .filter(|&(held, _)| held)
+                    .map(|(_, role)| Grant {
+                        role,
+                        scope: Scope::Global,
+                    })
+                    .collect();
+                Self::new(id, grants)
+            }
+        }
     }
 }
 
+/// Read from a bearer token if there is one, or else from the session.
 #[async_trait]
-impl<S> FromRequestParts<S> for AuthInfo
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<AppState> for AuthInfo {
     type Rejection = Error;
 
     async fn from_request_parts(
         parts: &mut Parts,
-        state: &S,
+        state: &AppState,
     ) -> std::result::Result<Self, Self::Rejection> {
+        if let Some(token) = bearer::token(parts)? {
+            return bearer::authenticate(state, parts, token).await;
+        }
         ReadableSession::from_request_parts(parts, state)
             .await
             .map_err(Error::from)?
-            .get::<Self>("auth_info")
+            .get::<Self>(session::AUTH_INFO_KEY)
             .ok_or(Error::Forbidden("Invalid cookie!".to_string()))
     }
 }
 
+/// Routes taking [`AuthInfo`] accept a bearer token besides the cookie they declare.
+impl OperationInput for AuthInfo {
+    fn operation_input(_ctx: &mut GenContext, operation: &mut Operation) {
+        if !operation
+            .security
+            .iter()
+            .any(|requirement| requirement.contains_key(docs::BEARER_SCHEME_NAME))
+        {
+            operation.security.push(
+                [(docs::BEARER_SCHEME_NAME.to_string(), Vec::new())]
+                    .into_iter()
+                    .collect(),
+            );
+        }
+    }
+}
+
 #[derive(OperationIo)]
 #[derive(Debug)]
 pub(crate) struct AuthInfoStorage(WritableSession);
 
 impl AuthInfoStorage {
-    pub(crate) fn store(
-        &mut self,
-        id: ObjectId,
-        is_administrator: bool,
-        is_editor: bool,
-    ) -> Result<()> {
+    /// Reads who is logged in, for handlers that also write the session and so cannot extract
+    /// [`AuthInfo`] at the same time.
+    pub(crate) fn load(&self) -> Result<AuthInfo> {
+        self.0
+            .get::<AuthInfo>(session::AUTH_INFO_KEY)
+            .ok_or(Error::Forbidden("Invalid cookie!".to_string()))
+    }
+
+    pub(crate) fn store(&mut self, auth_info: &AuthInfo) -> Result<()> {
         self.0
-            .insert(
-                "auth_info",
-                AuthInfo {
-                    id,
-                    roles: [is_administrator, is_editor],
-                },
-            )
+            .insert(session::AUTH_INFO_KEY, auth_info)
             .map_err(Error::from)
     }
 }
@@ -114,3 +413,30 @@ where
             .map(Self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_from_before_grants_are_read() {
+        let id = ObjectId::new();
+        let stored = serde_json::json!({"id": id, "roles": [true, false]});
+        let auth_info: AuthInfo = serde_json::from_value(stored).unwrap();
+        assert_eq!(auth_info.id, id);
+        assert!(auth_info.can(Action::Manage, &Resource::global()));
+        assert!(!auth_info.can(Action::Publish, &Resource::global()));
+        assert!(auth_info.can(Action::Review, &Resource::global()));
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let grants = BTreeSet::from([Grant {
+            role: Role::Editor,
+            scope: Scope::Magazine(ObjectId::new()),
+        }]);
+        let auth_info = AuthInfo::new(ObjectId::new(), grants);
+        let stored = serde_json::to_value(&auth_info).unwrap();
+        assert_eq!(serde_json::from_value::<AuthInfo>(stored).unwrap(), auth_info);
+    }
+}
@@ -0,0 +1,184 @@
+//! Personal access tokens sent as `Authorization: Bearer`.
+//!
+//! Only a SHA-256 hash of each token is stored, so a token can be shown once at creation and
+//! never again.
+
+use std::{collections::BTreeSet, str::FromStr};
+
+use axum::{
+    extract::OriginalUri,
+    http::{header::AUTHORIZATION, request::Parts, Method},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use passwords::hasher;
+use schemars::JsonSchema;
+use sea_orm::{ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    mongo_entities::profile::Profile,
+    sql_entities::{account, api_token, prelude::ApiToken},
+    state::AppState,
+};
+
+use super::{
+    auth::{AuthInfo, Grant},
+    err::{Error, Result},
+};
+
+const PREFIX: &str = "pp_";
+
+/// In seconds, how long `last_used_at` may lag behind, so that a busy token is not written on
+/// every request.
+const LAST_USED_RESOLUTION: i64 = 60;
+
+/// What a token may be used for. A token without scopes may be used for everything.
+#[derive(JsonSchema)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum TokenScope {
+    /// Reading, i.e. `GET` and `HEAD` requests.
+    Read,
+    /// Committing new versions of theses.
+    Commit,
+    /// Every other change, committing included.
+    Write,
+}
+
+impl TokenScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Commit => "commit",
+            TokenScope::Write => "write",
+        }
+    }
+
+    /// The scope a request needs.
+    fn of(parts: &Parts) -> Self {
+        let path = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map_or(parts.uri.path(), |uri| uri.path());
+        if matches!(parts.method, Method::GET | Method::HEAD) {
+            TokenScope::Read
+        } else if parts.method == Method::POST && path.ends_with("/commit") {
+            TokenScope::Commit
+        } else {
+            TokenScope::Write
+        }
+    }
+
+    fn covers(self, needed: Self) -> bool {
+        self == needed || (self, needed) == (TokenScope::Write, TokenScope::Commit)
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [TokenScope::Read, TokenScope::Commit, TokenScope::Write]
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or(Error::Common(format!("unknown token scope {}", s)))
+    }
+}
+
+pub(crate) fn join_scopes(scopes: &BTreeSet<TokenScope>) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub(crate) fn split_scopes(scopes: &str) -> Result<BTreeSet<TokenScope>> {
+    scopes
+        .split(',')
+        .filter(|scope| !scope.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// A new random token and the hash to store for it.
+pub(crate) fn generate() -> (String, Vec<u8>) {
+    let mut bytes = hasher::gen_salt().to_vec();
+    bytes.extend(hasher::gen_salt());
+    let token = format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(bytes));
+    let hash = hash(&token);
+    (token, hash)
+}
+
+fn hash(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// The bearer token of the request, if there is one.
+pub(super) fn token(parts: &Parts) -> Result<Option<&str>> {
+    let Some(value) = parts.headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| Some(token.trim()))
+        .ok_or(Error::Forbidden(
+            "Invalid authorization header!".to_string(),
+        ))
+}
+
+/// Who sent `token`, as long as the token is alive and its scopes cover the request.
+pub(super) async fn authenticate(state: &AppState, parts: &Parts, token: &str) -> Result<AuthInfo> {
+    let invalid = || Error::Forbidden("Invalid token!".to_string());
+    let now = Utc::now().naive_utc();
+    let (model, account) = ApiToken::find()
+        .filter(api_token::Column::TokenHash.eq(hash(token)))
+        .find_also_related(account::Entity)
+        .one(&state.sql_db)
+        .await
+        .map_err(Error::from)?
+        .ok_or_else(invalid)?;
+    let account = account.ok_or_else(invalid)?;
+    if model.expires_at.is_some_and(|expires_at| expires_at < now) {
+        return Err(Error::Forbidden("Token expired!".to_string()));
+    }
+    if account.locked_at.is_some() || account.verified_at.is_none() {
+        return Err(invalid());
+    }
+    let scopes = split_scopes(&model.scopes)?;
+    let needed = TokenScope::of(parts);
+    if !scopes.is_empty() && !scopes.iter().any(|scope| scope.covers(needed)) {
+        return Err(Error::Forbidden(format!(
+            "the token has no {} scope",
+            needed.as_str()
+        )));
+    }
+    if model.last_used_at.is_none_or(|last_used_at| {
+        (now - last_used_at).num_seconds() >= LAST_USED_RESOLUTION
+    }) {
+        ApiToken::update(api_token::ActiveModel {
+            id: ActiveValue::Unchanged(model.id),
+            last_used_at: ActiveValue::Set(Some(now)),
+            ..Default::default()
+        })
+        .exec(&state.sql_db)
+        .await
+        .map_err(Error::from)?;
+    }
+    let email = account.email.parse().map_err(|_| invalid())?;
+    let profile = Profile::get(state.mongo_db.clone(), &email)
+        .await?
+        .ok_or_else(invalid)?;
+    Ok(AuthInfo::new(
+        profile._id,
+        Grant::load(&state.sql_db, &account.email).await?,
+    )
+    .by_token())
+}
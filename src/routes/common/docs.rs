@@ -29,7 +29,7 @@ pub(crate) fn add_one_parameter(
                 format: ParameterSchemaOrContent::Schema(SchemaObject {
                     json_schema: schema_for!(String).schema.into(),
                     external_docs: None,
-                    example: example,
+                    example,
                 }),
                 example: None,
                 examples: Default::default(),
@@ -55,3 +55,5 @@ pub(crate) fn add_one_oid_parameter(
 }
 
 pub(crate) const SECURITY_SCHEME_NAME: &str = "cookieAuth";
+
+pub(crate) const BEARER_SCHEME_NAME: &str = "bearerAuth";
@@ -6,6 +6,8 @@ use axum::{
 use mongo::MongoError;
 use thiserror::Error;
 
+use crate::mongo_entities::{review::form::ReviewFormError, version::workflow::TransitionError};
+
 #[derive(OperationIo)]
 #[aide(output)]
 #[derive(Error, Debug)]
@@ -13,7 +15,7 @@ pub(crate) enum Error {
     #[error("i/o error: {0}")]
     IO(#[from] std::io::Error),
     #[error("PostgreSQL error: {0}")]
-    SQL(#[from] sea_orm::DbErr),
+    Sql(#[from] sea_orm::DbErr),
     #[error("MongoDB error: {0}")]
     Mongo(#[from] MongoError),
     #[error("session error: {0}")]
@@ -34,6 +36,24 @@ pub(crate) enum Error {
     NotFound(String),
     #[error("{0}")]
     Conflict(String),
+    #[error("{0}")]
+    TooLarge(String),
+}
+
+impl From<TransitionError> for Error {
+    fn from(e: TransitionError) -> Self {
+        match e {
+            TransitionError::Mongo(e) => Error::Mongo(e),
+            TransitionError::Conflict => Error::Conflict(e.to_string()),
+            TransitionError::Illegal { .. } => Error::BadReqest(e.to_string()),
+        }
+    }
+}
+
+impl From<ReviewFormError> for Error {
+    fn from(e: ReviewFormError) -> Self {
+        Error::BadReqest(e.to_string())
+    }
 }
 
 impl IntoResponse for Error {
@@ -45,12 +65,13 @@ impl IntoResponse for Error {
             Error::Session(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Error::Json(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Error::Conflict(e) => (StatusCode::CONFLICT, e),
+            Error::TooLarge(e) => (StatusCode::PAYLOAD_TOO_LARGE, e),
             Error::BadReqest(e) => (StatusCode::BAD_REQUEST, e),
             Error::Multipart(e) => (e.status(), e.body_text()),
             Error::IO(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Error::Task(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Error::Common(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
-            Error::SQL(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            Error::Sql(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
         }
         .into_response()
     }
@@ -1,67 +1,774 @@
+use std::collections::{btree_map, BTreeMap, BTreeSet};
+
 use axum::{
     body::StreamBody,
     extract::Multipart,
-    http::{header, HeaderName},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use mongo::{
+    bson::{self, doc},
+    gridfs::{self, FilesCollectionDocument, UploadError},
+    oid::{ObjectId, ObjectIdDef},
+    MongoDatabase, MongoError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    archive::{self, ArchiveError, ArchiveFormat, Entry},
+    inflate::{self, InflateError},
+    sniff::{self, FileType},
+    state::AppState,
 };
-use futures_codec::{BytesCodec, FramedRead};
-use futures_util::{Stream, TryStreamExt};
-use mongo::{gridfs, oid::ObjectId, MongoDatabase};
 
 use super::err::{Error, Result};
 
-pub(crate) async fn upload_files(
-    db: MongoDatabase,
+/// Streams every file of `multipart` into GridFS, within the size limits of `state`, as long as
+/// the `n`th one is of a type in `allowed(n)`.
+///
+/// The files stored so far are deleted if one of them fails.
+pub(crate) async fn upload_files<'a>(
+    state: &AppState,
     multipart: &mut Multipart,
+    allowed: impl Fn(usize) -> &'a BTreeSet<FileType>,
 ) -> Result<Vec<ObjectId>> {
     let mut file_ids = Vec::new();
-    let bucket = db.gridfs_bucket(None);
+    let uploaded = upload_each(state, multipart, allowed, &mut file_ids).await;
+    if uploaded.is_err() {
+        // Whatever cannot be deleted now is left to the file sweep, as nothing refers to it.
+        let bucket = state.mongo_db.gridfs_bucket(None);
+        for &id in &file_ids {
+            gridfs::delete(&bucket, id).await.ok();
+        }
+    }
+    uploaded.map(|_| file_ids)
+}
+
+async fn upload_each<'a>(
+    state: &AppState,
+    multipart: &mut Multipart,
+    allowed: impl Fn(usize) -> &'a BTreeSet<FileType>,
+    file_ids: &mut Vec<ObjectId>,
+) -> Result<()> {
+    let mut total = 0;
     while let Some(field) = multipart.next_field().await.map_err(Error::from)? {
-        file_ids.push(
-            gridfs::upload(
-                &bucket,
-                field
-                    .file_name()
-                    .ok_or(Error::BadReqest("file name needed".to_string()))?
-                    .to_string(),
-                field.size_hint().1.map(|u| u as u32),
-                field.content_type().map(ToString::to_string),
-                &field.bytes().await.map_err(Error::from)?,
-            )
+        let file_name = field
+            .file_name()
+            .ok_or(Error::BadReqest("file name needed".to_string()))?
+            .to_string();
+        let mut content = field.map_err(std::io::Error::other);
+        // What the client says the file is cannot be trusted, so look at it.
+        let mut head = Vec::new();
+        let mut peeked = Vec::new();
+        while head.len() < sniff::PEEK_LEN {
+            let Some(chunk) = content.try_next().await? else {
+                break;
+            };
+            head.extend_from_slice(&chunk);
+            peeked.push(Ok(chunk));
+        }
+        let allowed = allowed(file_ids.len());
+        let file_type = match sniff::detect(&head) {
+            Some(file_type) if allowed.contains(&file_type) => file_type,
+            detected => {
+                return Err(Error::BadReqest(format!(
+                    "{} is {}, but only {} files are allowed here",
+                    file_name,
+                    detected.map_or("of an unknown type".to_string(), |t| t.to_string()),
+                    sniff::join_list(allowed)
+                )))
+            }
+        };
+        let metadata = doc! {"Content-Type": file_type.mime(), "fileType": file_type.to_string()};
+        let content = stream::iter(peeked).chain(content);
+        let left = state.max_request_size.saturating_sub(total);
+        let limit = state.max_file_size.min(left);
+        let uploaded = gridfs::upload(&state.mongo_db, &file_name, metadata, limit, content)
             .await
-            .map_err(Error::from)?
-            .ok_or(Error::NotFound("inserted id not found".to_string()))?,
-        );
+            .map_err(|e| match e {
+                UploadError::Io(e) => Error::from(e),
+                UploadError::Mongo(e) => Error::from(e),
+                UploadError::TooLarge if limit < state.max_file_size => Error::TooLarge(format!(
+                    "the files are larger than {} bytes in all",
+                    state.max_request_size
+                )),
+                UploadError::TooLarge => Error::TooLarge(format!(
+                    "{} is larger than {} bytes",
+                    file_name, state.max_file_size
+                )),
+            })?;
+        total += uploaded.length;
+        file_ids.push(uploaded.id);
+    }
+    Ok(())
+}
+
+/// How a download is presented.
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+#[serde(default)]
+#[derive(Default)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) struct DownloadQuery {
+    #[schemars(
+        title = "Inline",
+        description = "Show the file in the browser instead of saving it."
+    )]
+    inline: bool,
+}
+
+/// What part of the file a request asks for.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+enum Requested {
+    Whole,
+    /// From the first byte to the last one, both included.
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn header_str(headers: &HeaderMap, name: HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Whether the client already has this very file.
+fn not_modified(headers: &HeaderMap, etag: &str, last_modified: DateTime<Utc>) -> bool {
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        return if_none_match.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+    header_str(headers, header::IF_MODIFIED_SINCE)
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+        .is_some_and(|since| last_modified.timestamp() <= since.timestamp())
+}
+
+/// Only a single range is served; anything else gets the whole file.
+fn requested(headers: &HeaderMap, length: u64, etag: &str, last_modified: &str) -> Requested {
+    let Some(range) = header_str(headers, header::RANGE) else {
+        return Requested::Whole;
+    };
+    if let Some(if_range) = header_str(headers, header::IF_RANGE) {
+        if if_range != etag && if_range != last_modified {
+            return Requested::Whole;
+        }
+    }
+    let Some((first, last)) = range
+        .strip_prefix("bytes=")
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.trim().split_once('-'))
+    else {
+        return Requested::Whole;
+    };
+    let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end),
+        (Ok(start), Err(_)) if last.is_empty() => (start, u64::MAX),
+        (Err(_), Ok(0)) if first.is_empty() => return Requested::Unsatisfiable,
+        (Err(_), Ok(suffix)) if first.is_empty() => (length.saturating_sub(suffix), u64::MAX),
+        _ => return Requested::Whole,
+    };
+    if start < length {
+        Requested::Part(start, end.min(length - 1))
+    } else {
+        Requested::Unsatisfiable
     }
-    Ok(file_ids)
 }
 
+/// `filename` for old clients, with what they may not understand replaced, and `filename*` as
+/// in RFC 5987 for the others.
+fn content_disposition(inline: bool, filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => (b as char).to_string(),
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        if inline { "inline" } else { "attachment" },
+        fallback,
+        encoded
+    )
+}
+
+fn set_header(headers: &mut HeaderMap, name: HeaderName, value: &str) -> Result<()> {
+    headers.insert(
+        name,
+        HeaderValue::from_str(value).map_err(|e| Error::Common(e.to_string()))?,
+    );
+    Ok(())
+}
+
+/// Serves the file, or the range of it asked for, unless the client has it already.
 pub(crate) async fn download_file(
     db: MongoDatabase,
     id: ObjectId,
-) -> Result<(
-    [(HeaderName, String); 3],
-    StreamBody<impl Stream<Item = std::io::Result<Vec<u8>>> + Sized>,
-)> {
-    let bucket = db.gridfs_bucket(None);
-    let (file, stream) = gridfs::download(bucket, id).await?;
-    let file = file.ok_or(Error::NotFound("no file".to_string()))?;
-    let stream = FramedRead::new(stream, BytesCodec).map_ok(|b| b.to_vec());
-    Ok((
-        [
-            (
-                header::CONTENT_DISPOSITION,
-                file.filename
-                    .map(|nm| format!("attachment; filename=\"{}\"", nm))
-                    .unwrap_or_default(),
-            ),
-            (header::CONTENT_LENGTH, file.length.to_string()),
-            (
-                header::CONTENT_TYPE,
-                file.metadata
-                    .and_then(|md| md.get("Content-Type").map(ToString::to_string))
-                    .unwrap_or_default(),
-            ),
-        ],
-        StreamBody::new(stream),
+    headers: &HeaderMap,
+    query: DownloadQuery,
+) -> Result<Response> {
+    let file = gridfs::find_file(&db.gridfs_bucket(None), id)
+        .await?
+        .ok_or(Error::NotFound("no file".to_string()))?;
+    let metadata = file.metadata.clone().unwrap_or_default();
+    let etag = format!(
+        "\"{}\"",
+        metadata
+            .get_str("sha256")
+            .map_or_else(|_| id.to_hex(), ToString::to_string)
+    );
+    let last_modified = file.upload_date.to_chrono();
+    let mut response_headers = HeaderMap::new();
+    let mut set = |name, value: &str| set_header(&mut response_headers, name, value);
+    set(header::ETAG, &etag)?;
+    set(header::LAST_MODIFIED, &http_date(last_modified))?;
+    if not_modified(headers, &etag, last_modified) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    set(header::ACCEPT_RANGES, "bytes")?;
+    set(
+        header::CONTENT_TYPE,
+        metadata
+            .get_str("Content-Type")
+            .unwrap_or("application/octet-stream"),
+    )?;
+    set(
+        header::CONTENT_DISPOSITION,
+        &content_disposition(query.inline, file.filename.as_deref().unwrap_or_default()),
+    )?;
+    let length = file.length;
+    let (status, start, end) = match requested(headers, length, &etag, &http_date(last_modified)) {
+        Requested::Whole => (StatusCode::OK, 0, length.saturating_sub(1)),
+        Requested::Part(start, end) => {
+            set(
+                header::CONTENT_RANGE,
+                &format!("bytes {}-{}/{}", start, end, length),
+            )?;
+            (StatusCode::PARTIAL_CONTENT, start, end)
+        }
+        Requested::Unsatisfiable => {
+            set(header::CONTENT_RANGE, &format!("bytes */{}", length))?;
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+        }
+    };
+    if length == 0 {
+        return Ok((status, response_headers).into_response());
+    }
+    set(header::CONTENT_LENGTH, &(end - start + 1).to_string())?;
+    let stream = gridfs::download_range(&db, &file, start, end).await?;
+    Ok((status, response_headers, StreamBody::new(stream)).into_response())
+}
+
+/// What is inside a source archive, kept in the metadata of the file as `archive` by
+/// [`index_archive`].
+#[derive(Serialize, Deserialize)]
+struct ArchiveIndex {
+    format: ArchiveFormat,
+    entries: Vec<Entry>,
+}
+
+fn archive_index(file: &FilesCollectionDocument) -> Option<ArchiveIndex> {
+    bson::from_bson(file.metadata.as_ref()?.get("archive")?.clone()).ok()
+}
+
+fn broken(file: &FilesCollectionDocument, e: ArchiveError) -> Error {
+    Error::BadReqest(format!(
+        "{} is a broken archive: {}",
+        file.filename.as_deref().unwrap_or_default(),
+        e
     ))
 }
+
+/// Bytes `start..start + len` of the file.
+async fn read_range(
+    db: &MongoDatabase,
+    file: &FilesCollectionDocument,
+    start: u64,
+    len: u64,
+) -> Result<Vec<u8>> {
+    if start.checked_add(len).is_none_or(|end| end > file.length) {
+        return Err(broken(file, ArchiveError::Truncated));
+    }
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(gridfs::download_range(db, file, start, start + len - 1)
+        .await?
+        .try_concat()
+        .await?)
+}
+
+/// The first `len` bytes of the gzipped `file` unpacked, or fewer if it is shorter, so that
+/// reading a file inside it unpacks no more than comes before it.
+async fn gunzip_file_head(
+    state: &AppState,
+    file: &FilesCollectionDocument,
+    len: u64,
+) -> Result<Vec<u8>> {
+    let packed = read_range(&state.mongo_db, file, 0, file.length).await?;
+    let len = usize::try_from(len.min(state.max_unpacked_size)).unwrap_or(usize::MAX);
+    tokio::task::spawn_blocking(move || inflate::gunzip_head(&packed, len))
+        .await?
+        .map_err(|e| broken(file, e.into()))
+}
+
+/// Lists the files inside the tar archive `file`, gzipped if `gzipped`, or `None` if it is gzipped
+/// but not a tar archive. It is read a chunk at a time and listed off the runtime, unpacking
+/// within the limit of `state`, with only the headers kept and nothing read past its end.
+async fn index_tar(
+    state: &AppState,
+    file: &FilesCollectionDocument,
+    gzipped: bool,
+) -> Result<Option<Vec<Entry>>> {
+    let limit = usize::try_from(state.max_unpacked_size).unwrap_or(usize::MAX);
+    let (sender, mut receiver) = tokio::sync::mpsc::channel::<Vec<u8>>(4);
+    let listing = tokio::task::spawn_blocking(move || {
+        let chunks = std::iter::from_fn(|| receiver.blocking_recv());
+        if gzipped {
+            return archive::tar_gz_entries(chunks.flatten(), limit);
+        }
+        archive::tar_entries(chunks).map(Some)
+    });
+    if file.length > 0 {
+        let mut chunks = gridfs::download_range(&state.mongo_db, file, 0, file.length - 1).await?;
+        while let Some(chunk) = chunks.try_next().await? {
+            // Listing is over once the end of the archive is read.
+            if sender.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    }
+    drop(sender);
+    listing.await?.map_err(|e| match e {
+        ArchiveError::Inflate(InflateError::TooLarge(_)) => Error::TooLarge(format!(
+            "{} is larger than {} bytes unpacked",
+            file.filename.as_deref().unwrap_or_default(),
+            limit
+        )),
+        e => broken(file, e),
+    })
+}
+
+/// Lists the files inside the file `id`, if it is a zip, tar or gzipped tar archive, and keeps
+/// the list in its metadata.
+pub(crate) async fn index_archive(state: &AppState, id: ObjectId) -> Result<()> {
+    let db = &state.mongo_db;
+    let file = gridfs::find_file(&db.gridfs_bucket(None), id)
+        .await?
+        .ok_or(Error::NotFound("no file".to_string()))?;
+    let file_type = file
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get_str("fileType").ok());
+    let (format, entries) = match file_type {
+        Some("zip") => {
+            let tail_start = file.length.saturating_sub(archive::ZIP_TAIL_LEN);
+            let tail = read_range(db, &file, tail_start, file.length - tail_start).await?;
+            let directory =
+                archive::zip_directory(&tail, tail_start).map_err(|e| broken(&file, e))?;
+            let directory = read_range(db, &file, directory.offset, directory.size).await?;
+            let entries = archive::zip_entries(&directory).map_err(|e| broken(&file, e))?;
+            (ArchiveFormat::Zip, entries)
+        }
+        Some("tar") => {
+            let entries = index_tar(state, &file, false).await?.unwrap_or_default();
+            (ArchiveFormat::Tar, entries)
+        }
+        Some("gzip") => {
+            // A single gzipped file has nothing to list.
+            let Some(entries) = index_tar(state, &file, true).await? else {
+                return Ok(());
+            };
+            (ArchiveFormat::TarGz, entries)
+        }
+        _ => return Ok(()),
+    };
+    let index = bson::to_bson(&ArchiveIndex { format, entries }).map_err(MongoError::from)?;
+    gridfs::set_metadata(db, id, "archive", index).await?;
+    Ok(())
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+pub(crate) struct ArchivedFile {
+    #[schemars(title = "Path")]
+    path: String,
+    #[schemars(title = "Size", description = "In bytes, unpacked.")]
+    size: u64,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+pub(crate) struct StoredFile {
+    #[schemars(title = "File ID")]
+    id: ObjectIdDef,
+    #[schemars(title = "File Name")]
+    name: String,
+    #[schemars(title = "Size", description = "In bytes.")]
+    size: u64,
+    #[schemars(title = "Type", description = "As told by its content.")]
+    file_type: Option<String>,
+    #[schemars(
+        title = "Files Inside",
+        description = "Sorted by path, if it is a zip, tar or gzipped tar archive."
+    )]
+    entries: Option<Vec<ArchivedFile>>,
+}
+
+/// Describes the files `ids`, in order, skipping those which are gone.
+pub(crate) async fn describe_files(
+    db: &MongoDatabase,
+    ids: &[ObjectId],
+) -> Result<Vec<StoredFile>> {
+    let bucket = db.gridfs_bucket(None);
+    let mut described = Vec::new();
+    for &id in ids {
+        let Some(file) = gridfs::find_file(&bucket, id).await? else {
+            continue;
+        };
+        let entries = archive_index(&file).map(|index| {
+            let mut entries: Vec<_> = index
+                .entries
+                .into_iter()
+                .map(|entry| ArchivedFile {
+                    path: entry.path,
+                    size: entry.size,
+                })
+                .collect();
+            entries.sort_by(|a, b| a.path.cmp(&b.path));
+            entries
+        });
+        described.push(StoredFile {
+            id: ObjectIdDef::pack(id),
+            name: file.filename.clone().unwrap_or_default(),
+            size: file.length,
+            file_type: file
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get_str("fileType").ok())
+                .map(ToString::to_string),
+            entries,
+        });
+    }
+    Ok(described)
+}
+
+/// Which file to get out of an archive, and how.
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+#[derive(Debug)]
+pub(crate) struct EntryQuery {
+    #[schemars(
+        title = "Path",
+        description = "Of the file inside the archive, as listed."
+    )]
+    path: String,
+    #[serde(default)]
+    #[schemars(
+        title = "Inline",
+        description = "Show the file in the browser instead of saving it."
+    )]
+    inline: bool,
+}
+
+/// The unpacked content of `entry` of the archive `file`, out of `unpacked` if it is gzipped
+/// and already unpacked.
+async fn read_entry(
+    state: &AppState,
+    file: &FilesCollectionDocument,
+    format: ArchiveFormat,
+    entry: &Entry,
+    unpacked: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    let db = &state.mongo_db;
+    Ok(match format {
+        ArchiveFormat::Zip => {
+            let storage = entry
+                .zip
+                .as_ref()
+                .ok_or_else(|| broken(file, ArchiveError::Corrupt("no zip storage")))?;
+            let local_header =
+                read_range(db, file, entry.offset, archive::ZIP_LOCAL_HEADER_LEN).await?;
+            let start =
+                archive::zip_data_offset(entry, &local_header).map_err(|e| broken(file, e))?;
+            let data = read_range(db, file, start, storage.compressed_size).await?;
+            archive::zip_unpack(entry, data).map_err(|e| broken(file, e))?
+        }
+        ArchiveFormat::Tar => read_range(db, file, entry.offset, entry.size).await?,
+        ArchiveFormat::TarGz => {
+            let gunzipped;
+            let content = match unpacked {
+                Some(content) => content,
+                None => {
+                    gunzipped = gunzip_file_head(state, file, entry.offset + entry.size).await?;
+                    &gunzipped
+                }
+            };
+            usize::try_from(entry.offset)
+                .ok()
+                .zip(usize::try_from(entry.offset + entry.size).ok())
+                .and_then(|(start, end)| content.get(start..end))
+                .ok_or_else(|| broken(file, ArchiveError::Truncated))?
+                .to_vec()
+        }
+    })
+}
+
+/// Reads the files `ids`, with each archive listed by [`index_archive`] taken for the files
+/// inside it, named `archive/path`. Files larger than `limit`, or than what is left of `budget`
+/// once those before them are read, are named but not read, as are those in a gzipped tar
+/// which end past what was left of `budget` before it. Where two have the same name the first
+/// wins.
+pub(crate) async fn read_files(
+    state: &AppState,
+    ids: &[ObjectId],
+    limit: u64,
+    budget: &mut u64,
+) -> Result<BTreeMap<String, Option<Vec<u8>>>> {
+    let db = &state.mongo_db;
+    let bucket = db.gridfs_bucket(None);
+    let mut files = BTreeMap::new();
+    for &id in ids {
+        let Some(file) = gridfs::find_file(&bucket, id).await? else {
+            continue;
+        };
+        let name = file.filename.clone().unwrap_or_default();
+        let Some(index) = archive_index(&file) else {
+            if let btree_map::Entry::Vacant(slot) = files.entry(name) {
+                let content = if file.length <= limit.min(*budget) {
+                    *budget -= file.length;
+                    Some(read_range(db, &file, 0, file.length).await?)
+                } else {
+                    None
+                };
+                slot.insert(content);
+            }
+            continue;
+        };
+        // A gzipped tar is unpacked from its start up to the end of the last file read, so
+        // those ending past the budget are left out however small.
+        let reach = match index.format {
+            ArchiveFormat::TarGz => *budget,
+            _ => u64::MAX,
+        };
+        let mut chosen = Vec::new();
+        for entry in &index.entries {
+            let path = format!("{}/{}", name, entry.path);
+            let btree_map::Entry::Vacant(slot) = files.entry(path.clone()) else {
+                continue;
+            };
+            if entry.size <= limit.min(*budget) && entry.offset.saturating_add(entry.size) <= reach
+            {
+                *budget -= entry.size;
+                chosen.push((path, entry));
+            }
+            slot.insert(None);
+        }
+        let end = chosen
+            .iter()
+            .map(|(_, entry)| entry.offset + entry.size)
+            .max();
+        let unpacked = match end {
+            Some(end) if index.format == ArchiveFormat::TarGz => {
+                Some(gunzip_file_head(state, &file, end).await?)
+            }
+            _ => None,
+        };
+        for (path, entry) in chosen {
+            let content =
+                read_entry(state, &file, index.format, entry, unpacked.as_deref()).await?;
+            files.insert(path, Some(content));
+        }
+    }
+    Ok(files)
+}
+
+/// Serves a single file out of the archive `id`, unless the client has it already.
+///
+/// Unlike [`download_file`], ranges are not served, since the file is unpacked whole anyway.
+pub(crate) async fn download_entry(
+    state: &AppState,
+    id: ObjectId,
+    headers: &HeaderMap,
+    query: EntryQuery,
+) -> Result<Response> {
+    let db = &state.mongo_db;
+    let file = gridfs::find_file(&db.gridfs_bucket(None), id)
+        .await?
+        .ok_or(Error::NotFound("no file".to_string()))?;
+    let index = archive_index(&file).ok_or(Error::BadReqest(
+        "not an archive whose files are listed".to_string(),
+    ))?;
+    let entry = archive::find_entry(&index.entries, &query.path)
+        .ok_or(Error::NotFound(format!("no {} in the archive", query.path)))?;
+    let archive_tag = file
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get_str("sha256").ok())
+        .map_or_else(|| id.to_hex(), ToString::to_string);
+    let etag = format!(
+        "\"{:x}\"",
+        Sha256::digest(format!("{}/{}", archive_tag, entry.path))
+    );
+    let last_modified = file.upload_date.to_chrono();
+    let mut response_headers = HeaderMap::new();
+    let mut set = |name, value: &str| set_header(&mut response_headers, name, value);
+    set(header::ETAG, &etag)?;
+    set(header::LAST_MODIFIED, &http_date(last_modified))?;
+    if not_modified(headers, &etag, last_modified) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    if entry.size > state.max_unpacked_size {
+        return Err(Error::TooLarge(format!(
+            "{} is larger than {} bytes",
+            entry.path, state.max_unpacked_size
+        )));
+    }
+    let content = read_entry(state, &file, index.format, entry, None).await?;
+    let head = &content[..content.len().min(sniff::PEEK_LEN)];
+    set(
+        header::CONTENT_TYPE,
+        sniff::detect(head).map_or("application/octet-stream", FileType::mime),
+    )?;
+    let name = entry.path.rsplit('/').next().unwrap_or_default();
+    set(
+        header::CONTENT_DISPOSITION,
+        &content_disposition(query.inline, name),
+    )?;
+    Ok((StatusCode::OK, response_headers, content).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const ETAG: &str = "\"abc\"";
+    const LAST_MODIFIED: &str = "Tue, 06 Jun 2023 08:00:00 GMT";
+
+    fn headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    fn range(spec: &str, length: u64) -> Requested {
+        requested(
+            &headers(&[(header::RANGE, spec)]),
+            length,
+            ETAG,
+            LAST_MODIFIED,
+        )
+    }
+
+    #[test]
+    fn single_ranges_are_served() {
+        assert_eq!(range("bytes=0-99", 1000), Requested::Part(0, 99));
+        assert_eq!(range("bytes=900-", 1000), Requested::Part(900, 999));
+        assert_eq!(range("bytes=-100", 1000), Requested::Part(900, 999));
+        assert_eq!(range("bytes=-5000", 1000), Requested::Part(0, 999));
+        assert_eq!(range("bytes=990-5000", 1000), Requested::Part(990, 999));
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_unsatisfiable() {
+        assert_eq!(range("bytes=1000-", 1000), Requested::Unsatisfiable);
+        assert_eq!(range("bytes=1000-1010", 1000), Requested::Unsatisfiable);
+        assert_eq!(range("bytes=-0", 1000), Requested::Unsatisfiable);
+        assert_eq!(range("bytes=0-", 0), Requested::Unsatisfiable);
+    }
+
+    #[test]
+    fn other_ranges_get_the_whole_file() {
+        let length = 1000;
+        assert_eq!(
+            requested(&HeaderMap::new(), length, ETAG, LAST_MODIFIED),
+            Requested::Whole
+        );
+        for spec in [
+            "bytes=0-9,20-29",
+            "bytes=9-0",
+            "bytes=a-b",
+            "bytes=-",
+            "items=0-9",
+        ] {
+            assert_eq!(range(spec, length), Requested::Whole, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn ranges_are_served_only_if_the_file_is_unchanged() {
+        let if_range = |value| {
+            requested(
+                &headers(&[(header::RANGE, "bytes=0-9"), (header::IF_RANGE, value)]),
+                1000,
+                ETAG,
+                LAST_MODIFIED,
+            )
+        };
+        assert_eq!(if_range(ETAG), Requested::Part(0, 9));
+        assert_eq!(if_range(LAST_MODIFIED), Requested::Part(0, 9));
+        assert_eq!(if_range("\"other\""), Requested::Whole);
+    }
+
+    #[test]
+    fn validators_are_matched() {
+        let last_modified = Utc.with_ymd_and_hms(2023, 6, 6, 8, 0, 0).unwrap();
+        let fresh =
+            |pairs: &[(HeaderName, &str)]| not_modified(&headers(pairs), ETAG, last_modified);
+        assert!(fresh(&[(header::IF_NONE_MATCH, ETAG)]));
+        assert!(fresh(&[(header::IF_NONE_MATCH, "\"x\", W/\"abc\"")]));
+        assert!(fresh(&[(header::IF_NONE_MATCH, "*")]));
+        assert!(!fresh(&[(header::IF_NONE_MATCH, "\"x\"")]));
+        assert!(fresh(&[(header::IF_MODIFIED_SINCE, LAST_MODIFIED)]));
+        assert!(fresh(&[(
+            header::IF_MODIFIED_SINCE,
+            "Wed, 07 Jun 2023 08:00:00 GMT"
+        )]));
+        assert!(!fresh(&[(
+            header::IF_MODIFIED_SINCE,
+            "Mon, 05 Jun 2023 08:00:00 GMT"
+        )]));
+        assert!(!fresh(&[(header::IF_MODIFIED_SINCE, "yesterday")]));
+        // If-None-Match wins over If-Modified-Since.
+        assert!(!fresh(&[
+            (header::IF_NONE_MATCH, "\"x\""),
+            (header::IF_MODIFIED_SINCE, LAST_MODIFIED),
+        ]));
+        assert!(!fresh(&[]));
+    }
+
+    #[test]
+    fn dispositions_carry_both_file_names() {
+        assert_eq!(
+            content_disposition(false, "thesis.pdf"),
+            "attachment; filename=\"thesis.pdf\"; filename*=UTF-8''thesis.pdf"
+        );
+        assert_eq!(
+            content_disposition(true, "my \"draft\" 论文.pdf"),
+            "inline; filename=\"my _draft_ __.pdf\"; filename*=UTF-8''my%20%22draft%22%20%E8%AE%BA%E6%96%87.pdf"
+        );
+    }
+}
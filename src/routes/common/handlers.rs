@@ -1,13 +1,22 @@
 use async_trait::async_trait;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum_jsonschema::Json;
 use crud::{View, Viewable};
 use mongo::{
-    entity::{update::SettableData, Data, Entity, EntityView},
+    bson::Document,
+    entity::{
+        doc, field,
+        operator::*,
+        page::{Page, Paging},
+        update::SettableData,
+        Data, Entity, EntityView,
+    },
     oid::ObjectIdDef,
     owned::{Owned, OwnedContent},
     MongoDatabase,
 };
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize};
 
 use crate::state::AppState;
 
@@ -41,7 +50,7 @@ pub(crate) async fn insert_body<I: InsertCfg>(
 }
 
 #[async_trait]
-pub(crate) trait ShowCfg {
+pub(crate) trait ShowCfg: Send + Sync {
     type D: Data;
     type DV: View<Object = Self::D>;
     async fn authenticate(
@@ -49,6 +58,14 @@ pub(crate) trait ShowCfg {
         db: MongoDatabase,
         model: &Entity<Self::D>,
     ) -> Result<bool>;
+    /// Takes out of `model` what `auth_info` may see it but not know.
+    async fn redact(
+        _auth_info: Option<&AuthInfo>,
+        _db: MongoDatabase,
+        _model: &mut Entity<Self::D>,
+    ) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub(crate) async fn show_object<S: ShowCfg>(
@@ -57,17 +74,119 @@ pub(crate) async fn show_object<S: ShowCfg>(
     Path(id): Path<ObjectIdDef>,
 ) -> Result<Json<EntityView<S::DV>>> {
     let id = id.unpack();
-    let model = <Entity<S::D>>::try_find_one_by_id(state.mongo_db.clone(), id)
+    let mut model = <Entity<S::D>>::try_find_one_by_id(state.mongo_db.clone(), id)
         .await
         .map_err(Error::from)?
         .ok_or(Error::NotFound(format!("no object with id {}", id)))?;
-    if !S::authenticate(auth_info, state.mongo_db, &model).await? {
+    if !S::authenticate(auth_info.clone(), state.mongo_db.clone(), &model).await? {
         Err(Error::Forbidden("no permission".to_string()))
     } else {
+        S::redact(Some(&auth_info), state.mongo_db, &mut model).await?;
         Ok(Json(model.into()))
     }
 }
 
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+#[derive(Default)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum SortKey {
+    #[default]
+    CreatedAt,
+    Downloads,
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+#[derive(Default)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum SortOrder {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+#[serde(default)]
+#[derive(Default)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) struct Sorting {
+    #[schemars(title = "Sort By")]
+    sort: SortKey,
+    #[schemars(title = "Sort Order")]
+    order: SortOrder,
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+#[derive(Default)]
+#[derive(Debug)]
+pub(crate) struct NoFilter {}
+
+/// Lists what [`ShowCfg::authenticate`] would show, page by page.
+#[async_trait]
+pub(crate) trait ListCfg: ShowCfg {
+    type F: 'static + Send + DeserializeOwned + JsonSchema;
+    /// Path of the download counter, if these objects could be sorted by downloads.
+    fn downloads_path() -> Option<&'static str>;
+    /// Builds a query matching exactly the objects `authenticate` accepts.
+    async fn filter(
+        auth_info: Option<AuthInfo>,
+        db: MongoDatabase,
+        filter: Self::F,
+    ) -> Result<Document>;
+}
+
+pub(crate) async fn list_objects<L: ListCfg>(
+    auth_info: Option<AuthInfo>,
+    State(state): State<AppState>,
+    Query(paging): Query<Paging>,
+    Query(sorting): Query<Sorting>,
+    Query(filter): Query<L::F>,
+) -> Result<Json<Page<EntityView<L::DV>>>> {
+    list_visible::<L>(auth_info, state, paging, sorting, filter, doc! {}).await
+}
+
+/// Like [`list_objects`], but only those also matching `restriction`.
+pub(crate) async fn list_visible<L: ListCfg>(
+    auth_info: Option<AuthInfo>,
+    state: AppState,
+    paging: Paging,
+    sorting: Sorting,
+    filter: L::F,
+    restriction: Document,
+) -> Result<Json<Page<EntityView<L::DV>>>> {
+    let path = match sorting.sort {
+        SortKey::CreatedAt => field!(created_at in Entity<Owned<()>>),
+        SortKey::Downloads => {
+            L::downloads_path().ok_or(Error::BadReqest("cannot sort by downloads".to_string()))?
+        }
+    };
+    let order = match sorting.order {
+        SortOrder::Ascending => 1,
+        SortOrder::Descending => -1,
+    };
+    let filter = L::filter(auth_info.clone(), state.mongo_db.clone(), filter).await?;
+    let mut page = <Entity<L::D>>::find_page(
+        state.mongo_db.clone(),
+        doc! {And: [filter, restriction]},
+        doc! {path: order, field!(_id in Entity<Owned<()>>): order},
+        paging,
+    )
+    .await
+    .map_err(Error::from)?;
+    for model in &mut page.items {
+        L::redact(auth_info.as_ref(), state.mongo_db.clone(), model).await?;
+    }
+    Ok(Json(page.map(EntityView::from)))
+}
+
 #[async_trait]
 pub(crate) trait SetCfg {
     type OC: OwnedContent;
@@ -93,7 +212,7 @@ pub(crate) async fn set_object<U: SetCfg>(
     if !U::authenticate(auth_info, state.mongo_db.clone(), &model, &body).await? {
         Err(Error::Forbidden("no permission".to_string()))
     } else {
-        <Entity<Owned<U::OC>>>::set_by_id(state.mongo_db, id, body)
+        <Entity<Owned<U::OC>>>::set_owned_by_id(state.mongo_db, id, body)
             .await
             .map_err(Error::from)?
             .ok_or(Error::NotFound(format!(
@@ -128,10 +247,38 @@ pub(crate) async fn delete_object<D: DeleteCfg>(
     if !D::authenticate(auth_info, state.mongo_db.clone(), &model).await? {
         Err(Error::Forbidden("no permission".to_string()))
     } else {
-        model
-            .delete_owneds(state.mongo_db)
+        <Entity<Owned<D::Cd>>>::trash_by_id(state.mongo_db, id)
             .await
             .map_err(Error::from)
+            .map(|trashed| Json(trashed.map_or(0, |_| 1)))
+    }
+}
+
+/// Takes an object out of the trash, for those who could have deleted it.
+pub(crate) async fn restore_object<D: DeleteCfg>(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+) -> Result<Json<<Entity<Owned<D::Cd>> as Viewable>::View>> {
+    let id = id.unpack();
+    let model = <Entity<Owned<D::Cd>>>::try_find_one_trashed_by_id(state.mongo_db.clone(), id)
+        .await
+        .map_err(Error::from)?
+        .ok_or(Error::NotFound(format!(
+            "no object with id {} in the trash",
+            id
+        )))?;
+    if !D::authenticate(auth_info, state.mongo_db.clone(), &model).await? {
+        Err(Error::Forbidden("no permission".to_string()))
+    } else {
+        <Entity<Owned<D::Cd>>>::restore_by_id(state.mongo_db, id)
+            .await
+            .map_err(Error::from)?
+            .ok_or(Error::NotFound(format!(
+                "no object with id {} in the trash while restore",
+                id
+            )))
+            .map(<Entity<Owned<D::Cd>>>::into)
             .map(Json)
     }
 }
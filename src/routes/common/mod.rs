@@ -1,4 +1,5 @@
 pub(super) mod auth;
+pub(super) mod bearer;
 pub(super) mod docs;
 pub(super) mod err;
 pub(super) mod file;
@@ -15,6 +15,7 @@ mod paper_collection;
 mod thesis;
 mod version;
 mod review;
+mod trash;
 
 pub(crate) fn new() -> Router<AppState> {
     aide::gen::in_context(|ctx| {
@@ -29,6 +30,7 @@ pub(crate) fn new() -> Router<AppState> {
         .merge(thesis::route())
         .merge(version::route())
         .merge(review::route())
+        .merge(trash::route())
         .route(
             "/api.json",
             routing::get(|Extension(api): Extension<Arc<OpenApi>>| async { Json(api) }),
@@ -43,7 +45,7 @@ pub(crate) fn new() -> Router<AppState> {
                 ..Info::default()
             })
             .security_scheme(
-                "cookieAuth",
+                common::docs::SECURITY_SCHEME_NAME,
                 SecurityScheme::ApiKey {
                     location: ApiKeyLocation::Cookie,
                     name: "sid".to_string(),
@@ -51,6 +53,15 @@ pub(crate) fn new() -> Router<AppState> {
                     extensions: Default::default(),
                 },
             )
+            .security_scheme(
+                common::docs::BEARER_SCHEME_NAME,
+                SecurityScheme::Http {
+                    scheme: "bearer".to_string(),
+                    bearer_format: Some("personal access token".to_string()),
+                    description: Some("created at /tokens, sent as `Authorization: Bearer`".to_string()),
+                    extensions: Default::default(),
+                },
+            )
         })
         .layer(Extension(Arc::new(open_api)))
 }
@@ -1,25 +1,49 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use aide::axum::{routing, ApiRouter};
 use async_trait::async_trait;
+use axum::{
+    debug_handler,
+    extract::{Path, Query, State},
+};
 use axum_jsonschema::Json;
 use crud::Viewable;
 use mongo::{
-    entity::{update::SettableData, Entity},
-    oid::ObjectIdDef,
+    bson::Document,
+    entity::{
+        doc, field,
+        operator::*,
+        page::{Page, Paging},
+        update::SettableData,
+        Entity, EntityView,
+    },
+    oid::{ObjectId, ObjectIdDef},
     owned::Owned,
 };
+use schemars::JsonSchema;
+use serde::Serialize;
 
 use crate::{
-    mongo_entities::paper_collection::{
-        category::Category, magazine::Magazine, PaperCollection, PaperCollectionDetail,
+    mongo_entities::{
+        paper_collection::{
+            category::Category,
+            magazine::{EditorialBoard, Magazine},
+            PaperCollection, PaperCollectionDetail,
+        },
+        profile::Profile,
+        thesis::{Thesis, ThesisIntroduction},
     },
     state::AppState,
 };
 
-use super::common::{
-    auth::{AuthInfo, Permission},
-    docs,
-    err::{Error, Result},
-    handlers::{self, DeleteCfg, InsertCfg, SetCfg, ShowCfg},
+use super::{
+    common::{
+        auth::{Action, AuthInfo, Resource},
+        docs,
+        err::{Error, Result},
+        handlers::{self, DeleteCfg, InsertCfg, ListCfg, NoFilter, SetCfg, ShowCfg, Sorting},
+    },
+    thesis,
 };
 
 struct InsertAuth<D: PaperCollectionDetail> {
@@ -35,6 +59,7 @@ impl<D: PaperCollectionDetail> InsertCfg for InsertAuth<D> {
         db: mongo::MongoDatabase,
         post: &<Self::OC as mongo::owned::OwnedContent>::Post,
     ) -> super::common::err::Result<()> {
+        D::check_post(&post.detail).map_err(Error::BadReqest)?;
         <Entity<Owned<PaperCollection<Category>>>>::include(db, &post.category_ids)
             .await?
             .then_some(())
@@ -42,11 +67,13 @@ impl<D: PaperCollectionDetail> InsertCfg for InsertAuth<D> {
     }
 }
 
-fn authenticate<D: PaperCollectionDetail>(
-    auth_info: AuthInfo,
+async fn authenticate<D: PaperCollectionDetail>(
+    auth_info: &AuthInfo,
+    db: mongo::MongoDatabase,
     model: &Entity<Owned<PaperCollection<D>>>,
-) -> bool {
-    auth_info.permitted(Permission::Managing) || model.data.owner_id == auth_info.id
+) -> Result<bool> {
+    Ok(model.data.owner_id == auth_info.id
+        || auth_info.can(Action::Manage, &Resource::under(db, [model._id]).await?))
 }
 
 struct ShowAuth<D: PaperCollectionDetail> {
@@ -61,10 +88,38 @@ impl<D: PaperCollectionDetail> ShowCfg for ShowAuth<D> {
 
     async fn authenticate(
         auth_info: AuthInfo,
-        _db: mongo::MongoDatabase,
+        db: mongo::MongoDatabase,
         model: &Entity<Self::D>,
     ) -> Result<bool> {
-        Ok(model.data.is_public || authenticate(auth_info, model))
+        Ok(model.data.is_public || authenticate(&auth_info, db, model).await?)
+    }
+}
+
+#[async_trait]
+impl<D: PaperCollectionDetail> ListCfg for ShowAuth<D> {
+    type F = NoFilter;
+
+    fn downloads_path() -> Option<&'static str> {
+        None
+    }
+
+    async fn filter(
+        auth_info: Option<AuthInfo>,
+        db: mongo::MongoDatabase,
+        _filter: Self::F,
+    ) -> Result<Document> {
+        let is_public = doc! {field!((data in Entity<Owned<()>>).(is_public in Owned<()>)): true};
+        let Some(auth_info) = auth_info else {
+            return Ok(is_public);
+        };
+        let Some(collection_ids) = auth_info.collection_ids_for(db, Action::Manage).await? else {
+            return Ok(doc! {});
+        };
+        Ok(doc! {Or: [
+            is_public,
+            {field!((data in Entity<Owned<()>>).(owner_id in Owned<()>)): auth_info.id},
+            {field!(_id in Entity<Owned<()>>): {In: collection_ids.into_iter().collect::<Vec<_>>()}},
+        ]})
     }
 }
 
@@ -82,12 +137,20 @@ impl<D: PaperCollectionDetail> SetCfg for PatchAuth<D> {
         model: &Entity<Owned<Self::OC>>,
         patch: &<Owned<Self::OC> as SettableData>::P,
     ) -> Result<bool> {
+        D::check_patch(&patch.detail).map_err(Error::BadReqest)?;
         if let Some(category_ids) = &patch.category_ids {
-            if !<Entity<Owned<PaperCollection<Category>>>>::include(db, category_ids).await? {
+            if !<Entity<Owned<PaperCollection<Category>>>>::include(db.clone(), category_ids)
+                .await?
+            {
                 return Err(Error::BadReqest("invalid catagoriy id".to_string()));
             }
+            if !D::could_belong_to(db.clone(), model._id, category_ids).await? {
+                return Err(Error::BadReqest(
+                    "cannot put a category under itself".to_string(),
+                ));
+            }
         }
-        Ok(authenticate(auth_info, model))
+        authenticate(&auth_info, db, model).await
     }
 }
 
@@ -101,25 +164,254 @@ impl<D: PaperCollectionDetail> DeleteCfg for DeleteAuth<D> {
 
     async fn authenticate(
         auth_info: AuthInfo,
-        _db: mongo::MongoDatabase,
+        db: mongo::MongoDatabase,
         model: &Entity<Owned<Self::Cd>>,
     ) -> Result<bool> {
-        Ok(authenticate(auth_info, model))
+        authenticate(&auth_info, db, model).await
     }
 }
 
 type Res<D> = Json<<Entity<Owned<PaperCollection<D>>> as Viewable>::View>;
 
+type ListRes<D> = Json<Page<EntityView<<Owned<PaperCollection<D>> as Viewable>::View>>>;
+
 fn tag<D: PaperCollectionDetail>(
     op: aide::transform::TransformPathItem,
 ) -> aide::transform::TransformPathItem {
     op.tag(D::plural())
 }
 
-fn nest<D: PaperCollectionDetail>() -> ApiRouter<AppState> {
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct CategoryNode {
+    #[serde(flatten)]
+    category: EntityView<<Owned<PaperCollection<Category>> as Viewable>::View>,
+    #[schemars(title = "Subcategories")]
+    children: Vec<CategoryNode>,
+}
+
+impl CategoryNode {
+    fn grow(
+        categories: &BTreeMap<ObjectId, Entity<Owned<PaperCollection<Category>>>>,
+        children: &BTreeMap<ObjectId, Vec<ObjectId>>,
+        ancestor_ids: &mut BTreeSet<ObjectId>,
+        id: ObjectId,
+    ) -> Self {
+        ancestor_ids.insert(id);
+        let mut nodes = Vec::new();
+        for &child_id in children.get(&id).into_iter().flatten() {
+            if !ancestor_ids.contains(&child_id) {
+                nodes.push(Self::grow(categories, children, ancestor_ids, child_id));
+            }
+        }
+        ancestor_ids.remove(&id);
+        Self {
+            category: categories[&id].clone().into(),
+            children: nodes,
+        }
+    }
+}
+
+type TreeRes = Json<Vec<CategoryNode>>;
+
+#[debug_handler]
+async fn tree(auth_info: Option<AuthInfo>, State(state): State<AppState>) -> Result<TreeRes> {
+    let filter =
+        ShowAuth::<Category>::filter(auth_info, state.mongo_db.clone(), NoFilter {}).await?;
+    let mut found =
+        <Entity<Owned<PaperCollection<Category>>>>::find(state.mongo_db, filter).await?;
+    let mut categories = BTreeMap::new();
+    while found.advance().await? {
+        let category = found.deserialize_current()?;
+        categories.insert(category._id, category);
+    }
+    let mut root_ids = Vec::new();
+    let mut children = <BTreeMap<ObjectId, Vec<ObjectId>>>::new();
+    for (&id, category) in &categories {
+        let parent_ids: Vec<_> = category
+            .data
+            .content
+            .category_ids
+            .iter()
+            .filter(|parent_id| categories.contains_key(parent_id))
+            .collect();
+        if parent_ids.is_empty() {
+            root_ids.push(id);
+        }
+        for &parent_id in parent_ids {
+            children.entry(parent_id).or_default().push(id);
+        }
+    }
+    Ok(Json(
+        root_ids
+            .into_iter()
+            .map(|id| CategoryNode::grow(&categories, &children, &mut BTreeSet::new(), id))
+            .collect(),
+    ))
+}
+
+/// IDs of the category `id` and its subcategories, if the category itself is visible.
+async fn subtree_ids(
+    auth_info: &Option<AuthInfo>,
+    state: &AppState,
+    id: ObjectId,
+) -> Result<Vec<ObjectId>> {
+    let category =
+        <Entity<Owned<PaperCollection<Category>>>>::try_find_one_by_id(state.mongo_db.clone(), id)
+            .await?
+            .ok_or(Error::NotFound(format!("no category with id {}", id)))?;
+    let visible = match auth_info {
+        _ if category.data.is_public => true,
+        Some(auth_info) => authenticate(auth_info, state.mongo_db.clone(), &category).await?,
+        None => false,
+    };
+    if !visible {
+        return Err(Error::Forbidden("no permission".to_string()));
+    }
+    Ok(Category::subtree_ids(state.mongo_db.clone(), id)
+        .await?
+        .into_iter()
+        .collect())
+}
+
+#[debug_handler]
+async fn magazines_under(
+    auth_info: Option<AuthInfo>,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+    Query(paging): Query<Paging>,
+    Query(sorting): Query<Sorting>,
+) -> Result<ListRes<Magazine>> {
+    let category_ids = subtree_ids(&auth_info, &state, id.unpack()).await?;
+    handlers::list_visible::<ShowAuth<Magazine>>(
+        auth_info,
+        state,
+        paging,
+        sorting,
+        NoFilter {},
+        doc! {field!((data in Entity<Owned<PaperCollection<Magazine>>>).(content in Owned<PaperCollection<Magazine>>).(category_ids in PaperCollection<Magazine>)): {In: category_ids}},
+    )
+    .await
+}
+
+type ThesesRes = Json<Page<EntityView<<Owned<Thesis> as Viewable>::View>>>;
+
+#[debug_handler]
+async fn theses_under(
+    auth_info: Option<AuthInfo>,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+    Query(paging): Query<Paging>,
+    Query(sorting): Query<Sorting>,
+) -> Result<ThesesRes> {
+    let category_ids = subtree_ids(&auth_info, &state, id.unpack()).await?;
+    let magazine_ids = <PaperCollection<Magazine>>::ids_in_categories(
+        state.mongo_db.clone(),
+        &category_ids.into_iter().collect(),
+    )
+    .await?;
+    handlers::list_visible::<thesis::ShowAuth>(
+        auth_info,
+        state,
+        paging,
+        sorting,
+        NoFilter {},
+        doc! {field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(magazine_ids in ThesisIntroduction)): {In: magazine_ids.into_iter().collect::<Vec<_>>()}},
+    )
+    .await
+}
+
+fn category_routes() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route_with(
+            "/tree",
+            routing::get_with(tree, |op| {
+                op.summary("show all categories as a tree")
+                    .description("a category with several parents shows under each of them")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<TreeRes>()
+            }),
+            tag::<Category>,
+        )
+        .api_route_with(
+            "/:id/magazines",
+            routing::get_with(magazines_under, |op| {
+                op.summary("list magazines in a category and its subcategories")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<ListRes<Magazine>>()
+            }),
+            |op| {
+                docs::add_one_oid_parameter(
+                    tag::<Category>(op),
+                    "id".to_string(),
+                    Some("category id".to_string()),
+                )
+            },
+        )
+        .api_route_with(
+            "/:id/theses",
+            routing::get_with(theses_under, |op| {
+                op.summary("list theses of magazines in a category and its subcategories")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response::<ThesesRes>()
+            }),
+            |op| {
+                docs::add_one_oid_parameter(
+                    tag::<Category>(op),
+                    "id".to_string(),
+                    Some("category id".to_string()),
+                )
+            },
+        )
+}
+
+#[debug_handler]
+async fn set_board(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+    Json(board): Json<EditorialBoard>,
+) -> Result<Res<Magazine>> {
+    let id = id.unpack();
+    let magazine =
+        <Entity<Owned<PaperCollection<Magazine>>>>::try_find_one_by_id(state.mongo_db.clone(), id)
+            .await?
+            .ok_or(Error::NotFound(format!("no magazine with id {}", id)))?;
+    if !authenticate(&auth_info, state.mongo_db.clone(), &magazine).await? {
+        return Err(Error::Forbidden("no permission".to_string()));
+    }
+    if !<Entity<Profile>>::include(state.mongo_db.clone(), board.member_ids()).await? {
+        return Err(Error::BadReqest("invalid editor id".to_string()));
+    }
+    Magazine::set_board(state.mongo_db, id, &board)
+        .await?
+        .ok_or(Error::NotFound(format!("no magazine with id {}", id)))
+        .map(|magazine| Json(magazine.into()))
+}
+
+fn magazine_routes() -> ApiRouter<AppState> {
+    ApiRouter::new().api_route_with(
+        "/:id/board",
+        routing::put_with(set_board, |op| {
+            op.summary("replace the editorial board of a magazine")
+                .description("editors on the board review and adjudge theses in the magazine")
+                .security_requirement(docs::SECURITY_SCHEME_NAME)
+                .default_response_with::<Res<Magazine>, _>(docs::require_cookie::<Res<Magazine>>)
+        }),
+        |op| {
+            docs::add_one_oid_parameter(
+                tag::<Magazine>(op),
+                "id".to_string(),
+                Some("magazine id".to_string()),
+            )
+        },
+    )
+}
+
+fn nest<D: PaperCollectionDetail>(extra: ApiRouter<AppState>) -> ApiRouter<AppState> {
     ApiRouter::new().nest(
         &format!("/{}", D::collection_name()),
-        ApiRouter::new()
+        extra
             .api_route_with(
                 "/",
                 routing::post_with(handlers::insert_body::<InsertAuth<D>>, |op| {
@@ -128,6 +420,12 @@ fn nest<D: PaperCollectionDetail>() -> ApiRouter<AppState> {
                         .default_response_with::<Json<ObjectIdDef>, _>(
                             docs::require_cookie::<Json<ObjectIdDef>>,
                         )
+                })
+                .get_with(handlers::list_objects::<ShowAuth<D>>, |op| {
+                    op.summary(&format!("list {}", D::plural()))
+                        .description("public ones, plus private ones you can see with a cookie")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response::<ListRes<D>>()
                 }),
                 tag::<D>,
             )
@@ -145,6 +443,24 @@ fn nest<D: PaperCollectionDetail>() -> ApiRouter<AppState> {
                 })
                 .delete_with(handlers::delete_object::<DeleteAuth<D>>, |op| {
                     op.summary(&format!("delete a {}", D::singular()))
+                        .description(
+                            "moves it to the trash, from where it can be restored for a while",
+                        )
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Res<D>, _>(docs::require_cookie::<Res<D>>)
+                }),
+                |op| {
+                    docs::add_one_oid_parameter(
+                        tag::<D>(op),
+                        "id".to_string(),
+                        Some("someone's object id".to_string()),
+                    )
+                },
+            )
+            .api_route_with(
+                "/:id/restore",
+                routing::post_with(handlers::restore_object::<DeleteAuth<D>>, |op| {
+                    op.summary(&format!("restore a {} from the trash", D::singular()))
                         .security_requirement(docs::SECURITY_SCHEME_NAME)
                         .default_response_with::<Res<D>, _>(docs::require_cookie::<Res<D>>)
                 }),
@@ -160,5 +476,7 @@ fn nest<D: PaperCollectionDetail>() -> ApiRouter<AppState> {
 }
 
 pub(super) fn route() -> ApiRouter<AppState> {
-    ApiRouter::new().merge(nest::<Magazine>())
+    ApiRouter::new()
+        .merge(nest::<Magazine>(magazine_routes()))
+        .merge(nest::<Category>(category_routes()))
 }
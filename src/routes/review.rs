@@ -1,5 +1,5 @@
 use crate::mongo_entities::review::Review;
-use crate::mongo_entities::thesis::Thesis;
+use crate::mongo_entities::version::anonymity::{ReviewAnonymity, Viewer};
 use crate::mongo_entities::version::Version;
 use crate::routes::common::auth::AuthInfo;
 use crate::routes::common::err::Error;
@@ -43,16 +43,49 @@ impl ShowCfg for ShowAuth {
             Ok(false)
         }
     }
+
+    async fn redact(
+        auth_info: Option<&AuthInfo>,
+        db: MongoDatabase,
+        model: &mut Entity<Self::D>,
+    ) -> crate::routes::common::err::Result<()> {
+        let version = <Entity<Attached<Version>>>::try_find_one_by_id(
+            db.clone(),
+            model.data.content.version_id,
+        )
+        .await
+        .map_err(Error::from)?;
+        let (anonymity, viewer) = match version {
+            Some(version) => super::version::blinds(auth_info, db, &version.data.content).await?,
+            None => (
+                ReviewAnonymity::DoubleBlind,
+                Viewer::Other(auth_info.map(|auth_info| auth_info.id)),
+            ),
+        };
+        anonymity.redact_review(viewer, &mut model.data);
+        Ok(())
+    }
 }
 
 type Res = Json<EntityView<<Attached<Review> as Viewable>::View>>;
 
 pub(super) fn route() -> ApiRouter<AppState> {
-    ApiRouter::new().nest(&format!("/{}", Thesis::plural()), ApiRouter::new().api_route_with("/:id", routing::get_with(handlers::show_object::<ShowAuth>, |op| {
-        op.summary("get content of a review")
-            .security_requirement(docs::SECURITY_SCHEME_NAME)
-            .default_response_with::<Res, _>(docs::require_cookie::<Res>)
-    }),|op|{
-        docs::add_one_oid_parameter(op.tag(Thesis::plural()), "id".to_string(), Some("review id".to_string()))
-    }))
+    ApiRouter::new().nest(
+        &format!("/{}", Review::plural()),
+        ApiRouter::new().api_route_with(
+            "/:id",
+            routing::get_with(handlers::show_object::<ShowAuth>, |op| {
+                op.summary("get content of a review")
+                    .security_requirement(docs::SECURITY_SCHEME_NAME)
+                    .default_response_with::<Res, _>(docs::require_cookie::<Res>)
+            }),
+            |op| {
+                docs::add_one_oid_parameter(
+                    op.tag(Review::plural()),
+                    "id".to_string(),
+                    Some("review id".to_string()),
+                )
+            },
+        ),
+    )
 }
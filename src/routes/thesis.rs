@@ -1,33 +1,53 @@
-use std::ops::Not;
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::Not,
+};
 
 use aide::axum::{routing, ApiRouter};
 use async_trait::async_trait;
 use axum::{
     debug_handler,
-    extract::{Multipart, Path, State},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
 };
 use axum_jsonschema::Json;
 use crud::{Countable, Viewable};
 use mongo::{
-    entity::{update::SettableData, Entity, EntityView},
+    bson::Document,
+    gridfs,
+    entity::{
+        doc, field,
+        operator::*,
+        page::{Page, Paging},
+        update::SettableData,
+        Entity, EntityView,
+    },
+    attached::Attached,
     oid::{ObjectId, ObjectIdDef},
     owned::{Owned, OwnedContent},
 };
 
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
 use crate::{
     mongo_entities::{
         paper_collection::{magazine::Magazine, PaperCollection},
-        thesis::Thesis,
+        text,
+        review::Review,
+        thesis::{Thesis, ThesisIntroduction},
+        version::{anonymity::ReviewAnonymity, PdfInfo, Version},
     },
+    pdf,
+    sniff::{self, FileType},
     state::AppState,
 };
 
 use super::common::{
-    auth::{AuthInfo, Permission},
+    auth::{Action, AuthInfo, Resource},
     docs,
     err::{self, Error},
     file,
-    handlers::{self, DeleteCfg, InsertCfg, SetCfg, ShowCfg},
+    handlers::{self, DeleteCfg, InsertCfg, ListCfg, NoFilter, SetCfg, ShowCfg},
 };
 
 struct InsertAuth;
@@ -48,13 +68,51 @@ impl InsertCfg for InsertAuth {
     }
 }
 
-fn authenticate(auth_info: AuthInfo, model: &Entity<Owned<Thesis>>) -> bool {
-    auth_info.permitted(Permission::Publishing)
-        || model.data.owner_id == auth_info.id
+/// Whether `auth_info` owns `model` or is one of its authors.
+fn is_author(auth_info: &AuthInfo, model: &Entity<Owned<Thesis>>) -> bool {
+    model.data.owner_id == auth_info.id
         || model.data.content.intro.author_ids.contains(&auth_info.id)
 }
 
-struct ShowAuth;
+/// Whether `auth_info` sits on the board of one of the magazines of `model`, and so may edit
+/// and adjudge its versions, unless they are one of its authors.
+pub(super) async fn is_editor(
+    auth_info: &AuthInfo,
+    db: mongo::MongoDatabase,
+    model: &Entity<Owned<Thesis>>,
+) -> err::Result<bool> {
+    if is_author(auth_info, model) {
+        return Ok(false);
+    }
+    Ok(!Magazine::ids_edited_by(db, auth_info.id)
+        .await?
+        .is_disjoint(&model.data.content.intro.magazine_ids))
+}
+
+/// Whether `auth_info` may see `model` and its versions however they are, as its editor or by a
+/// grant to publish in one of its magazines.
+pub(super) async fn oversees(
+    auth_info: &AuthInfo,
+    db: mongo::MongoDatabase,
+    model: &Entity<Owned<Thesis>>,
+) -> err::Result<bool> {
+    if is_editor(auth_info, db.clone(), model).await? {
+        return Ok(true);
+    }
+    let magazine_ids = model.data.content.intro.magazine_ids.iter().copied();
+    let resource = Resource::under(db, magazine_ids).await?;
+    Ok(auth_info.can(Action::Publish, &resource))
+}
+
+async fn authenticate(
+    auth_info: &AuthInfo,
+    db: mongo::MongoDatabase,
+    model: &Entity<Owned<Thesis>>,
+) -> err::Result<bool> {
+    Ok(is_author(auth_info, model) || is_editor(auth_info, db, model).await?)
+}
+
+pub(super) struct ShowAuth;
 
 #[async_trait]
 impl ShowCfg for ShowAuth {
@@ -64,10 +122,68 @@ impl ShowCfg for ShowAuth {
 
     async fn authenticate(
         auth_info: AuthInfo,
-        _db: mongo::MongoDatabase,
+        db: mongo::MongoDatabase,
         model: &Entity<Self::D>,
     ) -> super::common::err::Result<bool> {
-        Ok(model.data.is_public.clone() || authenticate(auth_info, model))
+        Ok(model.data.is_public
+            || is_author(&auth_info, model)
+            || oversees(&auth_info, db, model).await?)
+    }
+
+    async fn redact(
+        auth_info: Option<&AuthInfo>,
+        db: mongo::MongoDatabase,
+        model: &mut Entity<Self::D>,
+    ) -> err::Result<()> {
+        redact(auth_info, db, model).await
+    }
+}
+
+/// Takes out of `model` the authors its review anonymity keeps from `auth_info`.
+async fn redact(
+    auth_info: Option<&AuthInfo>,
+    db: mongo::MongoDatabase,
+    model: &mut Entity<Owned<Thesis>>,
+) -> err::Result<()> {
+    let anonymity = Thesis::anonymity(db.clone(), model).await?;
+    let viewer = super::version::viewer(auth_info, db, model).await?;
+    anonymity.redact_thesis(viewer, &mut model.data);
+    Ok(())
+}
+
+#[async_trait]
+impl ListCfg for ShowAuth {
+    type F = NoFilter;
+
+    fn downloads_path() -> Option<&'static str> {
+        Some(
+            field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(downloads in Thesis)),
+        )
+    }
+
+    async fn filter(
+        auth_info: Option<AuthInfo>,
+        db: mongo::MongoDatabase,
+        _filter: Self::F,
+    ) -> super::common::err::Result<Document> {
+        let is_public =
+            doc! {field!((data in Entity<Owned<Thesis>>).(is_public in Owned<Thesis>)): true};
+        let Some(auth_info) = auth_info else {
+            return Ok(is_public);
+        };
+        let Some(mut collection_ids) = auth_info
+            .collection_ids_for(db.clone(), Action::Publish)
+            .await?
+        else {
+            return Ok(doc! {});
+        };
+        collection_ids.extend(Magazine::ids_edited_by(db, auth_info.id).await?);
+        Ok(doc! {Or: [
+            is_public,
+            {field!((data in Entity<Owned<Thesis>>).(owner_id in Owned<Thesis>)): auth_info.id},
+            {field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(author_ids in ThesisIntroduction)): auth_info.id},
+            {field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(magazine_ids in ThesisIntroduction)): {In: collection_ids.into_iter().collect::<Vec<_>>()}},
+        ]})
     }
 }
 
@@ -84,11 +200,13 @@ impl SetCfg for UpdateAuth {
         patch: &<Owned<Self::OC> as SettableData>::P,
     ) -> super::common::err::Result<bool> {
         if let Some(magazine_ids) = &patch.magazine_ids {
-            if !<Entity<Owned<PaperCollection<Magazine>>>>::include(db, magazine_ids).await? {
+            if !<Entity<Owned<PaperCollection<Magazine>>>>::include(db.clone(), magazine_ids)
+                .await?
+            {
                 return Err(Error::BadReqest("invalid magazine id".to_string()));
             }
         }
-        Ok(authenticate(auth_info, model))
+        authenticate(&auth_info, db, model).await
     }
 }
 
@@ -100,48 +218,309 @@ impl DeleteCfg for DeleteAuth {
 
     async fn authenticate(
         auth_info: AuthInfo,
-        _db: mongo::MongoDatabase,
+        db: mongo::MongoDatabase,
         model: &Entity<Owned<Self::Cd>>,
     ) -> super::common::err::Result<bool> {
-        Ok(if model.data.is_public {
-            auth_info.permitted(Permission::Publishing)
+        if model.data.is_public {
+            is_editor(&auth_info, db, model).await
         } else {
-            authenticate(auth_info, model)
-        })
+            authenticate(&auth_info, db, model).await
+        }
     }
 }
 
 type Res = Json<EntityView<<Owned<Thesis> as Viewable>::View>>;
 
+type ListRes = Json<Page<EntityView<<Owned<Thesis> as Viewable>::View>>>;
+
+/// What became of a commit.
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct Committed {
+    #[schemars(title = "Version ID")]
+    id: ObjectIdDef,
+    #[schemars(
+        title = "Warnings",
+        description = "About the release, which was accepted all the same."
+    )]
+    warnings: Vec<String>,
+}
+
+/// Reads the release and checks it against the thesis and its magazines.
+///
+/// Too few pages for a magazine reject the release, while doubts only warn.
+async fn inspect_release(
+    state: &AppState,
+    thesis: &Entity<Owned<Thesis>>,
+    release_id: ObjectId,
+) -> err::Result<(Option<PdfInfo>, Vec<String>)> {
+    let bucket = state.mongo_db.gridfs_bucket(None);
+    let (content, whole) = gridfs::read_at_most(&bucket, release_id, pdf::SIZE_LIMIT).await?;
+    let head = &content[..content.len().min(sniff::PEEK_LEN)];
+    if sniff::detect(head) != Some(FileType::Pdf) {
+        return Ok((None, Vec::new()));
+    }
+    if !whole {
+        return Err(Error::TooLarge(format!(
+            "a PDF release can be at most {} bytes",
+            pdf::SIZE_LIMIT
+        )));
+    }
+    let read = tokio::task::spawn_blocking(move || PdfInfo::read(&content)).await?;
+    let Some(pdf) = read.map_err(|e| Error::TooLarge(e.to_string()))? else {
+        return Ok((
+            None,
+            vec!["the release cannot be read, so its pages and title are not checked".to_string()],
+        ));
+    };
+    let intro = &thesis.data.content.intro;
+    for magazine in Magazine::find_by_ids(state.mongo_db.clone(), &intro.magazine_ids).await? {
+        let pages_min = magazine.data.content.detail.pages_min;
+        if pdf.pages < pages_min {
+            return Err(Error::BadReqest(format!(
+                "the release has {} pages, but {} wants at least {}",
+                pdf.pages, magazine.data.content.name, pages_min
+            )));
+        }
+    }
+    let mut warnings = Vec::new();
+    match &pdf.title {
+        None => warnings.push("the release has no title in its metadata".to_string()),
+        Some(title) if text::terms([title.as_str()]) != text::terms([intro.title.as_str()]) => {
+            warnings.push(format!(
+                "the release is titled {:?}, but the thesis {:?}",
+                title, intro.title
+            ))
+        }
+        Some(_) => {}
+    }
+    Ok((Some(pdf), warnings))
+}
+
 #[debug_handler]
 async fn commit(
     auth_info: AuthInfo,
     State(state): State<AppState>,
     Path(id): Path<ObjectIdDef>,
     mut multipart: Multipart,
-) -> err::Result<ObjectIdDef> {
+) -> err::Result<Json<Committed>> {
     let id = id.unpack();
     let model = <Entity<Owned<Thesis>>>::try_find_one_by_id(state.mongo_db.clone(), id)
         .await?
         .ok_or(Error::BadReqest("wrong thesis id".to_string()))?;
-    if authenticate(auth_info, &model).not() {
+    if authenticate(&auth_info, state.mongo_db.clone(), &model)
+        .await?
+        .not()
+    {
         return Err(Error::Forbidden("cannot commit".to_string()));
     }
-    let file_ids = file::upload_files(state.mongo_db.clone(), &mut multipart).await?;
-    Thesis::commit(
-        state.mongo_db,
-        auth_info.id,
-        id,
-        file_ids
-            .first()
-            .ok_or(Error::BadReqest("at least one file".to_string()))?
-            .to_owned(),
-        file_ids.into_iter().skip(1).collect(),
+    // The first file is the release, and the others are its sources.
+    let file_ids = file::upload_files(&state, &mut multipart, |n| match n {
+        0 => &state.release_types,
+        _ => &state.source_types,
+    })
+    .await?;
+    let Some(&release_id) = file_ids.first() else {
+        return Err(Error::BadReqest("at least one file".to_string()));
+    };
+    let committed = async {
+        let (pdf, warnings) = inspect_release(&state, &model, release_id).await?;
+        for &source_id in &file_ids[1..] {
+            file::index_archive(&state, source_id).await?;
+        }
+        let version_id = Thesis::commit(
+            state.mongo_db.clone(),
+            auth_info.id,
+            id,
+            release_id,
+            file_ids.iter().skip(1).copied().collect(),
+            pdf,
+        )
+        .await?
+        .ok_or(Error::NotFound("cannot get new version id".to_string()))?;
+        Ok(Committed {
+            id: ObjectIdDef::pack(version_id),
+            warnings,
+        })
+    }
+    .await;
+    if committed.is_err() {
+        crate::mongo_entities::file::release(state.mongo_db, file_ids).await?;
+    }
+    committed.map(Json)
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[schemars(title = "Query", length(min = 1))]
+    q: String,
+    #[serde(default)]
+    #[schemars(
+        title = "Magazine IDs",
+        description = "Comma-separated. Theses in any of these magazines."
+    )]
+    magazine_ids: Option<String>,
+    #[schemars(title = "Language")]
+    language: Option<String>,
+    #[schemars(
+        title = "Author ID",
+        description = "Leaves out theses under double-blind review, unless you are the author."
+    )]
+    author_id: Option<ObjectIdDef>,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct Highlights {
+    #[schemars(description = "Matched parts are wrapped in <em> tags.")]
+    title: Option<String>,
+    #[schemars(description = "Pieces around the matched parts.")]
+    abstraction: Vec<String>,
+    #[schemars(description = "Matched keywords.")]
+    keywords: Vec<String>,
+}
+
+impl Highlights {
+    fn new(intro: &ThesisIntroduction, terms: &BTreeSet<String>) -> Self {
+        Self {
+            title: text::highlight(&intro.title, terms),
+            abstraction: text::snippets(&intro.abstraction, terms, 3),
+            keywords: intro
+                .keywords
+                .iter()
+                .filter_map(|keyword| text::highlight(keyword, terms))
+                .collect(),
+        }
+    }
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct SearchHit {
+    #[serde(flatten)]
+    thesis: EntityView<<Owned<Thesis> as Viewable>::View>,
+    highlights: Highlights,
+}
+
+type SearchRes = Json<Page<SearchHit>>;
+
+#[debug_handler]
+async fn search(
+    auth_info: Option<AuthInfo>,
+    State(state): State<AppState>,
+    Query(paging): Query<Paging>,
+    Query(query): Query<SearchQuery>,
+) -> err::Result<SearchRes> {
+    let terms: BTreeSet<String> = text::terms([query.q.as_str()]).into_iter().collect();
+    if terms.is_empty() {
+        return Err(Error::BadReqest("nothing to search".to_string()));
+    }
+    let mut filter = doc! {
+        Text: {"$search": terms.iter().map(String::as_str).collect::<Vec<_>>().join(" ")},
+        field!((data in Entity<Owned<Thesis>>).(is_public in Owned<Thesis>)): true,
+    };
+    if let Some(magazine_ids) = query.magazine_ids {
+        let magazine_ids = magazine_ids
+            .split(',')
+            .map(|id| id.trim().parse::<ObjectId>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| Error::BadReqest("invalid magazine IDs".to_string()))?;
+        filter.insert(
+            field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(magazine_ids in ThesisIntroduction)),
+            doc! {In: magazine_ids},
+        );
+    }
+    if let Some(language) = query.language {
+        filter.insert(
+            field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(language in ThesisIntroduction)),
+            language,
+        );
+    }
+    if let Some(author_id) = query.author_id {
+        let author_id = author_id.unpack();
+        filter.insert(
+            field!((data in Entity<Owned<Thesis>>).(content in Owned<Thesis>).(intro in Thesis).(author_ids in ThesisIntroduction)),
+            author_id,
+        );
+        // Otherwise the filter would tell who wrote the theses their review keeps anonymous.
+        if auth_info.map(|auth_info| auth_info.id) != Some(author_id) {
+            filter = doc! {And: [filter, Thesis::authors_shown(state.mongo_db.clone()).await?]};
+        }
+    }
+    let mut page = <Entity<Owned<Thesis>>>::find_page(
+        state.mongo_db.clone(),
+        filter,
+        doc! {"score": {Meta: "textScore"}},
+        paging,
     )
-    .await
-    .map_err(Error::from)?
-    .ok_or(Error::NotFound("cannot get new version id".to_string()))
-    .map(ObjectIdDef::pack)
+    .await?;
+    for thesis in &mut page.items {
+        redact(None, state.mongo_db.clone(), thesis).await?;
+    }
+    Ok(Json(page.map(|thesis| SearchHit {
+        highlights: Highlights::new(&thesis.data.content.intro, &terms),
+        thesis: thesis.into(),
+    })))
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct RoundView {
+    #[schemars(title = "Major Number")]
+    major_number: i32,
+    #[schemars(title = "Versions", description = "Oldest first.")]
+    versions: Vec<EntityView<<Attached<Version> as Viewable>::View>>,
+    #[schemars(title = "Reviews", description = "Of all the versions, version by version.")]
+    reviews: Vec<EntityView<<Attached<Review> as Viewable>::View>>,
+}
+
+#[debug_handler]
+async fn rounds(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+) -> err::Result<Json<Vec<RoundView>>> {
+    let id = id.unpack();
+    let thesis = <Entity<Owned<Thesis>>>::try_find_one_by_id(state.mongo_db.clone(), id)
+        .await?
+        .ok_or(Error::NotFound(format!("no thesis with id {}", id)))?;
+    if !(is_author(&auth_info, &thesis)
+        || oversees(&auth_info, state.mongo_db.clone(), &thesis).await?)
+    {
+        return Err(Error::Forbidden("no permission".to_string()));
+    }
+    let viewer = super::version::viewer(Some(&auth_info), state.mongo_db.clone(), &thesis).await?;
+    let mut rounds = Version::rounds(state.mongo_db.clone(), id).await?;
+    let mut anonymities = HashMap::new();
+    for round in &mut rounds {
+        for version in &mut round.versions {
+            let anonymity = version
+                .data
+                .content
+                .anonymity(state.mongo_db.clone(), &thesis)
+                .await?;
+            anonymity.redact_version(viewer, &mut version.data);
+            anonymities.insert(version._id, anonymity);
+        }
+        for review in &mut round.reviews {
+            anonymities
+                .get(&review.data.content.version_id)
+                .copied()
+                .unwrap_or(ReviewAnonymity::DoubleBlind)
+                .redact_review(viewer, &mut review.data);
+        }
+    }
+    Ok(Json(
+        rounds
+            .into_iter()
+            .map(|round| RoundView {
+                major_number: round.major_number,
+                versions: round.versions.into_iter().map(Into::into).collect(),
+                reviews: round.reviews.into_iter().map(Into::into).collect(),
+            })
+            .collect(),
+    ))
 }
 
 fn tag(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
@@ -161,6 +540,22 @@ pub(super) fn route() -> ApiRouter<AppState> {
                         .default_response_with::<ObjectIdDef, _>(
                             docs::require_cookie::<ObjectIdDef>,
                         )
+                })
+                .get_with(handlers::list_objects::<ShowAuth>, |op| {
+                    op.summary("list theses")
+                        .description("public ones, plus private ones you can see with a cookie")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response::<ListRes>()
+                }),
+                tag,
+            )
+            .api_route_with(
+                "/search",
+                routing::get_with(search, |op| {
+                    op.summary("search public theses")
+                        .description("by title, abstraction and keywords, most relevant first")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response::<SearchRes>()
                 }),
                 tag,
             )
@@ -178,6 +573,25 @@ pub(super) fn route() -> ApiRouter<AppState> {
                 })
                 .delete_with(handlers::delete_object::<DeleteAuth>, |op| {
                     op.summary("delete a thesis")
+                        .description(
+                            "moves it to the trash, from where it can be restored for a while",
+                        )
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Res, _>(docs::require_cookie::<Res>)
+                }),
+                |op| {
+                    docs::add_one_parameter(
+                        tag(op),
+                        "id".to_string(),
+                        Some("thesis id".to_string()),
+                        Some(ObjectId::new().to_hex().into()),
+                    )
+                },
+            )
+            .api_route_with(
+                "/:id/restore",
+                routing::post_with(handlers::restore_object::<DeleteAuth>, |op| {
+                    op.summary("restore a thesis from the trash")
                         .security_requirement(docs::SECURITY_SCHEME_NAME)
                         .default_response_with::<Res, _>(docs::require_cookie::<Res>)
                 }),
@@ -190,13 +604,43 @@ pub(super) fn route() -> ApiRouter<AppState> {
                     )
                 },
             )
+            .api_route_with(
+                "/:id/rounds",
+                routing::get_with(rounds, |op| {
+                    op.summary("list the review rounds of a thesis")
+                        .description("each with its versions and their reviews, oldest first")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Json<Vec<RoundView>>, _>(
+                            docs::require_cookie::<Json<Vec<RoundView>>>,
+                        )
+                }),
+                |op| {
+                    docs::add_one_parameter(
+                        tag(op),
+                        "id".to_string(),
+                        Some("thesis id".to_string()),
+                        Some(ObjectId::new().to_hex().into()),
+                    )
+                },
+            )
             .api_route_with(
                 "/:id/commit",
                 routing::post_with(commit, |op| {
                     op.summary("上传新版本")
+                        .description(
+                            "The first file is the release, and the others are its sources. \
+                            Their types are told by their content, not by what the client says. \
+                            A PDF release is read, and rejected if it has fewer pages than one of \
+                            the magazines of the thesis wants. \
+                            Zip, tar and gzipped tar sources are listed for browsing.",
+                        )
                         .security_requirement(docs::SECURITY_SCHEME_NAME)
-                        .default_response_with::<Res, _>(docs::require_cookie::<Res>)
-                }),
+                        .default_response_with::<Json<Committed>, _>(
+                            docs::require_cookie::<Json<Committed>>,
+                        )
+                })
+                // `upload_files` enforces the configured limits itself.
+                .layer(DefaultBodyLimit::disable()),
                 |op| {
                     docs::add_one_parameter(
                         tag(op),
@@ -0,0 +1,93 @@
+//! What the logged-in user has deleted lately.
+
+use aide::axum::{routing, ApiRouter};
+use axum::{debug_handler, extract::State};
+use axum_jsonschema::Json;
+use chrono::{DateTime, Duration, Utc};
+use crud::Viewable;
+use mongo::{
+    entity::{doc, field, Entity},
+    oid::ObjectId,
+    owned::{Owned, OwnedContent},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    mongo_entities::{
+        paper_collection::{category::Category, magazine::Magazine, PaperCollection},
+        thesis::Thesis,
+    },
+    state::AppState,
+};
+
+use super::common::{auth::AuthInfo, docs, err::Result};
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct Trashed<V> {
+    #[serde(flatten)]
+    object: V,
+    delete_time: DateTime<Utc>,
+    #[schemars(
+        title = "Purge Time",
+        description = "It cannot be restored after this."
+    )]
+    purge_time: DateTime<Utc>,
+}
+
+type TrashedView<C> = Trashed<<Entity<Owned<C>> as Viewable>::View>;
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct Trash {
+    theses: Vec<TrashedView<Thesis>>,
+    magazines: Vec<TrashedView<PaperCollection<Magazine>>>,
+    categories: Vec<TrashedView<PaperCollection<Category>>>,
+}
+
+async fn trashed<C: OwnedContent>(
+    state: &AppState,
+    owner_id: ObjectId,
+) -> Result<Vec<TrashedView<C>>> {
+    let retention = Duration::seconds(state.trash_retention.try_into().unwrap_or(i64::MAX));
+    let mut found = <Entity<Owned<C>>>::find_trashed(
+        state.mongo_db.clone(),
+        doc! {field!((data in Entity<Owned<()>>).(owner_id in Owned<()>)): owner_id},
+    )
+    .await?;
+    let mut trashed = Vec::new();
+    while found.advance().await? {
+        let entity = found.deserialize_current()?;
+        let delete_time = entity.deleted_at.unwrap_or(entity.updated_at).to_chrono();
+        trashed.push(Trashed {
+            object: entity.into(),
+            delete_time,
+            purge_time: delete_time + retention,
+        });
+    }
+    Ok(trashed)
+}
+
+#[debug_handler]
+async fn list(auth_info: AuthInfo, State(state): State<AppState>) -> Result<Json<Trash>> {
+    Ok(Json(Trash {
+        theses: trashed(&state, auth_info.id).await?,
+        magazines: trashed(&state, auth_info.id).await?,
+        categories: trashed(&state, auth_info.id).await?,
+    }))
+}
+
+pub(super) fn route() -> ApiRouter<AppState> {
+    ApiRouter::new().api_route_with(
+        "/trash",
+        routing::get_with(list, |op| {
+            op.summary("list my deleted objects")
+                .description("restore them at `/:kind/:id/restore` before they are purged")
+                .security_requirement(docs::SECURITY_SCHEME_NAME)
+                .default_response::<Json<Trash>>()
+        }),
+        |op| op.tag("trash"),
+    )
+}
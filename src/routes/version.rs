@@ -2,41 +2,53 @@ use aide::axum::{routing, ApiRouter};
 use async_trait::async_trait;
 use axum::body::Bytes;
 use axum::{
-    body::StreamBody,
     debug_handler,
-    extract::{Path, State},
-    http::HeaderName,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
 };
 use axum_jsonschema::Json;
 use crud::{Countable, Viewable};
-use futures_util::Stream;
+use mongo::bson::{to_bson, Document};
+use mongo::entity::page::Page;
 use mongo::entity::update::Update;
+use mongo::entity::{doc, field, operator::*};
 use mongo::oid::ObjectId;
 use mongo::owned::Owned;
+use mongo::MongoError;
 use mongo::{
     attached::Attached,
     entity::{Entity, EntityView},
     oid::ObjectIdDef,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use schemars::JsonSchema;
 
 use crate::mongo_entities::profile::Profile;
-use crate::mongo_entities::review::Review;
+use crate::mongo_entities::paper_collection::magazine::Magazine;
+use crate::mongo_entities::review::{
+    form::{ReviewForm, ReviewFormError, ReviewSummary},
+    Review,
+};
 use crate::mongo_entities::thesis::Thesis;
-use crate::mongo_entities::version::ReviewPattern;
 use super::common::{docs, notice};
 use crate::{
-    mongo_entities::version::{Reviewing, Version, VersionState},
+    diff,
+    mongo_entities::version::{
+        anonymity::{ReviewAnonymity, Viewer},
+        workflow, Reviewing, Version, VersionState,
+    },
+    pdf::{Exhausted, Pdf},
+    sniff::{self, FileType},
     state::AppState,
 };
 
 use super::common::{
-    auth::{AuthInfo, Permission},
+    auth::{Action, AuthInfo, Grant, Resource},
     err::{Error, Result},
-    file,
-    handlers::{self, ShowCfg},
+    file::{self, DownloadQuery, EntryQuery, StoredFile},
+    handlers::{self, ListCfg, ShowCfg},
 };
 
 pub(super) struct ShowAuth;
@@ -53,24 +65,190 @@ impl ShowCfg for ShowAuth {
         model: &mongo::entity::Entity<Self::D>,
     ) -> super::common::err::Result<bool> {
         match model.data.content.state {
-            VersionState::History | VersionState::Passed(true) => Ok(true),
+            VersionState::Passed(true) => Ok(true),
+            VersionState::History
+                if Version::published(db.clone(), model.data.content.thesis_id).await? =>
+            {
+                Ok(true)
+            }
             _ => {
-                if auth_info.permitted(Permission::Publishing)
-                    || model.data.creator_id == Some(auth_info.id)
-                {
+                if model.data.creator_id == Some(auth_info.id) {
                     Ok(true)
                 } else if let VersionState::Reviewing(Reviewing { remainder_ids, .. }) =
                     &model.data.content.state
                 {
-                    Ok(remainder_ids.contains(&auth_info.id))
-                } else if let Some(thesis) = model.data.content.thesis(db.clone()).await? {
-                    Ok(thesis.data.content.intro.author_ids.contains(&auth_info.id))
+                    if remainder_ids.contains(&auth_info.id) {
+                        return Ok(true);
+                    }
+                    is_author_or_editor(&auth_info, db, model).await
                 } else {
-                    Ok(false)
+                    is_author_or_editor(&auth_info, db, model).await
                 }
             }
         }
     }
+
+    async fn redact(
+        auth_info: Option<&AuthInfo>,
+        db: mongo::MongoDatabase,
+        model: &mut Entity<Self::D>,
+    ) -> Result<()> {
+        redact(auth_info, db, model).await
+    }
+}
+
+/// Where `auth_info` stands to `thesis`, as far as review anonymity goes. Authors count as
+/// authors even if they edit the magazine too.
+pub(super) async fn viewer(
+    auth_info: Option<&AuthInfo>,
+    db: mongo::MongoDatabase,
+    thesis: &Entity<Owned<Thesis>>,
+) -> Result<Viewer> {
+    let Some(auth_info) = auth_info else {
+        return Ok(Viewer::Other(None));
+    };
+    Ok(
+        if thesis.data.owner_id == auth_info.id
+            || thesis.data.content.intro.author_ids.contains(&auth_info.id)
+        {
+            Viewer::Author(auth_info.id)
+        } else if super::thesis::is_editor(auth_info, db, thesis).await? {
+            Viewer::Editor
+        } else {
+            Viewer::Other(Some(auth_info.id))
+        },
+    )
+}
+
+/// Takes out of `model` whom its review anonymity keeps from `auth_info`.
+pub(super) async fn redact(
+    auth_info: Option<&AuthInfo>,
+    db: mongo::MongoDatabase,
+    model: &mut Entity<Attached<Version>>,
+) -> Result<()> {
+    let (anonymity, viewer) = blinds(auth_info, db, &model.data.content).await?;
+    anonymity.redact_version(viewer, &mut model.data);
+    Ok(())
+}
+
+/// How anonymous the review of `version` is, and where `auth_info` stands to it. Everyone is
+/// kept from everything if its thesis is gone.
+pub(super) async fn blinds(
+    auth_info: Option<&AuthInfo>,
+    db: mongo::MongoDatabase,
+    version: &Version,
+) -> Result<(ReviewAnonymity, Viewer)> {
+    let Some(thesis) = version.thesis(db.clone()).await? else {
+        return Ok((
+            ReviewAnonymity::DoubleBlind,
+            Viewer::Other(auth_info.map(|auth_info| auth_info.id)),
+        ));
+    };
+    Ok((
+        version.anonymity(db.clone(), &thesis).await?,
+        viewer(auth_info, db, &thesis).await?,
+    ))
+}
+
+async fn is_author_or_editor(
+    auth_info: &AuthInfo,
+    db: mongo::MongoDatabase,
+    model: &Entity<Attached<Version>>,
+) -> Result<bool> {
+    Ok(match model.data.content.thesis(db.clone()).await? {
+        Some(thesis) => {
+            thesis.data.content.intro.author_ids.contains(&auth_info.id)
+                || super::thesis::oversees(auth_info, db, &thesis).await?
+        }
+        None => false,
+    })
+}
+
+/// Finds the version `id` and the magazines its thesis is filed under, if `auth_info` is an
+/// editor there.
+async fn find_as_editor(
+    auth_info: &AuthInfo,
+    db: mongo::MongoDatabase,
+    id: ObjectId,
+) -> Result<(Entity<Attached<Version>>, Resource)> {
+    let version = <Entity<Attached<Version>>>::try_find_one_by_id(db.clone(), id)
+        .await
+        .map_err(Error::from)?
+        .ok_or(Error::NotFound("cannot get version entity".to_string()))?;
+    let thesis = version
+        .data
+        .content
+        .thesis(db.clone())
+        .await?
+        .ok_or(Error::NotFound("cannot get thesis entity".to_string()))?;
+    if !super::thesis::is_editor(auth_info, db.clone(), &thesis).await? {
+        return Err(Error::Forbidden(
+            "you are not an editor of the thesis, or are one of its authors".to_string(),
+        ));
+    }
+    let resource = Resource::under(db, thesis.data.content.intro.magazine_ids).await?;
+    Ok((version, resource))
+}
+
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+pub(super) struct VersionFilter {
+    #[schemars(title = "Thesis ID")]
+    thesis_id: ObjectIdDef,
+}
+
+#[async_trait]
+impl ListCfg for ShowAuth {
+    type F = VersionFilter;
+
+    fn downloads_path() -> Option<&'static str> {
+        Some(
+            field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(downloads in Version)),
+        )
+    }
+
+    async fn filter(
+        auth_info: Option<AuthInfo>,
+        db: mongo::MongoDatabase,
+        filter: Self::F,
+    ) -> Result<Document> {
+        let thesis_id = filter.thesis_id.unpack();
+        let thesis = <Entity<Owned<Thesis>>>::try_find_one_by_id(db.clone(), thesis_id)
+            .await?
+            .ok_or(Error::NotFound(format!("no thesis with id {}", thesis_id)))?;
+        let of_thesis = doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(thesis_id in Version)): thesis_id};
+        let state = field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(state in Version));
+        let mut visible = vec![doc! {format!("{}.Passed", state): true}];
+        if Version::published(db.clone(), thesis_id).await? {
+            visible.push(doc! {state: "History"});
+        }
+        if let Some(auth_info) = auth_info {
+            if thesis.data.content.intro.author_ids.contains(&auth_info.id)
+                || super::thesis::oversees(&auth_info, db, &thesis).await?
+            {
+                return Ok(of_thesis);
+            }
+            visible.push(doc! {field!((data in Entity<Attached<Version>>).(creator_id in Attached<Version>)): auth_info.id});
+            visible.push(doc! {format!("{}.Reviewing.{}", state, field!(remainder_ids in Reviewing)): auth_info.id});
+        }
+        Ok(doc! {And: [of_thesis, {Or: visible}]})
+    }
+}
+
+/// Finds the version `id`, if `auth_info` can see it.
+async fn find_visible(
+    auth_info: AuthInfo,
+    db: mongo::MongoDatabase,
+    id: ObjectId,
+) -> Result<Entity<Attached<Version>>> {
+    let version = <Entity<Attached<Version>>>::try_find_one_by_id(db.clone(), id)
+        .await
+        .map_err(Error::from)?
+        .ok_or(Error::NotFound("version not found".to_string()))?;
+    if !ShowAuth::authenticate(auth_info, db, &version).await? {
+        return Err(Error::Forbidden("cannot see this version".to_string()));
+    }
+    Ok(version)
 }
 
 #[debug_handler]
@@ -78,20 +256,22 @@ async fn release(
     auth_info: AuthInfo,
     State(state): State<AppState>,
     Path(id): Path<ObjectIdDef>,
-) -> Result<(
-    [(HeaderName, String); 3],
-    StreamBody<impl Stream<Item = std::io::Result<Vec<u8>>> + Sized>,
-)> {
-    let id = id.unpack();
-    let version = <Entity<Attached<Version>>>::try_find_one_by_id(state.mongo_db.clone(), id)
-        .await
-        .map_err(Error::from)?
-        .ok_or(Error::BadReqest("version not found".to_string()))?;
-    ShowAuth::authenticate(auth_info, state.mongo_db.clone(), &version).await?;
-    Version::downloads(state.mongo_db.clone(), &version).await?;
-    file::download_file(state.mongo_db, version.data.content.release_id)
-        .await
-        .map_err(Error::from)
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let version = find_visible(auth_info, state.mongo_db.clone(), id.unpack()).await?;
+    let response = file::download_file(
+        state.mongo_db.clone(),
+        version.data.content.release_id,
+        &headers,
+        query,
+    )
+    .await?;
+    // Viewers fetch a file piece by piece and again and again, which is not worth counting.
+    if response.status() == StatusCode::OK {
+        Version::downloads(state.mongo_db, &version).await?;
+    }
+    Ok(response)
 }
 
 #[debug_handler]
@@ -99,16 +279,10 @@ async fn source(
     auth_info: AuthInfo,
     State(state): State<AppState>,
     Path((id, index)): Path<(ObjectIdDef, usize)>,
-) -> Result<(
-    [(HeaderName, String); 3],
-    StreamBody<impl Stream<Item = std::io::Result<Vec<u8>>> + Sized>,
-)> {
-    let id = id.unpack();
-    let version = <Entity<Attached<Version>>>::try_find_one_by_id(state.mongo_db.clone(), id)
-        .await
-        .map_err(Error::from)?
-        .ok_or(Error::BadReqest("version not found".to_string()))?;
-    ShowAuth::authenticate(auth_info, state.mongo_db.clone(), &version).await?;
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let version = find_visible(auth_info, state.mongo_db.clone(), id.unpack()).await?;
     file::download_file(
         state.mongo_db,
         version
@@ -118,52 +292,343 @@ async fn source(
             .get(index)
             .ok_or(Error::BadReqest("no such source file".to_string()))?
             .to_owned(),
+        &headers,
+        query,
     )
     .await
-    .map_err(Error::from)
+}
+
+#[debug_handler]
+async fn sources(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+) -> Result<Json<Vec<StoredFile>>> {
+    let version = find_visible(auth_info, state.mongo_db.clone(), id.unpack()).await?;
+    file::describe_files(&state.mongo_db, &version.data.content.source_ids)
+        .await
+        .map(Json)
+}
+
+#[debug_handler]
+async fn source_entry(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path((id, index)): Path<(ObjectIdDef, usize)>,
+    Query(query): Query<EntryQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let version = find_visible(auth_info, state.mongo_db.clone(), id.unpack()).await?;
+    let source_id = *version
+        .data
+        .content
+        .source_ids
+        .get(index)
+        .ok_or(Error::BadReqest("no such source file".to_string()))?;
+    file::download_entry(&state, source_id, &headers, query).await
+}
+
+/// How large a file can be to be compared, since both sides of it are held at once.
+const DIFF_SIZE_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// How much of both versions together is read to compare them.
+const DIFF_BUDGET: u64 = 32 * 1024 * 1024;
+
+/// How many unchanged lines are shown around each change.
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+struct VersionDiff {
+    #[schemars(
+        title = "Added Files",
+        description = "Sources only the second version has, with files inside archives named `archive/path`."
+    )]
+    added: Vec<String>,
+    #[schemars(title = "Removed Files", description = "Sources only the first version has.")]
+    removed: Vec<String>,
+    #[schemars(title = "Changed Files", description = "Sources both have, but not alike.")]
+    changed: Vec<String>,
+    #[schemars(
+        title = "Uncompared Files",
+        description = "Sources both have, but too large to compare."
+    )]
+    uncompared: Vec<String>,
+    #[schemars(
+        title = "Source Diff",
+        description = "Of the changed sources, unified, and a line for each of them which is not text."
+    )]
+    sources: String,
+    #[schemars(title = "Release Diff")]
+    release: ReleaseDiff,
+}
+
+#[derive(JsonSchema)]
+#[derive(Serialize)]
+enum ReleaseDiff {
+    /// The text of the releases, unified, with a `[page n]` line starting each page; empty if
+    /// the text is alike.
+    Compared(String),
+    /// A release is not a readable PDF.
+    Unreadable,
+    /// A release is too large to compare.
+    TooLarge,
+}
+
+/// `content` as text, if it is.
+fn as_text(content: &[u8]) -> Option<&str> {
+    let head = &content[..content.len().min(sniff::PEEK_LEN)];
+    if sniff::detect(head) != Some(FileType::Text) {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
+
+/// The text of the PDF `content`, each page after a `[page n]` line.
+fn release_text(content: &[u8]) -> std::result::Result<Option<String>, Exhausted> {
+    let Some(pdf) = Pdf::parse(content) else {
+        return Ok(None);
+    };
+    let pages = pdf.page_texts();
+    if pdf.exhausted() {
+        return Err(Exhausted);
+    }
+    if pages.is_empty() {
+        return Ok(None);
+    }
+    let mut text = String::new();
+    for (n, page) in pages.iter().enumerate() {
+        text += &format!("[page {}]\n{}\n", n + 1, page);
+    }
+    Ok(Some(text))
+}
+
+#[debug_handler]
+async fn compare(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path((id, other_id)): Path<(ObjectIdDef, ObjectIdDef)>,
+) -> Result<Json<VersionDiff>> {
+    let old = find_visible(auth_info.clone(), state.mongo_db.clone(), id.unpack()).await?;
+    let new = find_visible(auth_info, state.mongo_db.clone(), other_id.unpack()).await?;
+    let (old, new) = (old.data.content, new.data.content);
+    if old.thesis_id != new.thesis_id {
+        return Err(Error::BadReqest(
+            "the versions are of different theses".to_string(),
+        ));
+    }
+    let limit = DIFF_SIZE_LIMIT.min(state.max_unpacked_size);
+    let mut budget = DIFF_BUDGET;
+    // The releases are read first, so that the sources cannot leave them out.
+    let old_release = file::read_files(&state, &[old.release_id], limit, &mut budget).await?;
+    let new_release = file::read_files(&state, &[new.release_id], limit, &mut budget).await?;
+    let old_sources = file::read_files(&state, &old.source_ids, limit, &mut budget).await?;
+    let new_sources = file::read_files(&state, &new.source_ids, limit, &mut budget).await?;
+    let mut compared = VersionDiff {
+        added: new_sources
+            .keys()
+            .filter(|name| !old_sources.contains_key(*name))
+            .cloned()
+            .collect(),
+        removed: old_sources
+            .keys()
+            .filter(|name| !new_sources.contains_key(*name))
+            .cloned()
+            .collect(),
+        changed: Vec::new(),
+        uncompared: Vec::new(),
+        sources: String::new(),
+        release: ReleaseDiff::Unreadable,
+    };
+    // Diffing large sources takes a while, so it is kept off the runtime.
+    let mut compared = tokio::task::spawn_blocking(move || {
+        for (name, old_content) in &old_sources {
+            let Some(new_content) = new_sources.get(name) else {
+                continue;
+            };
+            let (Some(old_content), Some(new_content)) = (old_content, new_content) else {
+                compared.uncompared.push(name.clone());
+                continue;
+            };
+            if old_content == new_content {
+                continue;
+            }
+            match (as_text(old_content), as_text(new_content)) {
+                (Some(old_text), Some(new_text)) => {
+                    compared.sources += &diff::unified(name, old_text, new_text, DIFF_CONTEXT);
+                }
+                _ => {
+                    compared.sources += &format!("Binary files a/{} and b/{} differ\n", name, name)
+                }
+            }
+            compared.changed.push(name.clone());
+        }
+        compared
+    })
+    .await?;
+    compared.release = match (
+        old_release.into_values().next(),
+        new_release.into_values().next(),
+    ) {
+        (Some(Some(old_content)), Some(Some(new_content))) => {
+            let compared = tokio::task::spawn_blocking(move || {
+                Ok(
+                    match (release_text(&old_content)?, release_text(&new_content)?) {
+                        (Some(old_text), Some(new_text)) => {
+                            Some(diff::unified("release", &old_text, &new_text, DIFF_CONTEXT))
+                        }
+                        _ => None,
+                    },
+                )
+            })
+            .await?;
+            match compared {
+                Ok(Some(unified)) => ReleaseDiff::Compared(unified),
+                Ok(None) => ReleaseDiff::Unreadable,
+                Err(Exhausted) => ReleaseDiff::TooLarge,
+            }
+        }
+        (Some(None), _) | (_, Some(None)) => ReleaseDiff::TooLarge,
+        _ => ReleaseDiff::Unreadable,
+    };
+    Ok(Json(compared))
 }
 
 type Res = Json<EntityView<<Attached<Version> as Viewable>::View>>;
 
+type ListRes = Json<Page<EntityView<<Attached<Version> as Viewable>::View>>>;
+
+/// Who are to review a version, and how anonymously.
+#[derive(JsonSchema)]
+#[derive(Deserialize)]
+struct Assignment {
+    #[serde(flatten)]
+    reviewing: Reviewing,
+    #[serde(default)]
+    #[schemars(
+        title = "Review Anonymity",
+        description = "That of the magazines of the thesis if not set."
+    )]
+    anonymity: Option<ReviewAnonymity>,
+    #[serde(default)]
+    #[schemars(
+        title = "Magazine of the Review Form",
+        description = "Needed only if the magazines of the thesis have different review forms."
+    )]
+    form_magazine_id: Option<ObjectIdDef>,
+}
+
+/// The review form of the magazine `form_magazine_id`, or else the one all the magazines of
+/// `version` share.
+async fn review_form(
+    db: mongo::MongoDatabase,
+    version: &Version,
+    form_magazine_id: Option<ObjectIdDef>,
+) -> Result<Option<ReviewForm>> {
+    let thesis = version
+        .thesis(db.clone())
+        .await?
+        .ok_or(Error::NotFound("cannot get thesis entity".to_string()))?;
+    let magazines = Magazine::find_by_ids(db, &thesis.data.content.intro.magazine_ids).await?;
+    let form = match form_magazine_id {
+        Some(magazine_id) => {
+            let magazine_id = magazine_id.unpack();
+            magazines
+                .into_iter()
+                .find(|magazine| magazine._id == magazine_id)
+                .ok_or(Error::BadReqest(format!(
+                    "the thesis is not filed under magazine {}",
+                    magazine_id
+                )))?
+                .data
+                .content
+                .detail
+                .review_form
+        }
+        None => {
+            let mut forms = magazines
+                .into_iter()
+                .filter_map(|magazine| magazine.data.content.detail.review_form);
+            let form = forms.next();
+            if forms.any(|other| Some(&other) != form.as_ref()) {
+                return Err(Error::BadReqest(
+                    "the magazines of the thesis have different review forms, choose one"
+                        .to_string(),
+                ));
+            }
+            form
+        }
+    };
+    if let Some(form) = &form {
+        form.check()?;
+    }
+    Ok(form)
+}
+
 #[debug_handler]
 async fn edit(
     auth_info: AuthInfo,
     State(state): State<AppState>,
     Path(id): Path<ObjectIdDef>,
-    Json(reviewing): Json<Reviewing>,
+    Json(Assignment {
+        reviewing,
+        anonymity,
+        form_magazine_id,
+    }): Json<Assignment>,
 ) -> Result<Res> {
     let id = id.unpack();
-    if !auth_info.permitted(Permission::Publishing) {
-        return Err(Error::Forbidden("you are not a editor".to_string()));
-    }
-    let version = <Entity<Attached<Version>>>::try_find_one_by_id(state.mongo_db.clone(), id)
-        .await
-        .map_err(Error::from)?
-        .ok_or(Error::NotFound("cannot get version entity".to_string()))?;
-    if let VersionState::Uploaded = version.data.content.state {
-        for &reviewer_id in &reviewing.remainder_ids {
-            let reviewer = <Entity<Profile>>::try_find_one_by_id(state.mongo_db.clone(), reviewer_id)
-                .await
-                .map_err(Error::from)?
-                .ok_or(Error::BadReqest(format!(
-                    "invalid reviewer id {}",
-                    reviewer_id
-                )))?;
-            tokio::spawn(notice::send_email(state.clone(), reviewer, "new review task", ""));
+    let (version, resource) = find_as_editor(&auth_info, state.mongo_db.clone(), id).await?;
+    // Fail before checking the reviewers if the version is no longer to be assigned.
+    version
+        .data
+        .content
+        .state
+        .next(&workflow::Action::Assign(reviewing.clone()))?;
+    let mut reviewers = Vec::new();
+    for &reviewer_id in &reviewing.remainder_ids {
+        let reviewer = <Entity<Profile>>::try_find_one_by_id(state.mongo_db.clone(), reviewer_id)
+            .await
+            .map_err(Error::from)?
+            .ok_or(Error::BadReqest(format!(
+                "invalid reviewer id {}",
+                reviewer_id
+            )))?;
+        let grants = Grant::load(&state.sql_db, reviewer.data.email.as_ref()).await?;
+        if !AuthInfo::new(reviewer_id, grants).can(Action::Review, &resource) {
+            return Err(Error::BadReqest(format!(
+                "{} is not a reviewer of this thesis",
+                reviewer_id
+            )));
         }
-        <Entity<Attached<Version>>>::try_find_one_and_update_by_id(
-            state.mongo_db,
-            id,
-            Version::set_state(Update::default(), VersionState::Reviewing(reviewing))
-                .map_err(Error::from)?,
-        )
-        .await
-        .map_err(Error::from)?
-        .ok_or(Error::NotFound("cannot get updated version".to_string()))
-        .map(|v| Json(v.into()))
-    } else {
-        Err(Error::BadReqest("edited version".to_string()))
+        reviewers.push(reviewer);
+    }
+    let form = review_form(state.mongo_db.clone(), &version.data.content, form_magazine_id).await?;
+    let mut update = Update::default();
+    if let Some(form) = form {
+        update.set.insert(
+            field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(review_form in Version)),
+            to_bson(&form).map_err(MongoError::from)?,
+        );
+    }
+    if let Some(anonymity) = anonymity {
+        update.set.insert(
+            field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(anonymity in Version)),
+            to_bson(&anonymity).map_err(MongoError::from)?,
+        );
     }
+    let mut version = Version::transit(
+        state.mongo_db.clone(),
+        &version,
+        workflow::Action::Assign(reviewing),
+        Some(auth_info.id),
+        update,
+    )
+    .await?;
+    for reviewer in reviewers {
+        tokio::spawn(notice::send_email(state.clone(), reviewer, "new review task", ""));
+    }
+    redact(Some(&auth_info), state.mongo_db, &mut version).await?;
+    Ok(Json(version.into()))
 }
 
 #[debug_handler]
@@ -173,39 +638,76 @@ async fn adjudge(
     Path(id): Path<ObjectIdDef>,
     Json(judgement): Json<bool>,
 ) -> Result<Res> {
-    let id = id.unpack();
-    if !auth_info.permitted(Permission::Publishing) {
-        return Err(Error::Forbidden("you are not a editor".to_string()));
+    let (version, _) = find_as_editor(&auth_info, state.mongo_db.clone(), id.unpack()).await?;
+    let mut version = Version::transit(
+        state.mongo_db.clone(),
+        &version,
+        workflow::Action::Adjudge(judgement),
+        Some(auth_info.id),
+        Update::default(),
+    )
+    .await?;
+    if judgement {
+        <Entity<Owned<Thesis>>>::set_visibility(
+            state.mongo_db.clone(),
+            version.data.content.thesis_id,
+            true,
+        )
+        .await
+        .map_err(Error::from)?;
     }
-    let version = <Entity<Attached<Version>>>::try_find_one_by_id(state.mongo_db.clone(), id)
+    redact(Some(&auth_info), state.mongo_db, &mut version).await?;
+    Ok(Json(version.into()))
+}
+
+#[debug_handler]
+async fn request_revision(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+) -> Result<Res> {
+    let (version, _) = find_as_editor(&auth_info, state.mongo_db.clone(), id.unpack()).await?;
+    let mut version = Version::transit(
+        state.mongo_db.clone(),
+        &version,
+        workflow::Action::RequestRevision,
+        Some(auth_info.id),
+        Update::default(),
+    )
+    .await?;
+    redact(Some(&auth_info), state.mongo_db, &mut version).await?;
+    Ok(Json(version.into()))
+}
+
+#[debug_handler]
+async fn withdraw(
+    auth_info: AuthInfo,
+    State(state): State<AppState>,
+    Path(id): Path<ObjectIdDef>,
+) -> Result<Res> {
+    let version = <Entity<Attached<Version>>>::try_find_one_by_id(state.mongo_db.clone(), id.unpack())
         .await
         .map_err(Error::from)?
         .ok_or(Error::NotFound("cannot get version entity".to_string()))?;
-    match version.data.content.state {
-        VersionState::Reviewing(Reviewing {
-            pattern: ReviewPattern::Editor,
-            remainder_ids,
-        }) if !remainder_ids.is_empty() => Err(Error::BadReqest("reviewing".to_string())),
-        VersionState::Reviewing(Reviewing {
-            pattern: ReviewPattern::Editor,
-            ..
-        })
-        | VersionState::Uploaded => {
-            <Entity<Attached<Version>>>::try_find_one_and_update_by_id(state.mongo_db.clone(), id, {
-                if judgement {
-                    <Entity<Owned<Thesis>>>::set_visibility(
-                        state.mongo_db,
-                        version.data.content.thesis_id,
-                        true,
-                    )
-                    .await
-                    .map_err(Error::from)?;
-                }
-                Version::set_state(Update::default(), VersionState::Passed(judgement))?
-            }).await?.ok_or(Error::NotFound("cannot get updated version".to_string())).map(|v|Json(v.into()))
-        }
-        _ => Err(Error::BadReqest("cannot adjudge this version".to_string())),
+    let thesis = version
+        .data
+        .content
+        .thesis(state.mongo_db.clone())
+        .await?
+        .ok_or(Error::NotFound("cannot get thesis entity".to_string()))?;
+    if !thesis.data.content.intro.author_ids.contains(&auth_info.id) {
+        return Err(Error::Forbidden("you are not an author".to_string()));
     }
+    let mut version = Version::transit(
+        state.mongo_db.clone(),
+        &version,
+        workflow::Action::Withdraw,
+        Some(auth_info.id),
+        Update::default(),
+    )
+    .await?;
+    redact(Some(&auth_info), state.mongo_db, &mut version).await?;
+    Ok(Json(version.into()))
 }
 
 #[derive(JsonSchema)]
@@ -224,68 +726,83 @@ async fn review(
     Json(review): Json<Review>,
 ) -> Result<Json<ReviewRes>> {
     let id = id.unpack();
+    let review = review.posted_to(id);
     let version = <Entity<Attached<Version>>>::try_find_one_by_id(state.mongo_db.clone(), id)
         .await
         .map_err(Error::from)?
         .ok_or(Error::NotFound("cannot get version entity".to_string()))?;
-    match version.data.content.state {
-        VersionState::Reviewing(Reviewing {
-            remainder_ids,
-            pattern,
-        }) if remainder_ids.contains(&auth_info.id) => {
-            let judgement = review.judgement.clone();
-            let review_id = <Entity<Attached<Review>>>::insert_one(
-                state.mongo_db.clone(),
-                Attached {
-                    creator_id: Some(auth_info.id),
-                    content: review,
-                },
-            )
+    let VersionState::Reviewing(Reviewing { remainder_ids, .. }) = &version.data.content.state
+    else {
+        return Err(Error::Forbidden("no permission to reviewing it".to_string()));
+    };
+    if !remainder_ids.contains(&auth_info.id) {
+        return Err(Error::Forbidden("no permission to reviewing it".to_string()));
+    }
+    match &version.data.content.review_form {
+        Some(form) => form.check_review(&review)?,
+        None if !review.scores.is_empty() || review.recommendation.is_some() => {
+            return Err(ReviewFormError::NoForm.into())
+        }
+        None => {}
+    }
+    let mut reviews = Vec::new();
+    for &review_id in &version.data.content.review_ids {
+        let found = <Entity<Attached<Review>>>::try_find_one_by_id(state.mongo_db.clone(), review_id)
             .await
-            .map_err(Error::from)?
-            .ok_or(Error::NotFound("cannot get reviewer id".to_string()))?;
-            let remainder_count = remainder_ids.len() - 1;
-            if let (0, true, ReviewPattern::Reviewer) =
-                (remainder_count.clone(), judgement, pattern)
-            {
-                let mut judgement = true;
-                for review_id in version.data.content.review_ids {
-                    let review = <Entity<Attached<Review>>>::try_find_one_by_id(
-                        state.mongo_db.clone(),
-                        review_id,
-                    )
-                    .await
-                    .map_err(Error::from)?;
-                    if let Some(Entity {
-                        data: Attached {
-                            content: Review {
-                                judgement: false, ..
-                            },
-                            ..
-                        },
-                        ..
-                                }) = review
-                    {
-                        judgement = false;
-                        break;
-                    }
-                }
-                if judgement {
-                    <Entity<Owned<Thesis>>>::set_visibility(
-                        state.mongo_db.clone(),
-                        version.data.content.thesis_id,
-                        true,
-                    )
-                    .await
-                    .map_err(Error::from)?;
-                }
+            .map_err(Error::from)?;
+        reviews.extend(found.map(|found| found.data.content));
+    }
+    let approve = review.judgement && reviews.iter().all(|review| review.judgement);
+    let mut update = Update::default();
+    if let Some(form) = &version.data.content.review_form {
+        let summary = ReviewSummary::of(form, reviews.iter().chain([&review]));
+        update.set.insert(
+            field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(review_summary in Version)),
+            to_bson(&summary).map_err(MongoError::from)?,
+        );
+    }
+    let review_id = <Entity<Attached<Review>>>::insert_one(
+        state.mongo_db.clone(),
+        Attached {
+            creator_id: Some(auth_info.id),
+            content: review,
+        },
+    )
+    .await
+    .map_err(Error::from)?
+    .ok_or(Error::NotFound("cannot get reviewer id".to_string()))?;
+    update.add_to_set = doc! {field!((data in Entity<Attached<Version>>).(content in Attached<Version>).(review_ids in Version)): review_id};
+    let action = workflow::Action::Review {
+        reviewer_id: auth_info.id,
+        approve,
+    };
+    let version =
+        match Version::transit(state.mongo_db.clone(), &version, action, Some(auth_info.id), update)
+            .await
+        {
+            Ok(version) => version,
+            Err(e) => {
+                <Entity<Attached<Review>>>::delete_by_id(state.mongo_db, review_id).await?;
+                return Err(e.into());
+            }
+        };
+    let count = match &version.data.content.state {
+        VersionState::Reviewing(reviewing) => reviewing.remainder_ids.len(),
+        VersionState::Passed(passed) => {
+            if *passed {
+                <Entity<Owned<Thesis>>>::set_visibility(
+                    state.mongo_db,
+                    version.data.content.thesis_id,
+                    true,
+                )
+                .await
+                .map_err(Error::from)?;
             }
-            Ok(Json(ReviewRes {id: review_id, count: remainder_count}))
+            0
         }
-        _ => Err(Error::Forbidden(
-            "no permission to reviewing it".to_string(),
-        )),
-    }
+        _ => 0,
+    };
+    Ok(Json(ReviewRes { id: review_id, count }))
 }
 
 fn tag(op: aide::transform::TransformPathItem) -> aide::transform::TransformPathItem {
@@ -305,6 +822,16 @@ pub(super) fn route() -> ApiRouter<AppState> {
     ApiRouter::new().nest(
         &format!("/{}", Version::plural()),
         ApiRouter::new()
+            .api_route_with(
+                "/",
+                routing::get_with(handlers::list_objects::<ShowAuth>, |op| {
+                    op.summary("list versions of a thesis")
+                        .description("published ones and, once a thesis is published, its earlier rounds, plus private ones you can see with a cookie")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response::<ListRes>()
+                }),
+                tag,
+            )
             .api_route_with(
                 "/:id",
                 routing::get_with(handlers::show_object::<ShowAuth>, |op| {
@@ -317,7 +844,8 @@ pub(super) fn route() -> ApiRouter<AppState> {
             .api_route_with(
                 "/:id/release",
                 routing::get_with(release, |op| {
-                    op.summary("show version information")
+                    op.summary("download the release")
+                        .description("supports `Range`, `If-None-Match` and `If-Modified-Since`; served as uploaded, with its metadata, such as the PDF author, left in even under double-blind review")
                         .security_requirement(docs::SECURITY_SCHEME_NAME)
                         .default_response_with::<Bytes, _>(docs::require_cookie::<Bytes>)
                 }),
@@ -325,8 +853,9 @@ pub(super) fn route() -> ApiRouter<AppState> {
             )
             .api_route_with(
                 "/:id/source/:index",
-                routing::get_with(release, |op| {
-                    op.summary("show version information")
+                routing::get_with(source, |op| {
+                    op.summary("download a source file")
+                        .description("supports `Range`, `If-None-Match` and `If-Modified-Since`")
                         .security_requirement(docs::SECURITY_SCHEME_NAME)
                         .default_response_with::<Bytes, _>(docs::require_cookie::<Bytes>)
                 }),
@@ -339,6 +868,54 @@ pub(super) fn route() -> ApiRouter<AppState> {
                     )
                 },
             )
+            .api_route_with(
+                "/:id/sources",
+                routing::get_with(sources, |op| {
+                    op.summary("list the source files")
+                        .description("with the files inside those which are archives")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Json<Vec<StoredFile>>, _>(
+                            docs::require_cookie::<Json<Vec<StoredFile>>>,
+                        )
+                }),
+                |op| add_parameter_id(tag(op)),
+            )
+            .api_route_with(
+                "/:id/source/:index/entry",
+                routing::get_with(source_entry, |op| {
+                    op.summary("download a file inside a source archive")
+                        .description("supports `If-None-Match` and `If-Modified-Since`")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Bytes, _>(docs::require_cookie::<Bytes>)
+                }),
+                |op| {
+                    docs::add_one_parameter(
+                        add_parameter_id(tag(op)),
+                        "index".to_string(),
+                        Some("the n-th source file".to_string()),
+                        Some(Value::Number(0.into())),
+                    )
+                },
+            )
+            .api_route_with(
+                "/:id/diff/:other",
+                routing::get_with(compare, |op| {
+                    op.summary("compare two versions of a thesis")
+                        .description("the sources, matched by file name, and the text of the releases")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Json<VersionDiff>, _>(
+                            docs::require_cookie::<Json<VersionDiff>>,
+                        )
+                }),
+                |op| {
+                    docs::add_one_parameter(
+                        add_parameter_id(tag(op)),
+                        "other".to_string(),
+                        Some("the version to compare with".to_string()),
+                        Some(Value::String(ObjectId::new().to_hex())),
+                    )
+                },
+            )
             .api_route_with(
                 "/:id/edit",
                 routing::patch_with(edit, |op| {
@@ -357,6 +934,25 @@ pub(super) fn route() -> ApiRouter<AppState> {
                 }),
                 |op| add_parameter_id(tag(op)),
             )
+            .api_route_with(
+                "/:id/request-revision",
+                routing::patch_with(request_revision, |op| {
+                    op.summary("close the review round asking for a revision")
+                        .description("the next version committed starts a new round")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Res, _>(docs::require_cookie::<Res>)
+                }),
+                |op| add_parameter_id(tag(op)),
+            )
+            .api_route_with(
+                "/:id/withdraw",
+                routing::patch_with(withdraw, |op| {
+                    op.summary("withdraw a version before it is decided")
+                        .security_requirement(docs::SECURITY_SCHEME_NAME)
+                        .default_response_with::<Res, _>(docs::require_cookie::<Res>)
+                }),
+                |op| add_parameter_id(tag(op)),
+            )
             .api_route_with(
                 "/:id/review",
                 routing::patch_with(review, |op| {
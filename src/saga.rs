@@ -0,0 +1,159 @@
+//! Operations spanning PostgreSQL and MongoDB.
+//!
+//! Neither database can roll back the other. So such an operation does its PostgreSQL part in a
+//! transaction committed last, and keeps in a [`Saga`] how to undo each step done in MongoDB
+//! before. What is left over when even that fails, e.g. because the server went down halfway,
+//! is cleaned up by [`repair_orphans`].
+
+use std::{fmt::Display, future::Future, time::Duration};
+
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use mongo::{
+    bson,
+    entity::{doc, field, operator::*, Entity},
+    MongoDatabase, MongoError,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter};
+use thiserror::Error;
+
+use crate::{
+    mongo_entities::profile::Profile,
+    periodic,
+    sql_entities::{account, prelude::Account},
+};
+
+/// Compensating actions of the steps done so far, run backwards if the operation fails.
+pub(crate) struct Saga<E> {
+    name: &'static str,
+    compensations: Vec<BoxFuture<'static, Result<(), E>>>,
+}
+
+impl<E: Display> Saga<E> {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            compensations: Vec::new(),
+        }
+    }
+
+    /// Remembers how to undo the step just done.
+    pub(crate) fn compensate(
+        &mut self,
+        undo: impl Future<Output = Result<(), E>> + Send + 'static,
+    ) {
+        self.compensations.push(Box::pin(undo));
+    }
+
+    /// Passes `result` on, after undoing every step if it is an error.
+    pub(crate) async fn finish<T>(self, result: Result<T, E>) -> Result<T, E> {
+        if result.is_err() {
+            for undo in self.compensations.into_iter().rev() {
+                if let Err(e) = undo.await {
+                    tracing::error!("failed to undo a step of {}: {}", self.name, e);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum RepairError {
+    #[error("PostgreSQL error: {0}")]
+    Sql(#[from] DbErr),
+    #[error("MongoDB error: {0}")]
+    Mongo(#[from] MongoError),
+}
+
+/// How many accounts and profiles [`repair_orphans`] removed, and how many profiles it left.
+#[derive(Default)]
+#[derive(Debug)]
+pub(crate) struct Repaired {
+    pub(crate) accounts: u64,
+    pub(crate) profiles: u64,
+    pub(crate) kept_profiles: u64,
+}
+
+/// Removes accounts without a profile, which are of no use to anybody, and finds profiles
+/// without an account. Those profiles are removed only if `remove_profiles`, and then by
+/// themselves, leaving what they own, since the account may only be missing for a while, as
+/// when a database is restored. Only those older than `grace` are touched, so that signups going
+/// on are left alone.
+pub(crate) async fn repair_orphans(
+    sql_db: &DatabaseConnection,
+    mongo_db: &MongoDatabase,
+    grace: Duration,
+    remove_profiles: bool,
+) -> Result<Repaired, RepairError> {
+    let mut repaired = Repaired::default();
+    let before = Utc::now() - chrono::Duration::from_std(grace).unwrap_or(chrono::Duration::zero());
+    let mut accounts = Account::find()
+        .filter(account::Column::CreatedAt.lt(before.naive_utc()))
+        .paginate(sql_db, 100);
+    let mut orphan_emails = Vec::new();
+    while let Some(page) = accounts.fetch_and_next().await? {
+        for account in page {
+            let has_profile = match account.email.parse() {
+                Ok(email) => Profile::get(mongo_db.clone(), &email).await?.is_some(),
+                Err(_) => false,
+            };
+            if !has_profile {
+                orphan_emails.push(account.email);
+            }
+        }
+    }
+    if !orphan_emails.is_empty() {
+        repaired.accounts = Account::delete_many()
+            .filter(account::Column::Email.is_in(orphan_emails))
+            .exec(sql_db)
+            .await?
+            .rows_affected;
+    }
+    let mut profiles = <Entity<Profile>>::find(
+        mongo_db.clone(),
+        doc! {field!(created_at in Entity<Profile>): {LesserThan: bson::DateTime::from_chrono(before)}},
+    )
+    .await?;
+    while profiles.advance().await? {
+        let profile = profiles.deserialize_current()?;
+        if Account::find_by_id(profile.data.email.to_string())
+            .one(sql_db)
+            .await?
+            .is_none()
+        {
+            if remove_profiles {
+                repaired.profiles +=
+                    <Entity<Profile>>::delete_by_id(mongo_db.clone(), profile._id).await?;
+            } else {
+                repaired.kept_profiles += 1;
+            }
+        }
+    }
+    Ok(repaired)
+}
+
+/// Runs [`repair_orphans`] now and then every `period` for ever.
+pub(crate) async fn keep_repairing(
+    sql_db: DatabaseConnection,
+    mongo_db: MongoDatabase,
+    period: Duration,
+    grace: Duration,
+    remove_profiles: bool,
+) {
+    periodic::every(period, || async {
+        match repair_orphans(&sql_db, &mongo_db, grace, remove_profiles).await {
+            Ok(Repaired {
+                accounts: 0,
+                profiles: 0,
+                kept_profiles: 0,
+            }) => {}
+            Ok(repaired) => tracing::info!(
+                "removed {} accounts without profile and {} profiles without account, and kept {} of those",
+                repaired.accounts, repaired.profiles, repaired.kept_profiles
+            ),
+            Err(e) => tracing::error!("failed to repair orphaned accounts and profiles: {}", e),
+        }
+    })
+    .await
+}
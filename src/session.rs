@@ -0,0 +1,451 @@
+//! Session storage behind the `sid` cookie.
+//!
+//! Which store is used is decided by [`AppConfig::session_store`]: MongoDB and PostgreSQL keep
+//! sessions across restarts and replicas, while the in-memory store is only meant for tests.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use axum_sessions::{
+    async_session::{Result, Session, SessionStore},
+    SameSite, SessionLayer,
+};
+use chrono::{DateTime, Utc};
+use mongo::{
+    bson::{self, doc, Document},
+    oid::ObjectId,
+    MongoCollection, MongoDatabase, MongoReplaceOptions,
+};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    cfg::{AppConfig, SameSitePolicy, SessionStoreKind},
+    periodic,
+    sql_entities::{prelude, session},
+};
+
+/// Where the logged-in user is kept in session data.
+pub(crate) const AUTH_INFO_KEY: &str = "auth_info";
+
+const COOKIE_NAME: &str = "sid";
+
+const COLLECTION_NAME: &str = "sessions";
+
+impl From<SameSitePolicy> for SameSite {
+    fn from(policy: SameSitePolicy) -> Self {
+        match policy {
+            SameSitePolicy::Strict => SameSite::Strict,
+            SameSitePolicy::Lax => SameSite::Lax,
+            SameSitePolicy::None => SameSite::None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Owner {
+    id: ObjectId,
+}
+
+/// The profile id of the user logged in with `session`, stored beside the session so that all
+/// sessions of one user can be found.
+fn owner_id(session: &Session) -> Option<ObjectId> {
+    session.get::<Owner>(AUTH_INFO_KEY).map(|owner| owner.id)
+}
+
+fn bson_expiry(session: &Session) -> Option<bson::DateTime> {
+    session
+        .expiry()
+        .map(|expiry| bson::DateTime::from_millis(expiry.timestamp_millis()))
+}
+
+/// Sessions in a map, lost on restart.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MemoryStore {
+    sessions: Arc<RwLock<HashMap<String, Session>>>,
+}
+
+impl MemoryStore {
+    async fn cleanup(&self) -> Result {
+        self.sessions
+            .write()
+            .await
+            .retain(|_, session| !session.is_expired());
+        Ok(())
+    }
+
+    async fn destroy_owned_by(&self, id: ObjectId) -> Result {
+        self.sessions
+            .write()
+            .await
+            .retain(|_, session| owner_id(session) != Some(id));
+        Ok(())
+    }
+
+    async fn rewrite_owned_by(&self, id: ObjectId, key: &str, value: String) -> Result {
+        for session in self.sessions.write().await.values_mut() {
+            if owner_id(session) == Some(id) {
+                session.insert_raw(key, value.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load_session(&self, cookie_value: String) -> Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        Ok(self
+            .sessions
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .and_then(Session::validate))
+    }
+
+    async fn store_session(&self, session: Session) -> Result<Option<String>> {
+        self.sessions
+            .write()
+            .await
+            .insert(session.id().to_string(), session.clone());
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> Result {
+        self.sessions.write().await.remove(session.id());
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> Result {
+        self.sessions.write().await.clear();
+        Ok(())
+    }
+}
+
+/// Sessions as documents of the `sessions` collection, keyed by session id.
+#[derive(Clone, Debug)]
+pub(crate) struct MongoStore {
+    db: MongoDatabase,
+}
+
+impl MongoStore {
+    fn collection(&self) -> MongoCollection<Document> {
+        self.db.collection(COLLECTION_NAME)
+    }
+
+    async fn cleanup(&self) -> Result {
+        self.collection()
+            .delete_many(doc! {"expires_at": {"$lt": bson::DateTime::now()}}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn destroy_owned_by(&self, id: ObjectId) -> Result {
+        self.collection()
+            .delete_many(doc! {"owner_id": id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn rewrite_owned_by(&self, id: ObjectId, key: &str, value: String) -> Result {
+        self.collection()
+            .update_many(
+                doc! {"owner_id": id},
+                doc! {"$set": {format!("session.data.{}", key): value}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for MongoStore {
+    async fn load_session(&self, cookie_value: String) -> Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let found = self.collection().find_one(doc! {"_id": id}, None).await?;
+        Ok(match found {
+            Some(found) => {
+                bson::from_document::<Session>(found.get_document("session")?.clone())?.validate()
+            }
+            None => None,
+        })
+    }
+
+    async fn store_session(&self, session: Session) -> Result<Option<String>> {
+        self.collection()
+            .replace_one(
+                doc! {"_id": session.id()},
+                doc! {
+                    "_id": session.id(),
+                    "session": bson::to_document(&session)?,
+                    "expires_at": bson_expiry(&session),
+                    "owner_id": owner_id(&session),
+                },
+                MongoReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> Result {
+        self.collection()
+            .delete_one(doc! {"_id": session.id()}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> Result {
+        self.collection().delete_many(doc! {}, None).await?;
+        Ok(())
+    }
+}
+
+/// Sessions as rows of the `session` table.
+#[derive(Clone, Debug)]
+pub(crate) struct SqlStore {
+    db: DatabaseConnection,
+}
+
+impl SqlStore {
+    async fn cleanup(&self) -> Result {
+        prelude::Session::delete_many()
+            .filter(session::Column::ExpiresAt.lt(Utc::now().naive_utc()))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn destroy_owned_by(&self, id: ObjectId) -> Result {
+        prelude::Session::delete_many()
+            .filter(session::Column::OwnerId.eq(id.to_hex()))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn rewrite_owned_by(&self, id: ObjectId, key: &str, value: String) -> Result {
+        prelude::Session::update_many()
+            .col_expr(
+                session::Column::Data,
+                Expr::cust_with_values(
+                    r#"jsonb_set("data", ARRAY['data', $1::text], to_jsonb($2::text))"#,
+                    [key.to_string(), value],
+                ),
+            )
+            .filter(session::Column::OwnerId.eq(id.to_hex()))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqlStore {
+    async fn load_session(&self, cookie_value: String) -> Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        Ok(
+            match prelude::Session::find_by_id(id).one(&self.db).await? {
+                Some(found) => serde_json::from_value::<Session>(found.data)?.validate(),
+                None => None,
+            },
+        )
+    }
+
+    async fn store_session(&self, session: Session) -> Result<Option<String>> {
+        prelude::Session::insert(session::ActiveModel {
+            id: ActiveValue::Set(session.id().to_string()),
+            data: ActiveValue::Set(serde_json::to_value(&session)?),
+            expires_at: ActiveValue::Set(session.expiry().map(DateTime::naive_utc)),
+            owner_id: ActiveValue::Set(owner_id(&session).map(|id| id.to_hex())),
+        })
+        .on_conflict(
+            OnConflict::column(session::Column::Id)
+                .update_columns([
+                    session::Column::Data,
+                    session::Column::ExpiresAt,
+                    session::Column::OwnerId,
+                ])
+                .to_owned(),
+        )
+        .exec(&self.db)
+        .await?;
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> Result {
+        prelude::Session::delete_by_id(session.id().to_string())
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> Result {
+        prelude::Session::delete_many().exec(&self.db).await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Store {
+    Memory(MemoryStore),
+    Mongo(MongoStore),
+    Sql(SqlStore),
+}
+
+impl Store {
+    pub(crate) fn new(
+        kind: SessionStoreKind,
+        mongo_db: &MongoDatabase,
+        sql_db: &DatabaseConnection,
+    ) -> Self {
+        match kind {
+            SessionStoreKind::Memory => Self::Memory(MemoryStore::default()),
+            SessionStoreKind::Mongo => Self::Mongo(MongoStore {
+                db: mongo_db.clone(),
+            }),
+            SessionStoreKind::Sql => Self::Sql(SqlStore { db: sql_db.clone() }),
+        }
+    }
+
+    /// Removes all expired sessions.
+    pub(crate) async fn cleanup(&self) -> Result {
+        match self {
+            Self::Memory(store) => store.cleanup().await,
+            Self::Mongo(store) => store.cleanup().await,
+            Self::Sql(store) => store.cleanup().await,
+        }
+    }
+
+    /// Logs the user with profile `id` out everywhere.
+    pub(crate) async fn destroy_owned_by(&self, id: ObjectId) -> Result {
+        match self {
+            Self::Memory(store) => store.destroy_owned_by(id).await,
+            Self::Mongo(store) => store.destroy_owned_by(id).await,
+            Self::Sql(store) => store.destroy_owned_by(id).await,
+        }
+    }
+
+    /// Sets `key` to `value` in every session of the user with profile `id`, so that a change
+    /// made by someone else shows up without logging in again.
+    ///
+    /// Each store does it in place, leaving the rest of the sessions alone, and only to those
+    /// still logged in as that user.
+    pub(crate) async fn rewrite_owned_by(
+        &self,
+        id: ObjectId,
+        key: &str,
+        value: &impl Serialize,
+    ) -> Result {
+        let value = serde_json::to_string(value)?;
+        match self {
+            Self::Memory(store) => store.rewrite_owned_by(id, key, value).await,
+            Self::Mongo(store) => store.rewrite_owned_by(id, key, value).await,
+            Self::Sql(store) => store.rewrite_owned_by(id, key, value).await,
+        }
+    }
+
+    /// Runs [`Self::cleanup`] every `period` for ever.
+    pub(crate) async fn collect_garbage(self, period: Duration) {
+        periodic::every(period, || async {
+            if let Err(e) = self.cleanup().await {
+                tracing::error!("failed to clean up expired sessions: {}", e);
+            }
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl SessionStore for Store {
+    async fn load_session(&self, cookie_value: String) -> Result<Option<Session>> {
+        match self {
+            Self::Memory(store) => store.load_session(cookie_value).await,
+            Self::Mongo(store) => store.load_session(cookie_value).await,
+            Self::Sql(store) => store.load_session(cookie_value).await,
+        }
+    }
+
+    async fn store_session(&self, session: Session) -> Result<Option<String>> {
+        match self {
+            Self::Memory(store) => store.store_session(session).await,
+            Self::Mongo(store) => store.store_session(session).await,
+            Self::Sql(store) => store.store_session(session).await,
+        }
+    }
+
+    async fn destroy_session(&self, session: Session) -> Result {
+        match self {
+            Self::Memory(store) => store.destroy_session(session).await,
+            Self::Mongo(store) => store.destroy_session(session).await,
+            Self::Sql(store) => store.destroy_session(session).await,
+        }
+    }
+
+    async fn clear_store(&self) -> Result {
+        match self {
+            Self::Memory(store) => store.clear_store().await,
+            Self::Mongo(store) => store.clear_store().await,
+            Self::Sql(store) => store.clear_store().await,
+        }
+    }
+}
+
+pub(crate) fn layer(config: &AppConfig, store: Store) -> SessionLayer<Store> {
+    SessionLayer::new(store, config.session_secret.as_bytes())
+        .with_cookie_name(COOKIE_NAME)
+        .with_session_ttl(Some(Duration::from_secs(config.session_ttl)))
+        .with_secure(config.session_secure)
+        .with_same_site_policy(config.session_same_site.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn log_in(store: &MemoryStore, id: ObjectId) -> String {
+        let mut session = Session::new();
+        session
+            .insert(
+                AUTH_INFO_KEY,
+                serde_json::json!({ "id": id, "name": "old" }),
+            )
+            .unwrap();
+        store.store_session(session).await.unwrap().unwrap()
+    }
+
+    async fn name(store: &MemoryStore, cookie_value: String) -> String {
+        let session = store.load_session(cookie_value).await.unwrap().unwrap();
+        session.get::<serde_json::Value>(AUTH_INFO_KEY).unwrap()["name"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn only_sessions_of_the_owner_are_rewritten() {
+        let store = MemoryStore::default();
+        let (me, other) = (ObjectId::new(), ObjectId::new());
+        let mine = [log_in(&store, me).await, log_in(&store, me).await];
+        let theirs = log_in(&store, other).await;
+        let value = serde_json::json!({ "id": me, "name": "new" }).to_string();
+        store
+            .rewrite_owned_by(me, AUTH_INFO_KEY, value)
+            .await
+            .unwrap();
+        for cookie_value in mine {
+            assert_eq!(name(&store, cookie_value).await, "new");
+        }
+        assert_eq!(name(&store, theirs).await, "old");
+    }
+}
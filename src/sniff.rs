@@ -0,0 +1,225 @@
+//! Telling what a file is by its first bytes, whatever its name or the client says.
+
+use std::{collections::BTreeSet, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// How many bytes [`detect`] wants to see, if the file is that long.
+pub(crate) const PEEK_LEN: usize = 1024;
+
+/// How many bytes may come before the header of a PDF.
+const PDF_SLACK: usize = 16;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub(crate) enum FileType {
+    Pdf,
+    Zip,
+    Gzip,
+    Tar,
+    Bzip2,
+    Xz,
+    #[serde(rename = "7z")]
+    SevenZip,
+    Png,
+    Jpeg,
+    /// UTF-8 without NUL bytes, such as TeX sources.
+    Text,
+}
+
+const ALL: [FileType; 10] = [
+    FileType::Pdf,
+    FileType::Zip,
+    FileType::Gzip,
+    FileType::Tar,
+    FileType::Bzip2,
+    FileType::Xz,
+    FileType::SevenZip,
+    FileType::Png,
+    FileType::Jpeg,
+    FileType::Text,
+];
+
+impl FileType {
+    fn as_str(self) -> &'static str {
+        match self {
+            FileType::Pdf => "pdf",
+            FileType::Zip => "zip",
+            FileType::Gzip => "gzip",
+            FileType::Tar => "tar",
+            FileType::Bzip2 => "bzip2",
+            FileType::Xz => "xz",
+            FileType::SevenZip => "7z",
+            FileType::Png => "png",
+            FileType::Jpeg => "jpeg",
+            FileType::Text => "text",
+        }
+    }
+
+    pub(crate) fn mime(self) -> &'static str {
+        match self {
+            FileType::Pdf => "application/pdf",
+            FileType::Zip => "application/zip",
+            FileType::Gzip => "application/gzip",
+            FileType::Tar => "application/x-tar",
+            FileType::Bzip2 => "application/x-bzip2",
+            FileType::Xz => "application/x-xz",
+            FileType::SevenZip => "application/x-7z-compressed",
+            FileType::Png => "image/png",
+            FileType::Jpeg => "image/jpeg",
+            FileType::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+impl Display for FileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FileType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL.into_iter()
+            .find(|file_type| file_type.as_str() == s)
+            .ok_or(format!("unknown file type {}", s))
+    }
+}
+
+/// Parses a comma-separated list such as `pdf,zip`.
+pub(crate) fn parse_list(list: &str) -> Result<BTreeSet<FileType>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|file_type| !file_type.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+pub(crate) fn join_list(file_types: &BTreeSet<FileType>) -> String {
+    file_types
+        .iter()
+        .map(|file_type| file_type.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The type of a file starting with `head`, which holds its first [`PEEK_LEN`] bytes, or all of
+/// them if it is shorter.
+pub(crate) fn detect(head: &[u8]) -> Option<FileType> {
+    const MAGIC: [(&[u8], FileType); 9] = [
+        (b"%PDF-", FileType::Pdf),
+        (b"PK\x03\x04", FileType::Zip),
+        (b"PK\x05\x06", FileType::Zip),
+        (b"\x1f\x8b", FileType::Gzip),
+        (b"BZh", FileType::Bzip2),
+        (b"\xfd7zXZ\x00", FileType::Xz),
+        (b"7z\xbc\xaf\x27\x1c", FileType::SevenZip),
+        (b"\x89PNG\r\n\x1a\n", FileType::Png),
+        (b"\xff\xd8\xff", FileType::Jpeg),
+    ];
+    if let Some(&(_, file_type)) = MAGIC.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(file_type);
+    }
+    if head.get(257..262) == Some(b"ustar") {
+        return Some(FileType::Tar);
+    }
+    // The header of a PDF may come after a little junk, which readers put up with.
+    if head
+        .get(..PDF_SLACK + 5)
+        .unwrap_or(head)
+        .windows(5)
+        .any(|window| window == b"%PDF-")
+    {
+        return Some(FileType::Pdf);
+    }
+    let is_text = !head.contains(&0)
+        && match std::str::from_utf8(head) {
+            Ok(_) => true,
+            // A character cut off at the end of `head` is fine.
+            Err(e) => e.error_len().is_none(),
+        };
+    is_text.then_some(FileType::Text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_header() -> Vec<u8> {
+        let mut header = vec![0; 512];
+        header[..8].copy_from_slice(b"main.tex");
+        header[257..263].copy_from_slice(b"ustar\0");
+        header
+    }
+
+    #[test]
+    fn every_format_is_told_by_its_magic() {
+        let cases: [(&[u8], FileType); 11] = [
+            (b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n", FileType::Pdf),
+            (b"PK\x03\x04\x14\x00\x00\x00", FileType::Zip),
+            (b"PK\x05\x06\x00\x00\x00\x00", FileType::Zip),
+            (b"\x1f\x8b\x08\x00\x00\x00", FileType::Gzip),
+            (b"BZh91AY&SY", FileType::Bzip2),
+            (b"\xfd7zXZ\x00\x00\x04", FileType::Xz),
+            (b"7z\xbc\xaf\x27\x1c\x00\x04", FileType::SevenZip),
+            (b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR", FileType::Png),
+            (b"\xff\xd8\xff\xe0\x00\x10JFIF", FileType::Jpeg),
+            (&tar_header(), FileType::Tar),
+            (
+                "\\documentclass{article} % 论文\n".as_bytes(),
+                FileType::Text,
+            ),
+        ];
+        for (head, file_type) in cases {
+            assert_eq!(detect(head), Some(file_type), "{:?}", head);
+        }
+    }
+
+    #[test]
+    fn magic_counts_only_at_the_start() {
+        let mut zip_later = vec![0; 8];
+        zip_later.extend(b"PK\x03\x04");
+        assert_eq!(detect(&zip_later), None);
+        assert_eq!(detect(b"see \x89PNG\r\n\x1a\n"), None);
+    }
+
+    #[test]
+    fn pdf_header_may_come_after_a_little_junk() {
+        assert_eq!(detect(b"\xef\xbb\xbf  \r\n%PDF-1.4"), Some(FileType::Pdf));
+        let mut junk = vec![0; PDF_SLACK + 1];
+        junk.extend(b"%PDF-1.4");
+        assert_eq!(detect(&junk), None);
+        let mut text = "text ".repeat(20).into_bytes();
+        text.extend(b"%PDF-1.4");
+        assert_eq!(detect(&text), Some(FileType::Text));
+    }
+
+    #[test]
+    fn truncated_magic_is_not_enough() {
+        assert_eq!(detect(b"%PDF"), Some(FileType::Text));
+        assert_eq!(detect(b"\x89PNG"), None);
+        assert_eq!(detect(b"\xfd7zX"), None);
+        assert_eq!(detect(&tar_header()[..260]), None);
+    }
+
+    #[test]
+    fn text_is_utf8_without_nul() {
+        assert_eq!(detect(b""), Some(FileType::Text));
+        assert_eq!(detect(b"plain\0text"), None);
+        assert_eq!(detect(b"bad \xc3\x28 utf-8"), None);
+        // A character cut off at the end of the peeked bytes.
+        assert_eq!(detect(&"论".as_bytes()[..2]), Some(FileType::Text));
+    }
+
+    #[test]
+    fn lists_are_parsed_and_joined() {
+        let list = parse_list(" pdf, 7z,,zip ").unwrap();
+        assert_eq!(join_list(&list), "pdf, zip, 7z");
+        assert!(parse_list("pdf,docx").is_err());
+    }
+}
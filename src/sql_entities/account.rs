@@ -10,13 +10,30 @@ pub struct Model {
     pub salt: Uuid,
     #[sea_orm(column_type = "Binary(BlobSize::Blob(None))")]
     pub password_hash: Vec<u8>,
-    pub is_administrator: bool,
-    pub is_editor: bool,
     pub created_at: DateTime,
     pub updated_at: DateTime,
+    pub verified_at: Option<DateTime>,
+    pub locked_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::account_grant::Entity")]
+    AccountGrant,
+    #[sea_orm(has_many = "super::api_token::Entity")]
+    ApiToken,
+}
+
+impl Related<super::account_grant::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AccountGrant.def()
+    }
+}
+
+impl Related<super::api_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiToken.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
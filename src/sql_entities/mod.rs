@@ -3,3 +3,6 @@
 pub mod prelude;
 
 pub mod account;
+pub mod account_grant;
+pub mod api_token;
+pub mod session;
@@ -1,3 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
 pub use super::account::Entity as Account;
+pub use super::account_grant::Entity as AccountGrant;
+pub use super::api_token::Entity as ApiToken;
+pub use super::session::Entity as Session;
@@ -1,13 +1,28 @@
+use std::{collections::BTreeSet, sync::Arc};
+
 use lettre::{AsyncSmtpTransport, Tokio1Executor};
 use lettre::message::Mailbox;
-use mongo::MongoDatabase;
+use mongo::{MongoClient, MongoDatabase};
 use sea_orm::DatabaseConnection;
 
+use crate::{session, sniff::FileType};
+
 #[derive(Clone, Debug)]
 pub(crate) struct AppState {
     pub(crate) sql_db: DatabaseConnection,
+    pub(crate) mongo_client: MongoClient,
     pub(crate) mongo_db: MongoDatabase,
     pub(crate) hash_cost: u8,
     pub(crate) sender: Mailbox,
     pub(crate) smtp: AsyncSmtpTransport<Tokio1Executor>,
+    pub(crate) secret: Arc<[u8]>,
+    pub(crate) verification_ttl: u64,
+    pub(crate) reset_ttl: u64,
+    pub(crate) trash_retention: u64,
+    pub(crate) max_file_size: u64,
+    pub(crate) max_request_size: u64,
+    pub(crate) max_unpacked_size: u64,
+    pub(crate) release_types: Arc<BTreeSet<FileType>>,
+    pub(crate) source_types: Arc<BTreeSet<FileType>>,
+    pub(crate) sessions: session::Store,
 }
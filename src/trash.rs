@@ -0,0 +1,71 @@
+//! Emptying the trash.
+//!
+//! Deleted theses and paper collections are only moved to the trash, where their owners can still
+//! restore them. [`purge`] deletes them for good once they have been there long enough, with their
+//! versions, reviews and files.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use mongo::{
+    bson,
+    entity::Entity,
+    owned::{Owned, OwnedContent},
+    MongoClient, MongoDatabase, MongoResult,
+};
+
+use crate::{
+    mongo_entities::{
+        file,
+        paper_collection::{category::Category, magazine::Magazine, PaperCollection},
+        thesis::Thesis,
+    },
+    periodic,
+};
+
+async fn purge_kind<C: OwnedContent>(
+    client: &MongoClient,
+    db: &MongoDatabase,
+    before: bson::DateTime,
+) -> MongoResult<u64> {
+    let mut purged = 0;
+    let mut trashed = <Entity<Owned<C>>>::find_trashed_before(db.clone(), before).await?;
+    while trashed.advance().await? {
+        let entity = trashed.deserialize_current()?;
+        let file_ids = C::file_ids(db.clone(), &entity).await?;
+        purged += entity.delete_owneds(client, db.clone()).await?;
+        file::release(db.clone(), file_ids).await?;
+    }
+    Ok(purged)
+}
+
+/// Deletes for good what has been in the trash for longer than `retention`.
+pub(crate) async fn purge(
+    client: &MongoClient,
+    db: &MongoDatabase,
+    retention: Duration,
+) -> MongoResult<u64> {
+    let before =
+        Utc::now() - chrono::Duration::from_std(retention).unwrap_or(chrono::Duration::zero());
+    let before = bson::DateTime::from_chrono(before);
+    Ok(purge_kind::<Thesis>(client, db, before).await?
+        + purge_kind::<PaperCollection<Magazine>>(client, db, before).await?
+        + purge_kind::<PaperCollection<Category>>(client, db, before).await?)
+}
+
+/// Runs [`purge`] now and then every `period` for ever.
+pub(crate) async fn keep_purging(
+    client: MongoClient,
+    db: MongoDatabase,
+    period: Duration,
+    retention: Duration,
+) {
+    periodic::every(period, || async {
+        match purge(&client, &db, retention).await {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("purged {} objects from the trash", purged),
+            Err(e) => tracing::error!("failed to purge the trash: {}", e),
+        }
+    })
+    .await
+}